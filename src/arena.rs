@@ -0,0 +1,332 @@
+//! one big vertex and index buffer that every chunk mesh is written into
+//!
+//! space in the buffers is handed out by a free list, and the buffers grow
+//! when they run out of it. since all of the meshes live in the same
+//! buffers they can be drawn with a single multi-draw call. the chunk a
+//! mesh belongs to is picked with the base instance of its draw command,
+//! which selects the offset of that chunk from a buffer of per-instance
+//! offsets, so no uniform has to change between chunks.
+
+use std::error::Error;
+use std::ops::Range;
+
+use glium::backend::Facade;
+use glium::buffer::Buffer;
+use glium::index::{DrawCommandIndices, DrawCommandsIndicesBuffer, PrimitiveType};
+use glium::uniforms::Uniforms;
+use glium::{Api, Display, DrawParameters, IndexBuffer, Program, Surface, Version, VertexBuffer};
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::graphics::{ChunkInstance, ChunkVertex};
+
+const INITIAL_VERTICES: usize = 1 << 18;
+const INITIAL_INDICES: usize = 1 << 19;
+
+/// keeps track of which parts of a buffer are free, the ranges
+/// are sorted and never touch each other
+struct FreeList {
+    free: Vec<Range<usize>>,
+    capacity: usize,
+}
+
+impl FreeList {
+    fn new(capacity: usize) -> Self {
+        let mut list = FreeList {
+            free: vec![],
+            capacity: 0,
+        };
+        list.grow(capacity);
+        list
+    }
+
+    /// hands out the first free range that is long enough
+    fn allocate(&mut self, len: usize) -> Option<Range<usize>> {
+        let i = self.free.iter().position(|range| range.len() >= len)?;
+
+        let start = self.free[i].start;
+        self.free[i].start += len;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+
+        Some(start..start + len)
+    }
+
+    /// gives a range back, merging it with the free ranges next to it
+    fn free(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let i = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(i, range);
+
+        if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+            self.free[i].end = self.free.remove(i + 1).end;
+        }
+        if i > 0 && self.free[i - 1].end == self.free[i].start {
+            self.free[i - 1].end = self.free.remove(i).end;
+        }
+    }
+
+    fn grow(&mut self, capacity: usize) {
+        let added = self.capacity..capacity;
+        self.capacity = capacity;
+        self.free(added);
+    }
+}
+
+/// where a mesh was written to in the arena
+#[derive(Clone, Debug)]
+pub struct ArenaAllocation {
+    vertices: Range<usize>,
+    indices: Range<usize>,
+}
+
+pub struct MeshArena {
+    vertices: VertexBuffer<ChunkVertex>,
+    indices: IndexBuffer<u32>,
+    vertex_space: FreeList,
+    index_space: FreeList,
+
+    /// the offset of every chunk, indexed by the base instance
+    instances: VertexBuffer<ChunkInstance>,
+    /// if the driver can't do multi-draw-indirect every
+    /// mesh is drawn with a call of its own instead
+    multidraw: bool,
+    commands: Option<DrawCommandsIndicesBuffer>,
+    command_capacity: usize,
+}
+
+impl MeshArena {
+    pub fn new(display: &Display, chunk_offsets: &[[f32; 3]]) -> Result<Self, Box<dyn Error>> {
+        let multidraw = *display.get_context().get_opengl_version() >= Version(Api::Gl, 4, 3);
+        if !multidraw {
+            warn!("multi-draw-indirect isn't supported, every chunk gets a draw call of its own");
+        }
+
+        Ok(MeshArena {
+            vertices: VertexBuffer::empty_dynamic(display, INITIAL_VERTICES)?,
+            indices: IndexBuffer::empty_dynamic(
+                display,
+                PrimitiveType::TrianglesList,
+                INITIAL_INDICES,
+            )?,
+            vertex_space: FreeList::new(INITIAL_VERTICES),
+            index_space: FreeList::new(INITIAL_INDICES),
+            instances: Self::create_instances(display, chunk_offsets)?,
+            multidraw: multidraw,
+            commands: None,
+            command_capacity: 0,
+        })
+    }
+
+    fn create_instances(
+        display: &Display,
+        chunk_offsets: &[[f32; 3]],
+    ) -> Result<VertexBuffer<ChunkInstance>, Box<dyn Error>> {
+        let instances: Vec<ChunkInstance> = chunk_offsets
+            .iter()
+            .map(|&offset| ChunkInstance {
+                chunk_offset: offset,
+            })
+            .collect();
+
+        Ok(VertexBuffer::new(display, &instances)?)
+    }
+
+    /// sets the offset of every chunk, meshes are drawn
+    /// with the index of the chunk they belong to
+    pub fn set_chunk_offsets(
+        &mut self,
+        display: &Display,
+        chunk_offsets: &[[f32; 3]],
+    ) -> Result<(), Box<dyn Error>> {
+        self.instances = Self::create_instances(display, chunk_offsets)?;
+        Ok(())
+    }
+
+    /// makes the buffers at least as big as asked for, the old
+    /// contents are copied over on the gpu
+    fn grow(
+        &mut self,
+        display: &Display,
+        vertices: usize,
+        indices: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        if vertices > self.vertex_space.capacity {
+            let capacity = vertices.max(self.vertex_space.capacity * 2);
+            debug!("growing the vertex arena to {} vertices", capacity);
+
+            let grown = VertexBuffer::empty_dynamic(display, capacity)?;
+            let target: &Buffer<[ChunkVertex]> = &grown;
+            self.vertices
+                .copy_to(target.slice(0..self.vertex_space.capacity).unwrap())?;
+
+            self.vertices = grown;
+            self.vertex_space.grow(capacity);
+        }
+
+        if indices > self.index_space.capacity {
+            let capacity = indices.max(self.index_space.capacity * 2);
+            debug!("growing the index arena to {} indices", capacity);
+
+            let grown =
+                IndexBuffer::empty_dynamic(display, PrimitiveType::TrianglesList, capacity)?;
+            let target: &Buffer<[u32]> = &grown;
+            self.indices
+                .copy_to(target.slice(0..self.index_space.capacity).unwrap())?;
+
+            self.indices = grown;
+            self.index_space.grow(capacity);
+        }
+
+        Ok(())
+    }
+
+    /// writes a mesh into the arena, the indices start at 0 for every mesh
+    pub fn allocate(
+        &mut self,
+        display: &Display,
+        vertices: &[ChunkVertex],
+        indices: &[u32],
+    ) -> Result<ArenaAllocation, Box<dyn Error>> {
+        let vertex_range = match self.vertex_space.allocate(vertices.len()) {
+            Some(range) => range,
+            None => {
+                self.grow(display, self.vertex_space.capacity + vertices.len(), 0)?;
+                self.vertex_space.allocate(vertices.len()).unwrap()
+            }
+        };
+        let index_range = match self.index_space.allocate(indices.len()) {
+            Some(range) => range,
+            None => {
+                self.grow(display, 0, self.index_space.capacity + indices.len())?;
+                self.index_space.allocate(indices.len()).unwrap()
+            }
+        };
+
+        let allocation = ArenaAllocation {
+            vertices: vertex_range,
+            indices: index_range,
+        };
+        self.vertices
+            .slice(allocation.vertices.clone())
+            .unwrap()
+            .write(vertices);
+        self.write_indices(&allocation, indices);
+
+        Ok(allocation)
+    }
+
+    pub fn free(&mut self, allocation: ArenaAllocation) {
+        self.vertex_space.free(allocation.vertices);
+        self.index_space.free(allocation.indices);
+    }
+
+    /// forgets every allocation at once
+    pub fn clear(&mut self) {
+        self.vertex_space = FreeList::new(self.vertex_space.capacity);
+        self.index_space = FreeList::new(self.index_space.capacity);
+    }
+
+    /// overwrites the indices of a mesh, there has to be as many as before
+    pub fn write_indices(&self, allocation: &ArenaAllocation, indices: &[u32]) {
+        let buffer: &Buffer<[u32]> = &self.indices;
+        buffer
+            .slice(allocation.indices.clone())
+            .unwrap()
+            .write(indices);
+    }
+
+    /// makes room for the draw commands of that many meshes,
+    /// this has to happen before drawing them
+    pub fn reserve_draws(&mut self, display: &Display, count: usize) -> Result<(), Box<dyn Error>> {
+        if self.multidraw && count > self.command_capacity {
+            let capacity = count.next_power_of_two();
+            self.commands = Some(DrawCommandsIndicesBuffer::empty_dynamic(display, capacity)?);
+            self.command_capacity = capacity;
+        }
+
+        Ok(())
+    }
+
+    /// draws the meshes in the given order, each one along
+    /// with the index of the chunk it belongs to
+    pub fn draw<S: Surface, U: Uniforms>(
+        &self,
+        surface: &mut S,
+        meshes: &[(&ArenaAllocation, usize)],
+        shader: &Program,
+        uniforms: &U,
+        params: &DrawParameters,
+    ) -> Result<(), Box<dyn Error>> {
+        if meshes.is_empty() {
+            return Ok(());
+        }
+
+        let commands = match (self.multidraw, &self.commands) {
+            (true, Some(commands)) if meshes.len() <= self.command_capacity => commands,
+            (true, _) => return Err("not enough room was reserved for the draw commands".into()),
+            (false, _) => {
+                for &(allocation, chunk) in meshes.iter() {
+                    surface.draw(
+                        (
+                            self.vertices.slice(allocation.vertices.clone()).unwrap(),
+                            self.instances
+                                .slice(chunk..chunk + 1)
+                                .unwrap()
+                                .per_instance()
+                                .map_err(|_| "instancing isn't supported")?,
+                        ),
+                        self.indices.slice(allocation.indices.clone()).unwrap(),
+                        shader,
+                        uniforms,
+                        params,
+                    )?;
+                }
+
+                return Ok(());
+            }
+        };
+
+        // the commands that aren't used draw no instances, so nothing
+        let mut list = vec![
+            DrawCommandIndices {
+                count: 0,
+                instance_count: 0,
+                first_index: 0,
+                base_vertex: 0,
+                base_instance: 0,
+            };
+            self.command_capacity
+        ];
+        for (command, &(allocation, chunk)) in list.iter_mut().zip(meshes.iter()) {
+            *command = DrawCommandIndices {
+                count: allocation.indices.len() as u32,
+                instance_count: 1,
+                first_index: allocation.indices.start as u32,
+                base_vertex: allocation.vertices.start as u32,
+                base_instance: chunk as u32,
+            };
+        }
+        commands.write(&list);
+
+        surface.draw(
+            (
+                &self.vertices,
+                self.instances
+                    .per_instance()
+                    .map_err(|_| "instancing isn't supported")?,
+            ),
+            commands.with_index_buffer(&self.indices),
+            shader,
+            uniforms,
+            params,
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,478 @@
+//! inspects and edits saved worlds without opening a window
+//!
+//! `rmc1-tool <world dir> <command> [arguments]`, run it without
+//! arguments for the list of commands
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::error::Error;
+use std::process;
+use std::time::Instant;
+
+use rmc1::block::atlas::BlockAtlas;
+use rmc1::block::colors::BlockColors;
+use rmc1::block::definitions;
+use rmc1::block::types::{block_type_by_name, block_types, set_block_types, BLOCK_TYPES};
+use rmc1::block::Block;
+use rmc1::chunk::*;
+use rmc1::map::{self, Projection};
+use rmc1::pathtrace::{self, TraceSettings, View};
+use rmc1::resource_pack::ResourcePacks;
+use rmc1::save::patch::{self, Patch};
+use rmc1::save::snapshot;
+use rmc1::save::WorldSave;
+use rmc1::terraingen::GeneratorSettings;
+use rmc1::world::generation;
+use rmc1::world::World;
+
+const USAGE: &str = "usage: rmc1-tool <world dir> <command> [arguments]
+
+commands:
+    info                                 generator, seed, chunk count and bounds
+    stats                                how many blocks of each type there are
+    get <x> <y> <z>                      the block at the given coordinates
+    set <x> <y> <z> <block type | air>   replaces the block at the given coordinates
+    regen <chunk x> <chunk y> <chunk z>  generates a chunk's terrain again
+    prune                                removes saved chunks that are empty
+    diff <other world dir> [patch file]  compares the world with another one and
+                                         optionally writes a patch between them
+    patch <patch file>                   applies a patch written by diff
+    snapshot <name>                      takes a snapshot of the world
+    snapshots                            lists the snapshots of the world
+    restore <name>                       puts the world back the way it was in a snapshot
+    delete-snapshot <name>               deletes a snapshot
+    map <top-down|isometric> <png> [scale]
+                                         draws a map of the saved chunks
+    path-trace <png> <width> <height> [samples] [bounces]
+                                         path traces the saved chunks from the camera";
+
+fn parse_coords(args: &[String]) -> Result<[i32; 3], Box<dyn Error>> {
+    match args {
+        [x, y, z, ..] => Ok([x.parse()?, y.parse()?, z.parse()?]),
+        _ => Err("expected x, y and z coordinates".into()),
+    }
+}
+
+fn load_chunk(save: &WorldSave, coords: [i32; 3]) -> Result<Chunk, Box<dyn Error>> {
+    match save.load_chunks(&[coords]).pop().flatten() {
+        Some(chunk) => Ok(chunk),
+        None => Err(format!("chunk {:?} isn't saved", coords).into()),
+    }
+}
+
+fn info(save: &WorldSave) -> Result<(), Box<dyn Error>> {
+    let metadata = save.read_metadata()?;
+
+    match &metadata.generator {
+        GeneratorSettings::Flat { height } => {
+            println!("generator: flat, {} blocks high", height)
+        }
+        GeneratorSettings::Opensimplex { seed, .. } => {
+            println!("generator: opensimplex");
+            match seed {
+                Some(seed) => println!("seed: {}", seed),
+                None => println!("seed: none, the noise's default seed is used"),
+            }
+        }
+    }
+    println!("size: {} x {} blocks", metadata.size.0, metadata.size.1);
+
+    let chunks = save.saved_chunks()?;
+    println!(
+        "chunks: {} saved out of {}",
+        chunks.len(),
+        World::chunk_grid(metadata.size).len()
+    );
+
+    if let Some(&first) = chunks.first() {
+        let (mut min, mut max) = (first, first);
+        for chunk in chunks.iter() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(chunk[axis]);
+                max[axis] = max[axis].max(chunk[axis]);
+            }
+        }
+
+        println!("chunk bounds: {:?} to {:?}", min, max);
+        println!(
+            "block bounds: {:?} to {:?}",
+            [
+                min[0] * CHUNK_SIZE_I32,
+                min[1] * CHUNK_SIZE_I32,
+                min[2] * CHUNK_SIZE_I32,
+            ],
+            [
+                (max[0] + 1) * CHUNK_SIZE_I32 - 1,
+                (max[1] + 1) * CHUNK_SIZE_I32 - 1,
+                (max[2] + 1) * CHUNK_SIZE_I32 - 1,
+            ]
+        );
+    }
+
+    Ok(())
+}
+
+fn stats(save: &WorldSave) -> Result<(), Box<dyn Error>> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    let mut air = 0;
+    let mut chunks = 0;
+
+    save.for_each_saved_chunk(|chunk| {
+        chunks += 1;
+        for block in chunk.blocks.iter().flatten().flatten() {
+            match block {
+                Some(block) => *counts.entry(block.block_type.name).or_insert(0) += 1,
+                None => air += 1,
+            }
+        }
+    })?;
+
+    // every block type is listed, even the ones that don't occur
+    let mut counts: Vec<(&str, usize)> = BLOCK_TYPES
+        .iter()
+        .map(|t| (t.name, counts.get(t.name).copied().unwrap_or(0)))
+        .collect();
+    counts.sort_by_key(|&(_, count)| Reverse(count));
+
+    println!("{} chunks", chunks);
+    for (name, count) in counts {
+        println!("{:>12} {}", count, name);
+    }
+    println!("{:>12} air", air);
+
+    Ok(())
+}
+
+fn get(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(parse_coords(args)?);
+    let chunk = load_chunk(save, chunk_coords)?;
+
+    match chunk.get_block(block_coords) {
+        Some(block) => println!("{}", block.block_type.name),
+        None => println!("air"),
+    }
+
+    Ok(())
+}
+
+fn set(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let coords = parse_coords(args)?;
+
+    // block type names have spaces in them
+    let name = args.get(3..).unwrap_or(&[]).join(" ");
+    let block_type = match name.as_str() {
+        "" => return Err("expected a block type".into()),
+        "air" => None,
+        name => match block_type_by_name(name) {
+            Some(block_type) => Some(block_type),
+            None => return Err(format!("unknown block type \"{}\"", name).into()),
+        },
+    };
+
+    let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(coords);
+    let mut chunk = load_chunk(save, chunk_coords)?;
+
+    *chunk.get_block_mut(block_coords) = block_type.map(|t| Block::new(block_coords, t));
+    save.save_chunks(&[&chunk])?;
+
+    // the saved light doesn't fit the new block anymore, this makes the
+    // game light the chunk, its neighbours and the chunks below it again
+    save.invalidate_light(&[chunk_coords])?;
+
+    println!(
+        "set {:?} to {}",
+        coords,
+        block_type.map_or("air", |t| t.name)
+    );
+    Ok(())
+}
+
+fn regen(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let coords = parse_coords(args)?;
+    let metadata = save.read_metadata()?;
+
+    if !World::chunk_grid(metadata.size).contains(&coords) {
+        return Err(format!("chunk {:?} lies outside of the world", coords).into());
+    }
+
+    // the neighbours come along so decorations can spill over into them,
+    // and so the decorations they spilled into the chunk are placed again
+    let mut chunks = vec![Chunk::empty(coords)];
+    let neighbours = [
+        [coords[0] + 1, coords[1], coords[2]],
+        [coords[0] - 1, coords[1], coords[2]],
+        [coords[0], coords[1] + 1, coords[2]],
+        [coords[0], coords[1] - 1, coords[2]],
+        [coords[0], coords[1], coords[2] + 1],
+        [coords[0], coords[1], coords[2] - 1],
+    ];
+    chunks.extend(save.load_chunks(&neighbours).into_iter().flatten());
+
+    generation::regenerate(&mut chunks, 0, metadata.generator.build().as_ref());
+    save.save_chunks(&chunks.iter().collect::<Vec<_>>())?;
+
+    // decorations may have changed the neighbours as well
+    let changed: Vec<[i32; 3]> = chunks.iter().map(|c| c.coordinates).collect();
+    save.invalidate_light(&changed)?;
+
+    println!("regenerated chunk {:?}", coords);
+    Ok(())
+}
+
+fn prune(save: &WorldSave) -> Result<(), Box<dyn Error>> {
+    let generator = save.read_metadata()?.generator.build();
+    let mut empty = vec![];
+
+    save.for_each_saved_chunk(|chunk| {
+        if !chunk.is_empty() {
+            return;
+        }
+
+        // chunks that were dug out by hand would come back
+        // full of terrain if they were generated again
+        let mut generated = [Chunk::empty(chunk.coordinates)];
+        generation::regenerate(&mut generated, 0, generator.as_ref());
+
+        if generated[0].is_empty() {
+            empty.push(chunk.coordinates);
+        }
+    })?;
+
+    save.remove_chunks(&empty)?;
+
+    println!("removed {} empty chunks", empty.len());
+    Ok(())
+}
+
+fn diff(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let other = match args.first() {
+        Some(dir) => WorldSave::new(dir),
+        None => return Err("expected the world to compare with".into()),
+    };
+    if !other.exists() {
+        return Err(format!("there is no world saved in {}", args[0]).into());
+    }
+
+    let diff = patch::diff(save, &other)?;
+
+    for chunk in diff.chunks.iter() {
+        let counts = chunk.counts();
+        println!(
+            "chunk {:?}: {} added, {} removed, {} changed",
+            chunk.coordinates, counts.added, counts.removed, counts.changed
+        );
+    }
+
+    let counts = diff.counts();
+    println!(
+        "{} chunks differ, {} are the same",
+        diff.chunks.len(),
+        diff.unchanged
+    );
+    println!(
+        "{} blocks added, {} removed, {} changed",
+        counts.added, counts.removed, counts.changed
+    );
+
+    if let Some(path) = args.get(1) {
+        diff.to_patch().write(path)?;
+        println!("wrote patch to {}", path);
+    }
+
+    Ok(())
+}
+
+fn apply_patch(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let patch = match args.first() {
+        Some(path) => Patch::read(path)?,
+        None => return Err("expected a patch file".into()),
+    };
+
+    let replaced = patch.apply(save)?;
+
+    println!(
+        "replaced {} blocks in {} chunks",
+        replaced,
+        patch.chunks.len()
+    );
+    Ok(())
+}
+
+fn snapshot_name(args: &[String]) -> Result<&str, Box<dyn Error>> {
+    match args.first() {
+        Some(name) => Ok(name),
+        None => Err("expected the name of a snapshot".into()),
+    }
+}
+
+fn take_snapshot(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let name = snapshot_name(args)?;
+    let stats = snapshot::create(save, name)?;
+
+    println!(
+        "took snapshot \"{}\", {} files were stored and {} were already stored",
+        name, stats.stored, stats.reused
+    );
+    Ok(())
+}
+
+fn list_snapshots(save: &WorldSave) -> Result<(), Box<dyn Error>> {
+    let snapshots = snapshot::list(save)?;
+    if snapshots.is_empty() {
+        println!("there are no snapshots");
+    }
+
+    for snapshot in snapshots {
+        println!(
+            "{} ({}, {} files)",
+            snapshot.name,
+            snapshot.age(),
+            snapshot.files
+        );
+    }
+    Ok(())
+}
+
+/// every saved chunk, for the renderers
+fn load_saved_chunks(save: &WorldSave) -> Result<Vec<Chunk>, Box<dyn Error>> {
+    let mut chunks = vec![];
+    save.for_each_saved_chunk(|chunk| chunks.push(chunk))?;
+    if chunks.is_empty() {
+        return Err("the world has no saved chunks".into());
+    }
+
+    Ok(chunks)
+}
+
+/// uses the block definitions of the resource packs and loads the block
+/// textures, before the chunks are loaded so they get the packs' block types
+fn load_pack_assets() -> Result<BlockAtlas, Box<dyn Error>> {
+    let packs = ResourcePacks::load();
+    set_block_types(definitions::load(&packs)?);
+    World::load_block_atlas(&packs, block_types())
+}
+
+fn render_map(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (projection, path, scale) = match args {
+        [projection, path] => (projection, path, None),
+        [projection, path, scale] => (projection, path, Some(scale)),
+        _ => return Err("expected a projection, a png and optionally a scale".into()),
+    };
+
+    let projection = match projection.as_str() {
+        "top-down" => Projection::TopDown,
+        "isometric" => Projection::Isometric {
+            scale: match scale {
+                Some(scale) => scale.parse()?,
+                None => 1,
+            },
+        },
+        other => return Err(format!("unknown projection \"{}\"", other).into()),
+    };
+
+    let atlas = load_pack_assets()?;
+    let chunks = load_saved_chunks(save)?;
+    let image = map::render(&chunks, &BlockColors::from_block_atlas(&atlas), projection);
+    image.save_with_format(path, image::ImageFormat::Png)?;
+
+    println!(
+        "rendered a {}x{} map of {} chunks to {}",
+        image.width(),
+        image.height(),
+        chunks.len(),
+        path
+    );
+    Ok(())
+}
+
+fn path_trace(save: &WorldSave, args: &[String]) -> Result<(), Box<dyn Error>> {
+    if args.len() < 3 || args.len() > 5 {
+        return Err("wrong number of arguments".into());
+    }
+    let path = &args[0];
+
+    let mut settings = TraceSettings {
+        width: args[1].parse()?,
+        height: args[2].parse()?,
+        ..Default::default()
+    };
+    if let Some(samples) = args.get(3) {
+        settings.samples = samples.parse()?;
+    }
+    if let Some(bounces) = args.get(4) {
+        settings.bounces = bounces.parse()?;
+    }
+    if settings.width == 0 || settings.height == 0 || settings.samples == 0 {
+        return Err("the size and the sample count can't be zero".into());
+    }
+
+    let metadata = save.read_metadata()?;
+    let camera = World::create_camera(metadata.camera_position, metadata.camera_rotation);
+    let atlas = load_pack_assets()?;
+    let chunks = load_saved_chunks(save)?;
+
+    println!(
+        "path tracing a {}x{} image with {} samples per pixel",
+        settings.width, settings.height, settings.samples
+    );
+    let start = Instant::now();
+    let image = pathtrace::render(&chunks, &atlas, &View::from_camera(&camera), &settings);
+    image.save_with_format(path, image::ImageFormat::Png)?;
+
+    println!(
+        "path traced {} in {:.1} seconds",
+        path,
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (dir, command, rest) = match args {
+        [dir, command, rest @ ..] => (dir, command.as_str(), rest),
+        _ => return Err(USAGE.into()),
+    };
+
+    let save = WorldSave::new(dir);
+    if !save.exists() {
+        return Err(format!("there is no world saved in {}", dir).into());
+    }
+
+    match command {
+        "info" => info(&save),
+        "stats" => stats(&save),
+        "get" => get(&save, rest),
+        "set" => set(&save, rest),
+        "regen" => regen(&save, rest),
+        "prune" => prune(&save),
+        "diff" => diff(&save, rest),
+        "patch" => apply_patch(&save, rest),
+        "snapshot" => take_snapshot(&save, rest),
+        "snapshots" => list_snapshots(&save),
+        "restore" => {
+            let name = snapshot_name(rest)?;
+            snapshot::restore(&save, name)?;
+            println!("restored snapshot \"{}\"", name);
+            Ok(())
+        }
+        "delete-snapshot" => {
+            let name = snapshot_name(rest)?;
+            snapshot::delete(&save, name)?;
+            println!("deleted snapshot \"{}\"", name);
+            Ok(())
+        }
+        "map" => render_map(&save, rest),
+        "path-trace" => path_trace(&save, rest),
+        other => Err(format!("unknown command \"{}\"\n\n{}", other, USAGE).into()),
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,257 @@
+//! builds the block textures out of one png per texture
+//!
+//! block types name the texture of each of their faces, see types.rs
+//! and definitions.rs.
+//! every name gets a layer of the block texture array, in the order the
+//! names first show up in the block types, and is loaded from `<name>.png` in
+//! the block textures directory of the resource packs. everything that still wants a single
+//! image, like the exported meshes, gets the layers packed into an atlas
+//! of ATLAS_TILES by ATLAS_TILES tiles.
+
+use std::error::Error;
+
+use image::{GenericImage, RgbaImage};
+use log::*;
+
+use super::{BlockTextureLayers, BlockType, ATLAS_TILES};
+use crate::resource_pack::ResourcePacks;
+
+pub const BLOCK_TEXTURES_DIR: &str = "textures/blocks";
+
+// used when no resource pack has a texture
+const BUILTIN_TEXTURES: &[(&str, &[u8])] = &[
+    (
+        "grass_block_side",
+        include_bytes!("../../textures/blocks/grass_block_side.png"),
+    ),
+    (
+        "grass_block_top",
+        include_bytes!("../../textures/blocks/grass_block_top.png"),
+    ),
+    ("dirt", include_bytes!("../../textures/blocks/dirt.png")),
+    ("stone", include_bytes!("../../textures/blocks/stone.png")),
+    ("glass", include_bytes!("../../textures/blocks/glass.png")),
+];
+
+/// which layer every texture the block types use ends up in
+#[derive(Clone, Debug)]
+pub struct TextureLayers {
+    names: Vec<&'static str>,
+    // the names of the block types using each texture
+    users: Vec<Vec<&'static str>>,
+}
+
+impl TextureLayers {
+    pub fn from_block_types(block_types: &[&BlockType]) -> Self {
+        let mut names = vec![];
+        let mut users: Vec<Vec<&'static str>> = vec![];
+
+        for block_type in block_types.iter() {
+            for name in block_type.textures.faces() {
+                let layer = match names.iter().position(|&n| n == name) {
+                    Some(layer) => layer,
+                    None => {
+                        names.push(name);
+                        users.push(vec![]);
+                        names.len() - 1
+                    }
+                };
+
+                if !users[layer].contains(&block_type.name) {
+                    users[layer].push(block_type.name);
+                }
+            }
+        }
+
+        TextureLayers {
+            names: names,
+            users: users,
+        }
+    }
+
+    /// the names of the textures, ordered by their layer
+    pub fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.names
+            .iter()
+            .position(|&n| n == name)
+            .map(|layer| layer as u32)
+    }
+
+    /// the layers the faces of a block type use, the block
+    /// type has to be one of the ones the layers were made for
+    pub fn of(&self, block_type: &BlockType) -> BlockTextureLayers {
+        let layer = |name| {
+            self.layer(name)
+                .unwrap_or_else(|| panic!("{} has no texture layer", name))
+        };
+        let textures = block_type.textures;
+
+        BlockTextureLayers {
+            front: layer(textures.front),
+            right: layer(textures.right),
+            back: layer(textures.back),
+            left: layer(textures.left),
+            top: layer(textures.top),
+            bottom: layer(textures.bottom),
+        }
+    }
+}
+
+/// the image of every layer of the block textures
+pub struct BlockAtlas {
+    layers: TextureLayers,
+    tile_size: u32,
+    tiles: Vec<RgbaImage>,
+}
+
+impl BlockAtlas {
+    /// loads the texture of every layer from the resource packs, falling
+    /// back to the built in textures. fails if a block type uses a texture
+    /// that doesn't exist, or if the textures aren't all squares of one size
+    pub fn load(packs: &ResourcePacks, layers: &TextureLayers) -> Result<Self, Box<dyn Error>> {
+        let mut tiles = vec![];
+        let mut missing = vec![];
+        for &name in layers.names() {
+            let asset = format!("{}/{}.png", BLOCK_TEXTURES_DIR, name);
+            let png = match packs.read(&asset) {
+                Some(png) => png,
+                None => match BUILTIN_TEXTURES.iter().find(|(n, _)| *n == name) {
+                    Some((_, png)) => {
+                        warn!("no resource pack has {}, using the built in texture", asset);
+                        png.to_vec()
+                    }
+                    None => {
+                        missing.push(name);
+                        continue;
+                    }
+                },
+            };
+
+            let tile = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+                .map_err(|e| format!("{}: {}", asset, e))?
+                .to_rgba8();
+            tiles.push(tile);
+        }
+
+        if !missing.is_empty() {
+            return Err(Self::describe_missing(layers, &missing).into());
+        }
+
+        Self::warn_unused(packs, layers);
+        Self::from_tiles(layers, tiles)
+    }
+
+    /// names every missing texture along with the block types using it
+    fn describe_missing(layers: &TextureLayers, missing: &[&str]) -> String {
+        let mut description = String::from("no resource pack has these block textures:");
+
+        for &name in missing {
+            let users = &layers.users[layers.layer(name).unwrap() as usize];
+            description += &format!(
+                "\n    {}/{}.png, used by {}",
+                BLOCK_TEXTURES_DIR,
+                name,
+                users.join(", ")
+            );
+        }
+
+        description
+    }
+
+    /// pngs no block type uses are most likely misspelled
+    fn warn_unused(packs: &ResourcePacks, layers: &TextureLayers) {
+        for pack in packs.packs() {
+            for file in pack.files(BLOCK_TEXTURES_DIR) {
+                let name = match file.strip_suffix(".png") {
+                    Some(name) => name,
+                    None => continue,
+                };
+
+                if layers.layer(name).is_none() {
+                    warn!(
+                        "{}/{} in {} isn't used by any block type",
+                        BLOCK_TEXTURES_DIR,
+                        file,
+                        pack.name()
+                    );
+                }
+            }
+        }
+    }
+
+    fn from_tiles(layers: &TextureLayers, tiles: Vec<RgbaImage>) -> Result<Self, Box<dyn Error>> {
+        if tiles.len() > (ATLAS_TILES * ATLAS_TILES) as usize {
+            return Err(format!(
+                "there are {} block textures, the atlas only fits {}",
+                tiles.len(),
+                ATLAS_TILES * ATLAS_TILES
+            )
+            .into());
+        }
+
+        let tile_size = tiles.first().map_or(1, |tile| tile.width());
+        for (name, tile) in layers.names().iter().zip(tiles.iter()) {
+            if tile.dimensions() != (tile_size, tile_size) {
+                return Err(format!(
+                    "{}.png is {}x{}, every block texture has to be {}x{}",
+                    name,
+                    tile.width(),
+                    tile.height(),
+                    tile_size,
+                    tile_size
+                )
+                .into());
+            }
+        }
+
+        debug!(
+            "loaded {} {}x{} block textures",
+            tiles.len(),
+            tile_size,
+            tile_size
+        );
+
+        Ok(BlockAtlas {
+            layers: layers.clone(),
+            tile_size: tile_size,
+            tiles: tiles,
+        })
+    }
+
+    pub fn layers(&self) -> &TextureLayers {
+        &self.layers
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// the images ordered by their layer
+    pub fn tiles(&self) -> &[RgbaImage] {
+        &self.tiles
+    }
+
+    /// packs the layers into a single image, layer `n` is the `n`th tile
+    /// counting row by row from the top left, see block::atlas_uv
+    pub fn to_image(&self) -> RgbaImage {
+        let size = ATLAS_TILES * self.tile_size;
+        let mut atlas = RgbaImage::new(size, size);
+
+        for (layer, tile) in self.tiles.iter().enumerate() {
+            let layer = layer as u32;
+            atlas
+                .copy_from(
+                    tile,
+                    layer % ATLAS_TILES * self.tile_size,
+                    layer / ATLAS_TILES * self.tile_size,
+                )
+                .expect("every tile fits in the atlas");
+        }
+
+        atlas
+    }
+}
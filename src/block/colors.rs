@@ -0,0 +1,141 @@
+//! the average colour of every block type, measured on the block
+//! textures, used to turn coloured voxels and pixels into blocks
+
+use image::RgbaImage;
+
+use super::atlas::BlockAtlas;
+use super::types::block_types;
+use super::BlockType;
+
+pub struct BlockColors {
+    colors: Vec<(&'static BlockType, [f32; 4])>,
+    // the top face and the four side faces on their own, for renderers
+    // that shade each face differently
+    face_colors: Vec<([f32; 4], [f32; 4])>,
+}
+
+// colours weighted by their alpha so see-through pixels don't count
+#[derive(Copy, Clone, Default)]
+struct Sum {
+    rgb: [f64; 3],
+    alpha: f64,
+    pixels: usize,
+}
+
+impl Sum {
+    fn of_image(image: &RgbaImage) -> Sum {
+        let mut sum = Sum::default();
+
+        for pixel in image.pixels() {
+            let pixel = pixel.0;
+            let a = pixel[3] as f64 / 255.0;
+
+            for (channel, &value) in sum.rgb.iter_mut().zip(pixel.iter()) {
+                *channel += value as f64 / 255.0 * a;
+            }
+            sum.alpha += a;
+            sum.pixels += 1;
+        }
+
+        sum
+    }
+
+    fn add(self, other: &Sum) -> Sum {
+        Sum {
+            rgb: [
+                self.rgb[0] + other.rgb[0],
+                self.rgb[1] + other.rgb[1],
+                self.rgb[2] + other.rgb[2],
+            ],
+            alpha: self.alpha + other.alpha,
+            pixels: self.pixels + other.pixels,
+        }
+    }
+
+    fn average(&self) -> [f32; 4] {
+        if self.alpha > 0.0 {
+            [
+                (self.rgb[0] / self.alpha) as f32,
+                (self.rgb[1] / self.alpha) as f32,
+                (self.rgb[2] / self.alpha) as f32,
+                (self.alpha / self.pixels as f64) as f32,
+            ]
+        } else {
+            [0.0; 4]
+        }
+    }
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+impl BlockColors {
+    /// averages the pixels of all six faces of every block type
+    pub fn from_block_atlas(atlas: &BlockAtlas) -> Self {
+        let mut colors = vec![];
+        let mut face_colors = vec![];
+
+        for &block_type in block_types().iter() {
+            let layers = atlas.layers().of(block_type);
+            let sum = |layer: u32| Sum::of_image(&atlas.tiles()[layer as usize]);
+
+            let top = sum(layers.top);
+            let sides = [layers.front, layers.right, layers.back, layers.left]
+                .iter()
+                .fold(Sum::default(), |a, &b| a.add(&sum(b)));
+            let all = sides.add(&top).add(&sum(layers.bottom));
+
+            colors.push((block_type, all.average()));
+            face_colors.push((top.average(), sides.average()));
+        }
+
+        BlockColors {
+            colors: colors,
+            face_colors: face_colors,
+        }
+    }
+
+    /// the average colour of a block type as rgba between 0 and 1
+    pub fn get(&self, block_type: &BlockType) -> [f32; 4] {
+        self.colors
+            .iter()
+            .find(|(t, _)| t.name == block_type.name)
+            .map(|(_, c)| *c)
+            .unwrap_or([0.0; 4])
+    }
+
+    /// the average colours of the top face and of the side faces
+    pub fn faces(&self, block_type: &BlockType) -> ([f32; 4], [f32; 4]) {
+        self.colors
+            .iter()
+            .position(|(t, _)| t.name == block_type.name)
+            .map(|i| self.face_colors[i])
+            .unwrap_or(([0.0; 4], [0.0; 4]))
+    }
+
+    /// the opaque block type whose colour is closest to an rgb colour
+    pub fn closest(&self, rgb: [f32; 3]) -> &'static BlockType {
+        self.closest_of(rgb, |t| !t.transparent)
+    }
+
+    /// the closest block type out of the ones `include` returns true for,
+    /// falls back to every block type when it excludes all of them
+    pub fn closest_of<F: Fn(&BlockType) -> bool>(
+        &self,
+        rgb: [f32; 3],
+        include: F,
+    ) -> &'static BlockType {
+        let candidates = || self.colors.iter().filter(|(t, _)| include(t));
+        let compare = |a: &&(&BlockType, [f32; 4]), b: &&(&BlockType, [f32; 4])| {
+            distance(rgb, [a.1[0], a.1[1], a.1[2]])
+                .partial_cmp(&distance(rgb, [b.1[0], b.1[1], b.1[2]]))
+                .unwrap()
+        };
+
+        match candidates().min_by(compare) {
+            Some((block_type, _)) => block_type,
+            None => self.colors.iter().min_by(compare).unwrap().0,
+        }
+    }
+}
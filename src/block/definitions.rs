@@ -0,0 +1,231 @@
+//! block definitions, the properties and textures of the block types
+//! as the resource packs change them
+//!
+//! a pack keeps its definitions in `blocks.txt`, one
+//! `block type.property = value` per line, like
+//! `glass block.textures = stained_glass` or
+//! `glass block.transparent = false`. the properties are
+//!
+//! - `textures`, the texture of every face, the textures of
+//!   `top bottom sides` or the textures of
+//!   `front right back left top bottom`, see atlas.rs
+//! - `transparent`, `true` or `false`
+//!
+//! every property of a block type comes from the highest priority pack
+//! that sets it, the ones no pack sets keep the value the game gives them.
+//! packs can only change the block types the game has, since a world
+//! would lose blocks of a new type as soon as the pack was taken away.
+//! lines starting with `#` are comments.
+
+use std::error::Error;
+
+use super::types::{block_types, BLOCK_TYPES};
+use super::{BlockTextures, BlockType};
+use crate::resource_pack::ResourcePacks;
+
+/// where the resource packs keep their block definitions
+pub const BLOCK_DEFINITIONS_FILE: &str = "blocks.txt";
+
+const LINE_FORMAT: &str = "expected `block type.property = value`";
+
+/// the block types with the definitions of every pack applied, the same
+/// slice as `block_types()` when nothing changed. fails if a pack has a
+/// definitions file that can't be read or parsed
+pub fn load(packs: &ResourcePacks) -> Result<&'static [&'static BlockType], Box<dyn Error>> {
+    // lowest priority first, so the highest priority pack is applied last
+    let mut files = vec![];
+    for pack in packs.packs().iter().rev() {
+        if let Some(bytes) = pack.read(BLOCK_DEFINITIONS_FILE)? {
+            let definitions = String::from_utf8(bytes)
+                .map_err(|e| format!("{} in {}: {}", BLOCK_DEFINITIONS_FILE, pack.name(), e))?;
+            files.push((pack.name(), definitions));
+        }
+    }
+    let resolved = resolve(&files)?;
+
+    let in_use = block_types();
+    if resolved.iter().eq(in_use.iter().copied()) {
+        return Ok(in_use);
+    }
+
+    // blocks hold on to their block type forever, so the new ones are never freed
+    let leaked: Vec<&'static BlockType> = resolved
+        .into_iter()
+        .map(|block_type| &*Box::leak(Box::new(block_type)))
+        .collect();
+    Ok(Box::leak(leaked.into_boxed_slice()))
+}
+
+/// applies the definitions files, given as pack name and contents
+/// from the lowest priority pack to the highest, to the built in block types
+fn resolve(files: &[(&str, String)]) -> Result<Vec<BlockType>, Box<dyn Error>> {
+    let mut resolved: Vec<BlockType> = BLOCK_TYPES.iter().map(|&&block_type| block_type).collect();
+
+    for (pack, definitions) in files.iter() {
+        apply(definitions, &mut resolved)
+            .map_err(|e| format!("{} in {}: {}", BLOCK_DEFINITIONS_FILE, pack, e))?;
+    }
+
+    Ok(resolved)
+}
+
+/// changes the block types the way one definitions file says
+fn apply(definitions: &str, block_types: &mut [BlockType]) -> Result<(), Box<dyn Error>> {
+    for (number, line) in definitions.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        apply_line(line, block_types).map_err(|e| format!("line {}: {}", number + 1, e))?;
+    }
+
+    Ok(())
+}
+
+fn apply_line(line: &str, block_types: &mut [BlockType]) -> Result<(), Box<dyn Error>> {
+    let mut parts = line.splitn(2, '=');
+    let (key, value) = match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) => (key.trim(), value.trim()),
+        _ => return Err(LINE_FORMAT.into()),
+    };
+    // block type names have spaces but no dots in them
+    let (name, property) = match key.rsplit_once('.') {
+        Some((name, property)) => (name.trim(), property.trim()),
+        None => return Err(LINE_FORMAT.into()),
+    };
+
+    let block_type = match block_types.iter_mut().find(|t| t.name == name) {
+        Some(block_type) => block_type,
+        None => return Err(format!("unknown block type \"{}\"", name).into()),
+    };
+
+    match property {
+        "textures" => block_type.textures = parse_textures(value)?,
+        "transparent" => {
+            block_type.transparent = match value {
+                "true" => true,
+                "false" => false,
+                _ => return Err(format!("expected true or false, not \"{}\"", value).into()),
+            }
+        }
+        _ => return Err(format!("unknown property \"{}\"", property).into()),
+    }
+
+    Ok(())
+}
+
+fn parse_textures(value: &str) -> Result<BlockTextures, Box<dyn Error>> {
+    let names: Vec<&str> = value.split_whitespace().collect();
+
+    // the names end up in paths, so they can't point out of the textures directory
+    for name in names.iter() {
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "invalid texture name \"{}\", only letters, digits, '-' and '_' are allowed",
+                name
+            )
+            .into());
+        }
+    }
+
+    // block types live as long as the game, so their texture names do too
+    let names: Vec<&'static str> = names
+        .into_iter()
+        .map(|name| &*Box::leak(name.to_string().into_boxed_str()))
+        .collect();
+
+    match names[..] {
+        [all] => Ok(BlockTextures::all(all)),
+        [top, bottom, sides] => Ok(BlockTextures {
+            front: sides,
+            right: sides,
+            back: sides,
+            left: sides,
+            top: top,
+            bottom: bottom,
+        }),
+        [front, right, back, left, top, bottom] => Ok(BlockTextures {
+            front: front,
+            right: right,
+            back: back,
+            left: left,
+            top: top,
+            bottom: bottom,
+        }),
+        _ => Err(format!("expected 1, 3 or 6 textures, not {}", names.len()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::types::{GLASS_BLOCK, GRASS_BLOCK, STONE_BLOCK};
+
+    fn find<'a>(block_types: &'a [BlockType], name: &str) -> &'a BlockType {
+        block_types.iter().find(|t| t.name == name).unwrap()
+    }
+
+    #[test]
+    fn no_definitions_keep_the_built_in_block_types() {
+        let resolved = resolve(&[("pack", "# nothing here\n\n".to_string())]).unwrap();
+
+        assert!(resolved.iter().eq(BLOCK_TYPES.iter().copied()));
+    }
+
+    #[test]
+    fn definitions_change_textures_and_properties() {
+        let definitions = "
+            glass block.textures = stained_glass
+            glass block.transparent = false
+            grass block.textures = snow dirt snowy_side
+            stone block.textures = a b c d e f
+        ";
+        let resolved = resolve(&[("pack", definitions.to_string())]).unwrap();
+
+        let glass = find(&resolved, GLASS_BLOCK.name);
+        assert_eq!(glass.textures, BlockTextures::all("stained_glass"));
+        assert!(!glass.transparent);
+
+        let grass = find(&resolved, GRASS_BLOCK.name);
+        assert_eq!(grass.textures.top, "snow");
+        assert_eq!(grass.textures.bottom, "dirt");
+        assert_eq!(grass.textures.faces()[0], "snowy_side");
+
+        let stone = find(&resolved, STONE_BLOCK.name);
+        assert_eq!(stone.textures.faces(), ["d", "b", "f", "e", "c", "a"]);
+    }
+
+    #[test]
+    fn higher_priority_packs_win_per_property() {
+        let low = "
+            glass block.textures = low_glass
+            glass block.transparent = false
+        ";
+        let high = "glass block.textures = high_glass";
+        let resolved = resolve(&[("low", low.to_string()), ("high", high.to_string())]).unwrap();
+
+        let glass = find(&resolved, GLASS_BLOCK.name);
+        assert_eq!(glass.textures, BlockTextures::all("high_glass"));
+        assert!(!glass.transparent);
+    }
+
+    #[test]
+    fn bad_definitions_are_rejected() {
+        for definitions in [
+            "glass block = glass",
+            "marble block.textures = marble",
+            "glass block.colour = red",
+            "glass block.transparent = maybe",
+            "glass block.textures = a b",
+            "glass block.textures =",
+            "glass block.textures = ../../secret",
+        ] {
+            let error = resolve(&[("pack", definitions.to_string())]).unwrap_err();
+            assert!(error.to_string().contains("line 1"), "{}", error);
+        }
+    }
+}
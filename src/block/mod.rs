@@ -1,19 +1,92 @@
+pub mod atlas;
+pub mod colors;
+pub mod definitions;
 pub mod types;
 
-#[derive(Copy, Clone, Debug)]
-pub struct BlockUVCoordinates {
-    pub front: ([f32; 2], [f32; 2]),
-    pub right: ([f32; 2], [f32; 2]),
-    pub back: ([f32; 2], [f32; 2]),
-    pub left: ([f32; 2], [f32; 2]),
-    pub top: ([f32; 2], [f32; 2]),
-    pub bottom: ([f32; 2], [f32; 2]),
+/// how many tiles the packed atlas has along each side, see atlas.rs
+pub const ATLAS_TILES: u32 = 64;
+
+/// the name of the texture every face of a block uses, each one
+/// is loaded from `<name>.png` in the block textures directory
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockTextures {
+    pub front: &'static str,
+    pub right: &'static str,
+    pub back: &'static str,
+    pub left: &'static str,
+    pub top: &'static str,
+    pub bottom: &'static str,
+}
+
+impl BlockTextures {
+    /// the same texture on every face
+    pub const fn all(name: &'static str) -> Self {
+        BlockTextures {
+            front: name,
+            right: name,
+            back: name,
+            left: name,
+            top: name,
+            bottom: name,
+        }
+    }
+
+    /// the textures in the order -x, +x, -y, +y, -z, +z
+    pub fn faces(&self) -> [&'static str; 6] {
+        [
+            self.left,
+            self.right,
+            self.bottom,
+            self.top,
+            self.back,
+            self.front,
+        ]
+    }
 }
 
+/// the layer of the block texture array every face of a block
+/// uses, see atlas::TextureLayers
 #[derive(Copy, Clone, Debug)]
+pub struct BlockTextureLayers {
+    pub front: u32,
+    pub right: u32,
+    pub back: u32,
+    pub left: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl BlockTextureLayers {
+    /// the layers in the order -x, +x, -y, +y, -z, +z
+    pub fn faces(&self) -> [u32; 6] {
+        [
+            self.left,
+            self.right,
+            self.bottom,
+            self.top,
+            self.back,
+            self.front,
+        ]
+    }
+}
+
+/// where a layer is in the packed atlas as a uv rectangle, for everything
+/// that still uses a single image. the atlas is flipped when it's loaded,
+/// so v = 1 is the top row
+pub fn atlas_uv(layer: u32) -> ([f32; 2], [f32; 2]) {
+    let tile = 1.0 / ATLAS_TILES as f32;
+    let (x, row) = ((layer % ATLAS_TILES) as f32, (layer / ATLAS_TILES) as f32);
+
+    (
+        [x * tile, 1.0 - (row + 1.0) * tile],
+        [(x + 1.0) * tile, 1.0 - row * tile],
+    )
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct BlockType {
     pub name: &'static str,
-    pub uv: BlockUVCoordinates,
+    pub textures: BlockTextures,
     pub transparent: bool,
 }
 
@@ -1,15 +1,17 @@
-use super::{BlockType, BlockUVCoordinates};
+use std::sync::RwLock;
+
+use super::{BlockTextures, BlockType};
 
 #[allow(dead_code)]
 pub const GRASS_BLOCK: BlockType = BlockType {
     name: "grass block",
-    uv: BlockUVCoordinates {
-        front: ([0.0, 1.0 - 1.0 / 64.0], [1.0 / 64.0, 1.0]),
-        right: ([0.0, 1.0 - 1.0 / 64.0], [1.0 / 64.0, 1.0]),
-        back: ([0.0, 1.0 - 1.0 / 64.0], [1.0 / 64.0, 1.0]),
-        left: ([0.0, 1.0 - 1.0 / 64.0], [1.0 / 64.0, 1.0]),
-        top: ([1.0 / 64.0, 1.0 - 1.0 / 64.0], [1.0 / 32.0, 1.0]),
-        bottom: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
+    textures: BlockTextures {
+        front: "grass_block_side",
+        right: "grass_block_side",
+        back: "grass_block_side",
+        left: "grass_block_side",
+        top: "grass_block_top",
+        bottom: "dirt",
     },
     transparent: false,
 };
@@ -17,41 +19,50 @@ pub const GRASS_BLOCK: BlockType = BlockType {
 #[allow(dead_code)]
 pub const DIRT_BLOCK: BlockType = BlockType {
     name: "dirt block",
-    uv: BlockUVCoordinates {
-        front: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
-        right: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
-        back: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
-        left: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
-        top: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
-        bottom: ([1.0 / 32.0, 1.0 - 1.0 / 64.0], [3.0 * (1.0 / 64.0), 1.0]),
-    },
+    textures: BlockTextures::all("dirt"),
     transparent: false,
 };
 
 #[allow(dead_code)]
 pub const STONE_BLOCK: BlockType = BlockType {
     name: "stone block",
-    uv: BlockUVCoordinates {
-        front: ([3.0 * (1.0 / 64.0), 1.0 - 1.0 / 64.0], [1.0 / 16.0, 1.0]),
-        right: ([3.0 * (1.0 / 64.0), 1.0 - 1.0 / 64.0], [1.0 / 16.0, 1.0]),
-        back: ([3.0 * (1.0 / 64.0), 1.0 - 1.0 / 64.0], [1.0 / 16.0, 1.0]),
-        left: ([3.0 * (1.0 / 64.0), 1.0 - 1.0 / 64.0], [1.0 / 16.0, 1.0]),
-        top: ([3.0 * (1.0 / 64.0), 1.0 - 1.0 / 64.0], [1.0 / 16.0, 1.0]),
-        bottom: ([3.0 * (1.0 / 64.0), 1.0 - 1.0 / 64.0], [1.0 / 16.0, 1.0]),
-    },
+    textures: BlockTextures::all("stone"),
     transparent: false,
 };
 
 #[allow(dead_code)]
 pub const GLASS_BLOCK: BlockType = BlockType {
     name: "glass block",
-    uv: BlockUVCoordinates {
-        front: ([4.0 * (1.0 / 64.0), 63.0 / 64.0], [5.0 * (1.0 / 64.0), 1.0]),
-        right: ([4.0 * (1.0 / 64.0), 63.0 / 64.0], [5.0 * (1.0 / 64.0), 1.0]),
-        back: ([4.0 * (1.0 / 64.0), 63.0 / 64.0], [5.0 * (1.0 / 64.0), 1.0]),
-        left: ([4.0 * (1.0 / 64.0), 63.0 / 64.0], [5.0 * (1.0 / 64.0), 1.0]),
-        top: ([4.0 * (1.0 / 64.0), 63.0 / 64.0], [5.0 * (1.0 / 64.0), 1.0]),
-        bottom: ([4.0 * (1.0 / 64.0), 63.0 / 64.0], [5.0 * (1.0 / 64.0), 1.0]),
-    },
+    textures: BlockTextures::all("glass"),
     transparent: true,
 };
+
+/// every block type that exists, as the game defines them. blocks are
+/// looked up by their name when a world is loaded from disk
+pub const BLOCK_TYPES: &[&BlockType] = &[&GRASS_BLOCK, &DIRT_BLOCK, &STONE_BLOCK, &GLASS_BLOCK];
+
+// the block types with the definitions of the resource packs applied,
+// see definitions.rs. always has the same names in the same order as BLOCK_TYPES
+static IN_USE: RwLock<&[&BlockType]> = RwLock::new(BLOCK_TYPES);
+
+/// the block types in use, every new block should use one of these
+pub fn block_types() -> &'static [&'static BlockType] {
+    *IN_USE.read().unwrap()
+}
+
+/// replaces the block types in use, chunks still holding the
+/// old ones have to be moved over with `current`
+pub fn set_block_types(block_types: &'static [&'static BlockType]) {
+    *IN_USE.write().unwrap() = block_types;
+}
+
+pub fn block_type_by_name(name: &str) -> Option<&'static BlockType> {
+    block_types().iter().copied().find(|t| t.name == name)
+}
+
+/// the block type in use with the same name, for the constants above
+/// and for blocks that were placed before the block types were replaced
+pub fn current(block_type: &BlockType) -> &'static BlockType {
+    block_type_by_name(block_type.name)
+        .unwrap_or_else(|| panic!("{} is not a block type", block_type.name))
+}
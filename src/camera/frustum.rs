@@ -0,0 +1,63 @@
+use super::Camera;
+
+/// the six planes around the part of the world that ends up on screen,
+/// each one is `(a, b, c, d)` with the normal `(a, b, c)` pointing inwards
+pub struct Frustum {
+    planes: [[f32; 4]; 6],
+}
+
+/// multiplies two column major matrices
+fn multiply(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column][row] = (0..4).map(|i| a[i][row] * b[column][i]).sum();
+        }
+    }
+
+    result
+}
+
+impl Frustum {
+    /// the planes are taken straight from the rows of the combined
+    /// projection and view matrix, see Gribb and Hartmann's
+    /// "Fast Extraction of Viewing Frustum Planes"
+    pub fn from_camera(camera: &dyn Camera) -> Self {
+        let m = multiply(
+            camera.get_projection(),
+            multiply(camera.get_view_rotation(), camera.get_view_translation()),
+        );
+        let row = |i: usize| [m[0][i], m[1][i], m[2][i], m[3][i]];
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+        let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+        Frustum {
+            planes: [
+                add(w, x),
+                sub(w, x),
+                add(w, y),
+                sub(w, y),
+                add(w, z),
+                sub(w, z),
+            ],
+        }
+    }
+
+    /// checks if any part of an axis aligned box might be visible, boxes
+    /// close to a corner of the frustum can pass without being visible
+    pub fn intersects_aabb(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner of the box that is the furthest along the normal
+            let corner = [
+                if plane[0] >= 0.0 { max[0] } else { min[0] },
+                if plane[1] >= 0.0 { max[1] } else { min[1] },
+                if plane[2] >= 0.0 { max[2] } else { min[2] },
+            ];
+
+            plane[0] * corner[0] + plane[1] * corner[1] + plane[2] * corner[2] + plane[3] >= 0.0
+        })
+    }
+}
@@ -1,13 +1,19 @@
 pub mod controller;
 
 mod firstperson;
+mod frustum;
 mod orbital;
 
 pub use firstperson::FirstPersonCamera;
+pub use frustum::Frustum;
 pub use orbital::OrbitalCamera;
 
-pub const CLIP_NEAR: f32 = 0.01;
-pub const CLIP_FAR: f32 = 64.0;
+// with a 24 bit depth buffer the precision far away depends on how close
+// the near plane is, at 0.01 faces a few hundred blocks away fight
+pub const CLIP_NEAR: f32 = 0.1;
+// sees from one corner of a 256 by 256 block world, 512 blocks high, to the
+// other. far chunks are drawn with level of detail meshes, see world/lod.rs
+pub const CLIP_FAR: f32 = 768.0;
 
 pub trait Camera {
     // these return matrices that manipulate the world around the camera
@@ -1,25 +1,23 @@
+use std::collections::hash_map::DefaultHasher;
 use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
 
+use crate::arena::{ArenaAllocation, MeshArena};
+use crate::block::atlas::TextureLayers;
+use crate::block::types::{block_types, current};
 pub use crate::block::*;
-use crate::camera::Camera;
-use crate::graphics::{Mesh, Vertex, WorldUniforms};
+use crate::graphics::{ChunkVertex, Vertex};
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 
-use glium::index::PrimitiveType;
-use glium::{uniform, Display, DrawParameters, Program, Surface};
+use glium::Display;
 
 pub const CHUNK_SIZE: usize = 32;
 pub const CHUNK_SIZE_I8: i8 = 32;
 pub const CHUNK_SIZE_U8: u8 = 32;
 pub const CHUNK_SIZE_I32: i32 = 32;
 
-pub struct ChunkUniforms {
-    model_translation: [[f32; 4]; 4],
-    model_rotation: [[f32; 4]; 4],
-}
-
 #[derive(Clone, Copy)]
 pub struct ChunkNeighbours<'a> {
     pub front: Option<&'a Chunk>,
@@ -48,6 +46,36 @@ pub fn get_chunk_neighbours<'a>(chunks: &'a [Chunk], coords: [i32; 3]) -> ChunkN
     }
 }
 
+/// looks up a block by its global coordinates, blocks
+/// in chunks that don't exist are treated as air
+pub fn get_block_at(chunks: &[Chunk], coords: [i32; 3]) -> Option<Block> {
+    let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(coords);
+
+    match chunks.iter().find(|c| c.coordinates == chunk_coords) {
+        Some(chunk) => *chunk.get_block(block_coords),
+        None => None,
+    }
+}
+
+/// replaces a block by its global coordinates, with the block type in use
+/// of the same name. returns false if the chunk it would be in doesn't exist
+pub fn set_block_at(
+    chunks: &mut [Chunk],
+    coords: [i32; 3],
+    block_type: Option<&'static BlockType>,
+) -> bool {
+    let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(coords);
+
+    match chunks.iter_mut().find(|c| c.coordinates == chunk_coords) {
+        Some(chunk) => {
+            *chunk.get_block_mut(block_coords) =
+                block_type.map(|block_type| Block::new(block_coords, current(block_type)));
+            true
+        }
+        None => false,
+    }
+}
+
 impl<'a> ChunkNeighbours<'a> {
     /*pub fn is_complete(&self) -> bool {
         self.front.is_some()
@@ -63,55 +91,206 @@ impl<'a> ChunkNeighbours<'a> {
     }
 }
 
+/// how far the camera has to move, in blocks, before the faces
+/// of a transparent mesh are sorted again
+const FACE_RESORT_DISTANCE: f32 = 1.0;
+
+/// the faces of a transparent mesh, kept around so that they
+/// can be drawn from the back to the front
+struct FaceOrder {
+    /// the center of every face in the chunk's local coordinates
+    /// along with the indices of its two triangles
+    faces: Vec<([f32; 3], [u32; 6])>,
+    /// where the camera was, relative to the chunk, at the last sort
+    sorted_from: Option<[f32; 3]>,
+}
+
+impl FaceOrder {
+    fn new(verts: &[ChunkVertex], indices: &[u32]) -> Self {
+        let faces = indices
+            .chunks_exact(6)
+            .map(|face| {
+                // the triangles share two corners, see base_indices
+                let corners = [face[0], face[1], face[2], face[4]];
+                let mut center = [0.0; 3];
+                for &corner in corners.iter() {
+                    let position = verts[corner as usize].position();
+                    for (axis, coordinate) in center.iter_mut().enumerate() {
+                        *coordinate += position[axis] / 4.0;
+                    }
+                }
+
+                (center, face.try_into().unwrap())
+            })
+            .collect();
+
+        FaceOrder {
+            faces: faces,
+            sorted_from: None,
+        }
+    }
+}
+
 pub struct ChunkMesh {
-    mesh: Option<Mesh<Vertex>>,
+    allocation: Option<ArenaAllocation>,
+    face_order: Option<FaceOrder>,
     pub dirty: bool,
 }
 
 impl ChunkMesh {
     pub fn ungenerated() -> Self {
         ChunkMesh {
-            mesh: None,
+            allocation: None,
+            face_order: None,
             dirty: true,
         }
     }
 
-    pub fn render<S: Surface>(
-        &self,
-        surface: &mut S,
-        shader: &Program,
-        params: &DrawParameters,
-        world_uniforms: &WorldUniforms,
-        chunk_uniforms: &ChunkUniforms,
-        camera: &dyn Camera,
-    ) {
-        match &self.mesh {
-            Some(mesh) => {
-                let uniforms = uniform! {
-                    projection: camera.get_projection(),
-                    view_translation: camera.get_view_translation(),
-                    view_rotation: camera.get_view_rotation(),
-                    model_translation: chunk_uniforms.model_translation,
-                    model_rotation: chunk_uniforms.model_rotation,
-                    atlas: world_uniforms.texture_atlas,
-                    render_distance: world_uniforms.render_distance,
-                };
+    /// writes already built geometry into the arena, `sorted` keeps the
+    /// faces around so they can be sorted later, see sort_faces
+    pub fn new(
+        display: &Display,
+        arena: &mut MeshArena,
+        verts: &[ChunkVertex],
+        indices: &[u32],
+        sorted: bool,
+    ) -> Self {
+        if verts.len() == 0 {
+            ChunkMesh {
+                allocation: None,
+                face_order: None,
+                dirty: false,
+            }
+        } else {
+            let face_order = match sorted {
+                true => Some(FaceOrder::new(verts, indices)),
+                false => None,
+            };
+
+            let allocation = arena
+                .allocate(display, verts, indices)
+                .expect("failed to write mesh into the arena");
 
-                surface
-                    .draw(&mesh.vertices, &mesh.indices, &shader, &uniforms, &params)
-                    .unwrap();
+            ChunkMesh {
+                allocation: Some(allocation),
+                face_order: face_order,
+                dirty: false,
+            }
+        }
+    }
+
+    /// where the mesh is in the arena, None if it has no faces
+    pub fn allocation(&self) -> Option<&ArenaAllocation> {
+        self.allocation.as_ref()
+    }
+
+    /// gives the space the mesh took up back to the arena
+    pub fn free(self, arena: &mut MeshArena) {
+        if let Some(allocation) = self.allocation {
+            arena.free(allocation);
+        }
+    }
+
+    /// orders the faces of a transparent mesh from the furthest to the
+    /// closest one, but only once the camera has moved far enough since
+    /// the last time. only the index buffer is written, the vertices stay
+    pub fn sort_faces(&mut self, arena: &MeshArena, camera: [f32; 3]) {
+        let (allocation, order) = match (&self.allocation, &mut self.face_order) {
+            (Some(allocation), Some(order)) => (allocation, order),
+            _ => return,
+        };
+
+        if let Some(sorted_from) = order.sorted_from {
+            let moved = (0..3)
+                .map(|axis| (camera[axis] - sorted_from[axis]).powi(2))
+                .sum::<f32>();
+            if moved < FACE_RESORT_DISTANCE * FACE_RESORT_DISTANCE {
+                return;
             }
-            None => (),
         }
+
+        let distance = |center: &[f32; 3]| {
+            (0..3)
+                .map(|axis| (center[axis] - camera[axis]).powi(2))
+                .sum::<f32>()
+        };
+        order.faces.sort_by(|a, b| {
+            distance(&b.0)
+                .partial_cmp(&distance(&a.0))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let indices: Vec<u32> = order
+            .faces
+            .iter()
+            .flat_map(|(_, face)| face.iter().copied())
+            .collect();
+        arena.write_indices(allocation, &indices);
+
+        order.sorted_from = Some(camera);
     }
 }
 
+/// how far a chunk has progressed through the generation pipeline,
+/// the stages are run in declaration order, see `world::generation`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChunkStatus {
+    Empty,
+    Shaped,
+    Carved,
+    Surfaced,
+    Decorated,
+    Lit,
+    Meshed,
+}
+
+impl ChunkStatus {
+    pub fn next(self) -> Option<ChunkStatus> {
+        match self {
+            ChunkStatus::Empty => Some(ChunkStatus::Shaped),
+            ChunkStatus::Shaped => Some(ChunkStatus::Carved),
+            ChunkStatus::Carved => Some(ChunkStatus::Surfaced),
+            ChunkStatus::Surfaced => Some(ChunkStatus::Decorated),
+            ChunkStatus::Decorated => Some(ChunkStatus::Lit),
+            ChunkStatus::Lit => Some(ChunkStatus::Meshed),
+            ChunkStatus::Meshed => None,
+        }
+    }
+}
+
+pub const MAX_LIGHT: u8 = 15;
+
 pub struct Chunk {
     pub coordinates: [i32; 3],
+    pub status: ChunkStatus,
     pub blocks: Box<[[[Option<Block>; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]>,
+    pub skylight: Box<[[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]>,
 }
 
 impl Chunk {
+    pub fn allocate_skylight() -> Box<[[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]> {
+        vec![[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]
+            .into_boxed_slice()
+            .try_into()
+            .unwrap()
+    }
+
+    /// creates a chunk that hasn't gone through any generation stage yet
+    pub fn empty(coords: [i32; 3]) -> Chunk {
+        let blocks: Box<[[[Option<Block>; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]> =
+            vec![[[None; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]
+                .into_boxed_slice()
+                .try_into()
+                .unwrap();
+
+        Chunk {
+            coordinates: coords,
+            status: ChunkStatus::Empty,
+            blocks: blocks,
+            skylight: Self::allocate_skylight(),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn filled(coords: [i32; 3], block_type: &'static BlockType) -> Chunk {
         let mut blocks: Box<[[[Option<Block>; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]> =
@@ -131,10 +310,13 @@ impl Chunk {
 
         Chunk {
             coordinates: coords,
+            status: ChunkStatus::Decorated,
             blocks: blocks,
+            skylight: Self::allocate_skylight(),
         }
     }
 
+    #[allow(dead_code)]
     pub fn from_blocktypes(
         coords: [i32; 3],
         blocktypes: &[[[Option<&'static BlockType>; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
@@ -160,11 +342,13 @@ impl Chunk {
         trace!("initializing chunk at {:?}", coords);
         Chunk {
             coordinates: coords,
+            status: ChunkStatus::Decorated,
             blocks: blocks,
+            skylight: Self::allocate_skylight(),
         }
     }
 
-    fn get_translation_matrix(&self) -> [[f32; 4]; 4] {
+    pub fn get_translation_matrix(&self) -> [[f32; 4]; 4] {
         [
             [1.0, 0.0, 0.0, 0.0],
             [0.0, 1.0, 0.0, 0.0],
@@ -178,20 +362,16 @@ impl Chunk {
         ]
     }
 
-    fn get_rotation_matrix(&self) -> [[f32; 4]; 4] {
-        [
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]
-    }
+    /// the lowest and highest corner of the space the chunk takes up
+    pub fn get_bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let min = [
+            (self.coordinates[0] * CHUNK_SIZE_I32) as f32,
+            (self.coordinates[1] * CHUNK_SIZE_I32) as f32,
+            (self.coordinates[2] * CHUNK_SIZE_I32) as f32,
+        ];
+        let size = CHUNK_SIZE as f32;
 
-    pub fn get_uniforms(&self) -> ChunkUniforms {
-        ChunkUniforms {
-            model_translation: self.get_translation_matrix(),
-            model_rotation: self.get_rotation_matrix(),
-        }
+        (min, [min[0] + size, min[1] + size, min[2] + size])
     }
 
     fn get_block_neighbors(
@@ -327,6 +507,22 @@ impl Chunk {
         neighbors
     }
 
+    /// checks if the chunk contains nothing but air
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().flatten().flatten().all(|b| b.is_none())
+    }
+
+    /// a hash of the block types in the chunk, chunks with the same blocks
+    /// hash the same no matter their light or status. the hash differs
+    /// between builds of the game, so it must not be saved
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for block in self.blocks.iter().flatten().flatten() {
+            block.map(|b| b.block_type.name).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
     pub fn get_block(&self, coords: [u8; 3]) -> &Option<Block> {
         trace!(
             "getting block at {:?} in chunk {:?}",
@@ -371,27 +567,159 @@ impl Chunk {
     pub fn generate_base_chunkmesh(
         &self,
         display: &Display,
+        arena: &mut MeshArena,
         chunk_neighbors: ChunkNeighbours,
     ) -> ChunkMesh {
-        self.generate_chunkmesh(display, |t| !t.transparent, chunk_neighbors)
+        self.generate_chunkmesh(display, arena, |t| !t.transparent, chunk_neighbors, false)
     }
 
     pub fn generate_transparent_chunkmesh(
         &self,
         display: &Display,
+        arena: &mut MeshArena,
         chunk_neighbors: ChunkNeighbours,
     ) -> ChunkMesh {
-        self.generate_chunkmesh(display, |t| t.transparent, chunk_neighbors)
+        self.generate_chunkmesh(display, arena, |t| t.transparent, chunk_neighbors, true)
     }
 
-    // block_includer should return true when
-    // a block should be included in the mesh
     fn generate_chunkmesh<F: Fn(&BlockType) -> bool>(
         &self,
         display: &glium::Display,
+        arena: &mut MeshArena,
         block_includer: F,
         chunk_neighbors: ChunkNeighbours,
+        sorted: bool,
     ) -> ChunkMesh {
+        let (verts, indices) = self.generate_chunkmesh_geometry(block_includer, chunk_neighbors);
+        let verts = self.pack_vertices(&verts, chunk_neighbors, 1);
+
+        ChunkMesh::new(display, arena, &verts, &indices, sorted)
+    }
+
+    /// looks up a block and the light in it, the coordinates may be outside
+    /// of the chunk on one axis. blocks that aren't in the chunk or one of
+    /// its neighbours are treated as air in full light
+    fn get_block_and_light(
+        &self,
+        coords: [i32; 3],
+        chunk_neighbors: ChunkNeighbours,
+    ) -> (Option<Block>, u8) {
+        let outside: Vec<usize> = (0..3)
+            .filter(|&axis| coords[axis] < 0 || coords[axis] >= CHUNK_SIZE_I32)
+            .collect();
+
+        let chunk = match outside[..] {
+            [] => Some(self),
+            [0] if coords[0] < 0 => chunk_neighbors.left,
+            [0] => chunk_neighbors.right,
+            [1] if coords[1] < 0 => chunk_neighbors.below,
+            [1] => chunk_neighbors.above,
+            [2] if coords[2] < 0 => chunk_neighbors.back,
+            [2] => chunk_neighbors.front,
+            _ => None,
+        };
+
+        match chunk {
+            Some(chunk) => {
+                let x = coords[0].rem_euclid(CHUNK_SIZE_I32) as usize;
+                let y = coords[1].rem_euclid(CHUNK_SIZE_I32) as usize;
+                let z = coords[2].rem_euclid(CHUNK_SIZE_I32) as usize;
+                (chunk.blocks[x][y][z], chunk.skylight[x][y][z])
+            }
+            None => (None, MAX_LIGHT),
+        }
+    }
+
+    /// packs the vertices from generate_chunkmesh_geometry for the gpu and
+    /// works out the ambient occlusion and light at every corner of every
+    /// face. the positions are multiplied by `scale`, see world/lod.rs
+    pub fn pack_vertices(
+        &self,
+        verts: &[Vertex],
+        chunk_neighbors: ChunkNeighbours,
+        scale: u32,
+    ) -> Vec<ChunkVertex> {
+        let occludes = |coords: [i32; 3]| {
+            let (block, _) = self.get_block_and_light(coords, chunk_neighbors);
+            block.is_some_and(|block| !block.block_type.transparent)
+        };
+
+        let texture_layers = TextureLayers::from_block_types(block_types());
+        let mut packed = Vec::with_capacity(verts.len());
+
+        for face in verts.chunks_exact(4) {
+            let normal = face[0].normal;
+            let axis = (0..3).find(|&axis| normal[axis] != 0.0).unwrap();
+            let face_index = axis * 2 + (normal[axis] > 0.0) as usize;
+
+            let mut center = [0.0; 3];
+            let mut min_uv = [1.0f32; 2];
+            for vert in face.iter() {
+                for (c, p) in center.iter_mut().zip(vert.position.iter()) {
+                    *c += p / 4.0;
+                }
+                min_uv = [min_uv[0].min(vert.uv[0]), min_uv[1].min(vert.uv[1])];
+            }
+
+            // the block the face belongs to and the block it looks into
+            let mut back = [0; 3];
+            let mut front = [0; 3];
+            for (axis, (c, n)) in center.iter().zip(normal.iter()).enumerate() {
+                back[axis] = (c - n * 0.5).floor() as usize;
+                front[axis] = (c + n * 0.5).floor() as i32;
+            }
+            let layer = match self.blocks[back[0]][back[1]][back[2]] {
+                Some(block) => texture_layers.of(block.block_type).faces()[face_index],
+                None => unreachable!("faces always belong to a block of the chunk"),
+            };
+            let (_, light) = self.get_block_and_light(front, chunk_neighbors);
+
+            for vert in face.iter() {
+                // the blocks next to the corner in front of the face, see
+                // https://0fps.net/2013/07/03/ambient-occlusion-for-minecraft-like-worlds/
+                let mut sides = [front; 2];
+                let mut corner = front;
+                for (side, i) in (0..3).filter(|&i| i != axis).enumerate() {
+                    let step = if vert.position[i] > center[i] { 1 } else { -1 };
+                    sides[side][i] += step;
+                    corner[i] += step;
+                }
+
+                let ao = match (occludes(sides[0]), occludes(sides[1])) {
+                    (true, true) => 0,
+                    (a, b) => 3 - a as u32 - b as u32 - occludes(corner) as u32,
+                };
+
+                packed.push(ChunkVertex::new(
+                    [
+                        vert.position[0] as u32 * scale,
+                        vert.position[1] as u32 * scale,
+                        vert.position[2] as u32 * scale,
+                    ],
+                    face_index as u32,
+                    [
+                        (vert.uv[0] > min_uv[0]) as u32,
+                        (vert.uv[1] > min_uv[1]) as u32,
+                    ],
+                    layer,
+                    ao,
+                    light as u32,
+                ));
+            }
+        }
+
+        packed
+    }
+
+    /// builds the faces of the chunk that aren't hidden by other blocks,
+    /// in the chunk's local coordinates. block_includer should return true
+    /// when a block should be included in the mesh
+    pub fn generate_chunkmesh_geometry<F: Fn(&BlockType) -> bool>(
+        &self,
+        block_includer: F,
+        chunk_neighbors: ChunkNeighbours,
+    ) -> (Vec<Vertex>, Vec<u32>) {
+        let texture_layers = TextureLayers::from_block_types(block_types());
         let mut verts: Vec<Vertex> = vec![];
         let mut indices: Vec<u32> = vec![];
 
@@ -410,14 +738,14 @@ impl Chunk {
                             let neighbors =
                                 self.get_block_neighbors(block.in_chunk_position, chunk_neighbors);
 
-                            let uv = block.block_type.uv;
+                            let layers = texture_layers.of(block.block_type);
                             if Self::add_face(&block, &neighbors.right) {
                                 let xcoord = (block.in_chunk_position[0] + 1) as f32;
                                 let ybase = block.in_chunk_position[1] as f32;
                                 let zbase = block.in_chunk_position[2] as f32;
 
                                 let n = [1.0, 0.0, 0.0];
-                                let uv = uv.right;
+                                let uv = atlas_uv(layers.right);
 
                                 let face_verts = &[
                                     Vertex {
@@ -456,7 +784,7 @@ impl Chunk {
                                 let zbase = block.in_chunk_position[2] as f32;
 
                                 let n = [-1.0, 0.0, 0.0];
-                                let uv = uv.left;
+                                let uv = atlas_uv(layers.left);
 
                                 let face_verts = &[
                                     Vertex {
@@ -495,7 +823,7 @@ impl Chunk {
                                 let zbase = block.in_chunk_position[2] as f32;
 
                                 let n = [0.0, 1.0, 0.0];
-                                let uv = uv.top;
+                                let uv = atlas_uv(layers.top);
 
                                 let face_verts = &[
                                     Vertex {
@@ -534,7 +862,7 @@ impl Chunk {
                                 let zbase = block.in_chunk_position[2] as f32;
 
                                 let n = [0.0, -1.0, 0.0];
-                                let uv = uv.bottom;
+                                let uv = atlas_uv(layers.bottom);
 
                                 let face_verts = &[
                                     Vertex {
@@ -573,7 +901,7 @@ impl Chunk {
                                 let zcoord = (block.in_chunk_position[2] + 1) as f32;
 
                                 let n = [0.0, 0.0, 1.0];
-                                let uv = uv.front;
+                                let uv = atlas_uv(layers.front);
 
                                 let face_verts = &[
                                     Vertex {
@@ -612,7 +940,7 @@ impl Chunk {
                                 let zcoord = block.in_chunk_position[2] as f32;
 
                                 let n = [0.0, 0.0, -1.0];
-                                let uv = uv.back;
+                                let uv = atlas_uv(layers.back);
 
                                 let face_verts = &[
                                     Vertex {
@@ -651,35 +979,16 @@ impl Chunk {
         }
 
         debug!("mesh contains {} faces", indices.len() / 3);
-        if verts.len() == 0 {
-            ChunkMesh {
-                mesh: None,
-                dirty: false,
-            }
-        } else {
-            let verts = glium::vertex::VertexBuffer::new(display, &verts)
-                .expect("failed to create vertex buffer");
-            let indices =
-                glium::index::IndexBuffer::new(display, PrimitiveType::TrianglesList, &indices)
-                    .expect("failed to create index buffer");
-
-            ChunkMesh {
-                mesh: Some(Mesh {
-                    vertices: verts,
-                    indices: indices,
-                }),
-                dirty: false,
-            }
-        }
+        (verts, indices)
     }
 
     /// computes the chunk and block inside that chunk that a global
     /// coordinate corresponds to
     pub fn get_local_coord_from_world_coord(coord: [i32; 3]) -> ([i32; 3], [u8; 3]) {
         let inchunk = [
-            (coord[0] % CHUNK_SIZE_I32) as u8,
-            (coord[1] % CHUNK_SIZE_I32) as u8,
-            (coord[2] % CHUNK_SIZE_I32) as u8,
+            coord[0].rem_euclid(CHUNK_SIZE_I32) as u8,
+            coord[1].rem_euclid(CHUNK_SIZE_I32) as u8,
+            coord[2].rem_euclid(CHUNK_SIZE_I32) as u8,
         ];
 
         let chunk = [
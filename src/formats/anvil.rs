@@ -0,0 +1,307 @@
+//! importing terrain from minecraft's anvil region (`.mca`) files
+//!
+//! a region file starts with a table of 1024 chunk locations, each chunk
+//! is a compressed nbt compound split into 16x16x16 sections. the blocks
+//! of a section are stored as a palette of block states and a long array
+//! of palette indices packed into as few bits as possible. both the 1.18+
+//! layout and the older 1.13 to 1.17 one are understood, worlds from
+//! before 1.13 used numeric block ids and are not supported.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use log::*;
+
+use super::block_names::BlockNameTable;
+use super::nbt::{self, Tag};
+use crate::block::BlockType;
+use crate::chunk::{Chunk, CHUNK_SIZE_I32};
+use crate::volume::Volume;
+
+const SECTOR_SIZE: usize = 4096;
+const REGION_CHUNKS: i32 = 32;
+const SECTION_SIZE: usize = 16;
+const SECTION_VOLUME: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+
+// starting with this data version (1.16) packed indices no
+// longer span across two longs
+const NON_SPANNING_DATA_VERSION: i64 = 2529;
+
+// starting with this data version (1.18) worlds go down to y = -64
+// instead of starting at y = 0
+const DEEPER_WORLD_DATA_VERSION: i64 = 2825;
+
+pub struct Section {
+    /// the position of the section in units of sections
+    pub position: [i32; 3],
+    /// the y of the lowest block of the world the section is from
+    pub world_bottom: i32,
+    /// the blocks of the section, ordered like a `Volume`
+    pub blocks: Vec<Option<&'static BlockType>>,
+}
+
+/// reads the nbt of every chunk stored in a region file, chunks
+/// that can't be read are skipped with a warning
+pub fn read_region<P: AsRef<Path>>(path: P) -> Result<Vec<Tag>, Box<dyn Error>> {
+    let bytes = fs::read(path.as_ref())?;
+    if bytes.len() < 2 * SECTOR_SIZE {
+        return Err("region file is shorter than its header".into());
+    }
+
+    let mut chunks = vec![];
+
+    for slot in 0..1024 {
+        let entry = &bytes[slot * 4..slot * 4 + 4];
+        let offset = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as usize * SECTOR_SIZE;
+        if offset == 0 {
+            continue; // the chunk was never generated
+        }
+
+        match read_chunk(&bytes, offset) {
+            Ok(tag) => chunks.push(tag),
+            Err(e) => warn!(
+                "skipping chunk {} of {}: {}",
+                slot,
+                path.as_ref().display(),
+                e
+            ),
+        }
+    }
+
+    Ok(chunks)
+}
+
+fn read_chunk(bytes: &[u8], offset: usize) -> Result<Tag, Box<dyn Error>> {
+    let header = bytes
+        .get(offset..offset + 5)
+        .ok_or("chunk lies past the end of the file")?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if length == 0 {
+        return Err("chunk is empty".into());
+    }
+
+    // the length includes the compression type byte
+    let data = bytes
+        .get(offset + 5..offset + 4 + length)
+        .ok_or("chunk is truncated")?;
+
+    match header[4] {
+        // gzip, zlib and uncompressed are told apart by read_compressed
+        1..=3 => Ok(nbt::read_compressed(data)?.1),
+        4 => Err("lz4 compressed chunks are not supported".into()),
+        c if c >= 128 => Err("chunks stored in external files are not supported".into()),
+        c => Err(format!("unknown compression type {}", c).into()),
+    }
+}
+
+/// unpacks palette indices stored in longs, `spanning` indices continue
+/// in the next long when they don't fit into the current one
+fn unpack_indices(data: &[i64], bits: usize, count: usize, spanning: bool) -> Vec<usize> {
+    let mask = (1u64 << bits) - 1;
+    let mut indices = Vec::with_capacity(count);
+
+    if spanning {
+        for i in 0..count {
+            let bit = i * bits;
+            let (long, shift) = (bit / 64, bit % 64);
+
+            let mut value = data.get(long).map_or(0, |&l| l as u64) >> shift;
+            if shift + bits > 64 {
+                value |= data.get(long + 1).map_or(0, |&l| l as u64) << (64 - shift);
+            }
+            indices.push((value & mask) as usize);
+        }
+    } else {
+        let per_long = 64 / bits;
+        for i in 0..count {
+            let long = data.get(i / per_long).map_or(0, |&l| l as u64);
+            indices.push(((long >> ((i % per_long) * bits)) & mask) as usize);
+        }
+    }
+
+    indices
+}
+
+/// the number of bits each index takes up, block states never use less than 4
+fn bits_per_index(palette_len: usize) -> usize {
+    let mut bits = 4;
+    while (1 << bits) < palette_len {
+        bits += 1;
+    }
+    bits
+}
+
+fn read_section(
+    palette: &[Tag],
+    data: Option<&[i64]>,
+    spanning: bool,
+    table: &BlockNameTable,
+) -> Result<Vec<Option<&'static BlockType>>, Box<dyn Error>> {
+    let mut block_types = vec![];
+    for state in palette.iter() {
+        let name = state
+            .get("Name")
+            .and_then(|n| n.as_str())
+            .ok_or("palette entry has no name")?;
+        block_types.push(table.to_internal(name));
+    }
+
+    if block_types.is_empty() {
+        return Err("section has an empty palette".into());
+    }
+
+    // sections with a single block state don't need any data
+    let indices = match data {
+        Some(data) if block_types.len() > 1 => unpack_indices(
+            data,
+            bits_per_index(block_types.len()),
+            SECTION_VOLUME,
+            spanning,
+        ),
+        _ => vec![0; SECTION_VOLUME],
+    };
+
+    let mut blocks = Vec::with_capacity(SECTION_VOLUME);
+    for index in indices {
+        match block_types.get(index) {
+            Some(&block_type) => blocks.push(block_type),
+            None => return Err(format!("unknown palette index {}", index).into()),
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// converts the sections of a chunk into our block types, sections
+/// that only hold lighting or are unreadable are skipped
+pub fn read_sections(chunk: &Tag, table: &BlockNameTable) -> Result<Vec<Section>, Box<dyn Error>> {
+    let data_version = chunk
+        .get("DataVersion")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(0);
+    let spanning = data_version < NON_SPANNING_DATA_VERSION;
+    let world_bottom = match data_version >= DEEPER_WORLD_DATA_VERSION {
+        true => -64,
+        false => 0,
+    };
+
+    // before 1.18 everything was wrapped in a "Level" compound
+    let (level, sections) = match chunk.get("sections") {
+        Some(sections) => (chunk, sections),
+        None => {
+            let level = chunk.get("Level").ok_or("chunk has no sections")?;
+            (level, level.get("Sections").ok_or("chunk has no sections")?)
+        }
+    };
+
+    let position = |name: &str| -> Result<i32, Box<dyn Error>> {
+        match level.get(name).and_then(|v| v.as_i64()) {
+            Some(v) => Ok(v as i32),
+            None => Err(format!("chunk has no \"{}\"", name).into()),
+        }
+    };
+    let (x, z) = (position("xPos")?, position("zPos")?);
+
+    let mut converted = vec![];
+
+    for section in sections.as_list().ok_or("sections are not a list")? {
+        let y = match section.get("Y").and_then(|y| y.as_i64()) {
+            Some(y) => y as i32,
+            None => continue,
+        };
+
+        let (palette, data) = match section.get("block_states") {
+            Some(states) => (states.get("palette"), states.get("data")),
+            None => (section.get("Palette"), section.get("BlockStates")),
+        };
+
+        let palette = match palette.and_then(|p| p.as_list()) {
+            Some(palette) => palette,
+            None => continue, // lighting only
+        };
+
+        match read_section(
+            palette,
+            data.and_then(|d| d.as_long_array()),
+            spanning,
+            table,
+        ) {
+            Ok(blocks) => converted.push(Section {
+                position: [x, y, z],
+                world_bottom: world_bottom,
+                blocks: blocks,
+            }),
+            Err(e) => warn!("skipping section {} of chunk {:?}: {}", y, [x, z], e),
+        }
+    }
+
+    Ok(converted)
+}
+
+/// copies the terrain in the region files of a minecraft world into the
+/// chunks. `origin` is the minecraft block on the x and z axes that ends
+/// up at 0, 0, the lowest block of the minecraft world ends up at y = 0.
+/// blocks that land outside of the chunks are dropped.
+///
+/// returns the number of sections that were imported
+pub fn import_regions<P: AsRef<Path>>(
+    region_dir: P,
+    chunks: &mut [Chunk],
+    origin: [i32; 2],
+    table: &BlockNameTable,
+) -> Result<usize, Box<dyn Error>> {
+    // minecraft blocks covered by the chunks, on the x and z axes
+    let mut min = [i32::MAX; 2];
+    let mut max = [i32::MIN; 2];
+    for chunk in chunks.iter() {
+        for (i, axis) in [0, 2].iter().enumerate() {
+            let start = chunk.coordinates[*axis] * CHUNK_SIZE_I32 + origin[i];
+            min[i] = min[i].min(start);
+            max[i] = max[i].max(start + CHUNK_SIZE_I32);
+        }
+    }
+
+    let region_blocks = REGION_CHUNKS * SECTION_SIZE as i32;
+    let mut imported = 0;
+
+    for region_x in min[0].div_euclid(region_blocks)..=(max[0] - 1).div_euclid(region_blocks) {
+        for region_z in min[1].div_euclid(region_blocks)..=(max[1] - 1).div_euclid(region_blocks) {
+            let path = region_dir
+                .as_ref()
+                .join(format!("r.{}.{}.mca", region_x, region_z));
+            if !path.is_file() {
+                continue;
+            }
+
+            info!("importing {}", path.display());
+
+            for chunk in read_region(&path)? {
+                let sections = match read_sections(&chunk, table) {
+                    Ok(sections) => sections,
+                    Err(e) => {
+                        warn!("skipping chunk in {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+
+                for section in sections {
+                    let volume = Volume {
+                        size: [SECTION_SIZE; 3],
+                        blocks: section.blocks,
+                    };
+                    let section_origin = [
+                        section.position[0] * SECTION_SIZE as i32 - origin[0],
+                        section.position[1] * SECTION_SIZE as i32 - section.world_bottom,
+                        section.position[2] * SECTION_SIZE as i32 - origin[1],
+                    ];
+
+                    volume.paste_into_chunks(chunks, section_origin, true);
+                    imported += 1;
+                }
+            }
+        }
+    }
+
+    Ok(imported)
+}
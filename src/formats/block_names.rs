@@ -0,0 +1,170 @@
+//! translating between minecraft block names and our block types
+//!
+//! a table is a text file with one `external name = block type` mapping
+//! per line, where the block type is the name of one of our block types
+//! or `air`. block states in external names, like the `[snowy=false]` in
+//! `minecraft:grass_block[snowy=false]`, are ignored when looking names
+//! up unless the table has an entry for the full name. the first mapping
+//! of a block type is the name it gets exported as. a `* = block type`
+//! line sets the block that unknown names turn into, without one they
+//! turn into air. lines starting with `#` are comments.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use log::*;
+
+use crate::block::types::*;
+use crate::block::BlockType;
+use crate::resource_pack::ResourcePacks;
+
+/// where the resource packs keep a table that replaces the default one
+pub const BLOCK_NAMES_FILE: &str = "block_names.txt";
+
+const DEFAULT_TABLE: &str = "
+minecraft:air = air
+minecraft:cave_air = air
+minecraft:void_air = air
+minecraft:grass_block = grass block
+minecraft:dirt = dirt block
+minecraft:coarse_dirt = dirt block
+minecraft:stone = stone block
+minecraft:glass = glass block
+* = stone block
+";
+
+pub struct BlockNameTable {
+    to_internal: HashMap<String, Option<&'static BlockType>>,
+    to_external: Vec<(Option<&'static BlockType>, String)>,
+    fallback: Option<&'static BlockType>,
+}
+
+fn parse_block_type(name: &str) -> Result<Option<&'static BlockType>, Box<dyn Error>> {
+    if name == "air" {
+        return Ok(None);
+    }
+
+    match block_type_by_name(name) {
+        Some(block_type) => Ok(Some(block_type)),
+        None => Err(format!("unknown block type \"{}\"", name).into()),
+    }
+}
+
+fn same_block(a: Option<&BlockType>, b: Option<&BlockType>) -> bool {
+    a.map(|t| t.name) == b.map(|t| t.name)
+}
+
+impl BlockNameTable {
+    pub fn parse(table: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = BlockNameTable {
+            to_internal: HashMap::new(),
+            to_external: vec![],
+            fallback: None,
+        };
+
+        for (number, line) in table.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let (external, internal) = match (parts.next(), parts.next()) {
+                (Some(external), Some(internal)) => (external.trim(), internal.trim()),
+                _ => {
+                    return Err(format!("line {}: expected `name = block type`", number + 1).into())
+                }
+            };
+
+            let block_type =
+                parse_block_type(internal).map_err(|e| format!("line {}: {}", number + 1, e))?;
+
+            if external == "*" {
+                parsed.fallback = block_type;
+                continue;
+            }
+
+            if !parsed
+                .to_external
+                .iter()
+                .any(|(t, _)| same_block(*t, block_type))
+            {
+                parsed.to_external.push((block_type, external.to_string()));
+            }
+            parsed.to_internal.insert(external.to_string(), block_type);
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// loads the table from the resource packs, falling back to
+    /// the default table when there is none or it can't be parsed.
+    /// only the names come from the packs, the block types don't
+    pub fn load_or_default(packs: &ResourcePacks) -> Self {
+        let table = match packs.read_to_string(BLOCK_NAMES_FILE) {
+            Some(table) => table,
+            None => {
+                debug!(
+                    "no resource pack has {}, using the default block names",
+                    BLOCK_NAMES_FILE
+                );
+                return Self::default();
+            }
+        };
+
+        match Self::parse(&table) {
+            Ok(table) => table,
+            Err(e) => {
+                error!(
+                    "using the default block names instead of {}: {}",
+                    BLOCK_NAMES_FILE, e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    /// looks up the block type an external name corresponds to,
+    /// None means air
+    pub fn to_internal(&self, external: &str) -> Option<&'static BlockType> {
+        if let Some(&block_type) = self.to_internal.get(external) {
+            return block_type;
+        }
+
+        let without_state = external.split('[').next().unwrap_or(external);
+        if let Some(&block_type) = self.to_internal.get(without_state) {
+            return block_type;
+        }
+
+        debug!("unknown block \"{}\", using the fallback", external);
+        self.fallback
+    }
+
+    /// the name a block type is exported as, block types that aren't in
+    /// the table get a name derived from their own name
+    pub fn to_external(&self, block_type: Option<&BlockType>) -> String {
+        match self
+            .to_external
+            .iter()
+            .find(|(t, _)| same_block(*t, block_type))
+        {
+            Some((_, name)) => name.clone(),
+            None => match block_type {
+                Some(block_type) => format!("rmc1:{}", block_type.name.replace(' ', "_")),
+                None => "minecraft:air".to_string(),
+            },
+        }
+    }
+}
+
+impl Default for BlockNameTable {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TABLE).unwrap()
+    }
+}
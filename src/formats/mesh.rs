@@ -0,0 +1,329 @@
+//! exporting the world as a textured mesh, either as a wavefront
+//! obj with its material library or as a binary gltf (`.glb`)
+//!
+//! the meshes are built with the same face culling as the chunkmeshes
+//! the game renders and use the uv coordinates of the texture atlas, which
+//! the exported files reference by name instead of embedding it
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use log::*;
+
+use crate::chunk::*;
+use crate::graphics::Vertex;
+
+// the gltf constants for the data types and filters used below
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_NEAREST: u32 = 9728;
+
+pub struct ExportMesh {
+    /// vertices in world coordinates
+    pub vertices: Vec<Vertex>,
+    pub opaque_indices: Vec<u32>,
+    pub transparent_indices: Vec<u32>,
+}
+
+fn transform(matrix: &[[f32; 4]; 4], position: [f32; 3]) -> [f32; 3] {
+    let mut transformed = [0.0; 3];
+    for (row, value) in transformed.iter_mut().enumerate() {
+        *value = matrix[0][row] * position[0]
+            + matrix[1][row] * position[1]
+            + matrix[2][row] * position[2]
+            + matrix[3][row];
+    }
+    transformed
+}
+
+/// copies the blocks of the chunks that lie inside the box starting at
+/// `origin`, so that the faces at the edge of the box aren't culled by
+/// blocks outside of it
+pub fn clip_chunks(chunks: &[Chunk], origin: [i32; 3], size: [usize; 3]) -> Vec<Chunk> {
+    let mut clipped = vec![];
+
+    for chunk in chunks.iter() {
+        let overlaps = (0..3).all(|axis| {
+            let start = chunk.coordinates[axis] * CHUNK_SIZE_I32;
+            start < origin[axis] + size[axis] as i32 && origin[axis] < start + CHUNK_SIZE_I32
+        });
+        if !overlaps {
+            continue;
+        }
+
+        let mut copy = Chunk::empty(chunk.coordinates);
+        let mut any_inside = false;
+
+        for x in 0..CHUNK_SIZE_U8 {
+            for y in 0..CHUNK_SIZE_U8 {
+                for z in 0..CHUNK_SIZE_U8 {
+                    let global =
+                        Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, y, z]);
+
+                    let inside = (0..3).all(|axis| {
+                        global[axis] >= origin[axis]
+                            && global[axis] < origin[axis] + size[axis] as i32
+                    });
+
+                    if inside {
+                        any_inside = true;
+                        *copy.get_block_mut([x, y, z]) = *chunk.get_block([x, y, z]);
+                    }
+                }
+            }
+        }
+
+        if any_inside {
+            clipped.push(copy);
+        }
+    }
+
+    clipped
+}
+
+impl ExportMesh {
+    /// meshes every chunk, chunks are culled against each other
+    /// but not against chunks that aren't in the slice
+    pub fn from_chunks(chunks: &[Chunk]) -> Self {
+        let mut mesh = ExportMesh {
+            vertices: vec![],
+            opaque_indices: vec![],
+            transparent_indices: vec![],
+        };
+
+        for chunk in chunks.iter() {
+            let neighbours = get_chunk_neighbours(chunks, chunk.coordinates);
+            let translation = chunk.get_translation_matrix();
+
+            let opaque = chunk.generate_chunkmesh_geometry(|t| !t.transparent, neighbours);
+            let transparent = chunk.generate_chunkmesh_geometry(|t| t.transparent, neighbours);
+
+            for ((vertices, indices), target) in [
+                (opaque, &mut mesh.opaque_indices),
+                (transparent, &mut mesh.transparent_indices),
+            ] {
+                let offset = mesh.vertices.len() as u32;
+
+                mesh.vertices.extend(vertices.into_iter().map(|v| Vertex {
+                    position: transform(&translation, v.position),
+                    ..v
+                }));
+                target.extend(indices.into_iter().map(|i| i + offset));
+            }
+        }
+
+        debug!(
+            "exported mesh contains {} faces",
+            (mesh.opaque_indices.len() + mesh.transparent_indices.len()) / 6
+        );
+
+        mesh
+    }
+
+    /// writes the mesh as an obj file and a material library with the same
+    /// name next to it, `texture` is the file name of the texture atlas
+    pub fn write_obj<P: AsRef<Path>>(&self, path: P, texture: &str) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let mtl_path = path.with_extension("mtl");
+        let mtl_name = mtl_path
+            .file_name()
+            .ok_or("the obj path has no file name")?
+            .to_string_lossy();
+
+        let mut mtl = String::new();
+        for (name, transparent) in [("blocks", false), ("transparent_blocks", true)] {
+            writeln!(mtl, "newmtl {}", name)?;
+            writeln!(mtl, "Kd 1 1 1")?;
+            writeln!(mtl, "map_Kd {}", texture)?;
+            if transparent {
+                writeln!(mtl, "map_d {}", texture)?;
+            }
+            writeln!(mtl)?;
+        }
+
+        let mut obj = String::new();
+        writeln!(obj, "mtllib {}", mtl_name)?;
+        writeln!(obj, "o world")?;
+
+        for v in self.vertices.iter() {
+            writeln!(
+                obj,
+                "v {} {} {}",
+                v.position[0], v.position[1], v.position[2]
+            )?;
+        }
+        for v in self.vertices.iter() {
+            writeln!(obj, "vt {} {}", v.uv[0], v.uv[1])?;
+        }
+        for v in self.vertices.iter() {
+            writeln!(obj, "vn {} {} {}", v.normal[0], v.normal[1], v.normal[2])?;
+        }
+
+        for (name, indices) in [
+            ("blocks", &self.opaque_indices),
+            ("transparent_blocks", &self.transparent_indices),
+        ] {
+            if indices.is_empty() {
+                continue;
+            }
+
+            writeln!(obj, "usemtl {}", name)?;
+            for triangle in indices.chunks_exact(3) {
+                // obj indices start at 1 and every vertex has its own
+                // position, uv and normal
+                let (a, b, c) = (triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
+                writeln!(obj, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+            }
+        }
+
+        fs::write(&mtl_path, mtl)?;
+        fs::write(path, obj)?;
+        Ok(())
+    }
+
+    /// writes the mesh as a binary gltf, `texture` is the file
+    /// name of the texture atlas
+    pub fn write_glb<P: AsRef<Path>>(&self, path: P, texture: &str) -> Result<(), Box<dyn Error>> {
+        if self.vertices.is_empty() {
+            return Err("there are no blocks to export".into());
+        }
+
+        let mut bin = vec![];
+        let mut buffer_views = vec![];
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in self.vertices.iter() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(v.position[axis]);
+                max[axis] = max[axis].max(v.position[axis]);
+            }
+        }
+
+        // gltf puts the origin of texture coordinates in the
+        // top left instead of the bottom left
+        let attributes: [Vec<f32>; 3] = [
+            self.vertices.iter().flat_map(|v| v.position).collect(),
+            self.vertices.iter().flat_map(|v| v.normal).collect(),
+            self.vertices
+                .iter()
+                .flat_map(|v| [v.uv[0], 1.0 - v.uv[1]])
+                .collect(),
+        ];
+        for attribute in attributes.iter() {
+            buffer_views.push(format!(
+                "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+                bin.len(),
+                attribute.len() * 4,
+                GLTF_ARRAY_BUFFER
+            ));
+            bin.extend(attribute.iter().flat_map(|f| f.to_le_bytes()));
+        }
+
+        let count = self.vertices.len();
+        let mut accessors = vec![
+            format!(
+                "{{\"bufferView\":0,\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+                GLTF_FLOAT, count, min[0], min[1], min[2], max[0], max[1], max[2]
+            ),
+            format!(
+                "{{\"bufferView\":1,\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}}",
+                GLTF_FLOAT, count
+            ),
+            format!(
+                "{{\"bufferView\":2,\"componentType\":{},\"count\":{},\"type\":\"VEC2\"}}",
+                GLTF_FLOAT, count
+            ),
+        ];
+
+        // empty buffer views and primitives aren't allowed,
+        // so only the materials with faces get written
+        let mut primitives = vec![];
+        for (material, indices) in [(0, &self.opaque_indices), (1, &self.transparent_indices)] {
+            if indices.is_empty() {
+                continue;
+            }
+
+            buffer_views.push(format!(
+                "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+                bin.len(),
+                indices.len() * 4,
+                GLTF_ELEMENT_ARRAY_BUFFER
+            ));
+            bin.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+
+            accessors.push(format!(
+                "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+                buffer_views.len() - 1,
+                GLTF_UNSIGNED_INT,
+                indices.len()
+            ));
+            primitives.push(format!(
+                "{{\"attributes\":{{\"POSITION\":0,\"NORMAL\":1,\"TEXCOORD_0\":2}},\"indices\":{},\"material\":{}}}",
+                accessors.len() - 1,
+                material
+            ));
+        }
+
+        // the faces of chunkmeshes aren't wound consistently since the
+        // game doesn't cull back faces, so the materials are double sided
+        let material = |name: &str, alpha_mode: &str| {
+            format!(
+                "{{\"name\":\"{}\",\"pbrMetallicRoughness\":{{\"baseColorTexture\":{{\"index\":0}},\"metallicFactor\":0,\"roughnessFactor\":1}},\"alphaMode\":\"{}\",\"doubleSided\":true}}",
+                name, alpha_mode
+            )
+        };
+
+        let json = format!(
+            concat!(
+                "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"rmc1\"}},",
+                "\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],",
+                "\"nodes\":[{{\"name\":\"world\",\"mesh\":0}}],",
+                "\"meshes\":[{{\"primitives\":[{}]}}],",
+                "\"materials\":[{},{}],",
+                "\"textures\":[{{\"source\":0,\"sampler\":0}}],",
+                "\"images\":[{{\"uri\":\"{}\"}}],",
+                "\"samplers\":[{{\"magFilter\":{},\"minFilter\":{}}}],",
+                "\"accessors\":[{}],",
+                "\"bufferViews\":[{}],",
+                "\"buffers\":[{{\"byteLength\":{}}}]}}"
+            ),
+            primitives.join(","),
+            material("blocks", "OPAQUE"),
+            material("transparent_blocks", "BLEND"),
+            texture.replace('\\', "\\\\").replace('"', "\\\""),
+            GLTF_NEAREST,
+            GLTF_NEAREST,
+            accessors.join(","),
+            buffer_views.join(","),
+            bin.len()
+        );
+
+        // both chunks have to be padded to a multiple of 4 bytes
+        let mut json = json.into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let mut glb = vec![];
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+
+        fs::write(path, glb)?;
+        Ok(())
+    }
+}
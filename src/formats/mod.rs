@@ -0,0 +1,10 @@
+//! reading and writing files shared with other voxel games and tools
+
+pub mod anvil;
+pub mod block_names;
+pub mod mesh;
+pub mod nbt;
+pub mod schematic;
+pub mod triangle_mesh;
+pub mod vox;
+pub mod zip;
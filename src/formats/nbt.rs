@@ -0,0 +1,316 @@
+//! reading and writing minecraft's named binary tag format
+//!
+//! all numbers are big endian and strings are treated as plain utf-8,
+//! which is what the modified utf-8 of the format boils down to for
+//! everything but unusual unicode characters.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// compounds and lists nested deeper than this are rejected
+// so that malicious files can't overflow the stack
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// looks up a child of a compound tag
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// returns any integer tag widened to an i64
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Tag>> {
+        match self {
+            Tag::Compound(children) => Some(children),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_length<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let length = i32::from_be_bytes(read_array(reader)?);
+    if length < 0 {
+        return Err(invalid_data("negative length"));
+    }
+    Ok(length as usize)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let length = u16::from_be_bytes(read_array(reader)?) as usize;
+    let mut buf = vec![0; length];
+    reader.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// reads a number of fixed size elements, without trusting the length
+/// enough to allocate everything up front
+fn read_elements<R: Read, T, F: Fn(&[u8]) -> T>(
+    reader: &mut R,
+    element_size: usize,
+    convert: F,
+) -> io::Result<Vec<T>> {
+    let length = read_length(reader)?;
+    let mut bytes = vec![];
+    reader
+        .take((length * element_size) as u64)
+        .read_to_end(&mut bytes)?;
+
+    if bytes.len() != length * element_size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "array is truncated",
+        ));
+    }
+
+    Ok(bytes.chunks_exact(element_size).map(convert).collect())
+}
+
+fn read_payload<R: Read>(reader: &mut R, id: u8, depth: usize) -> io::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("tags are nested too deeply"));
+    }
+
+    Ok(match id {
+        1 => Tag::Byte(i8::from_be_bytes(read_array(reader)?)),
+        2 => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+        3 => Tag::Int(i32::from_be_bytes(read_array(reader)?)),
+        4 => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+        5 => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+        6 => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+        7 => Tag::ByteArray(read_elements(reader, 1, |b| b[0])?),
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let element_id = read_array::<_, 1>(reader)?[0];
+            let length = read_length(reader)?;
+
+            if element_id == 0 && length > 0 {
+                return Err(invalid_data("list of end tags"));
+            }
+
+            let mut elements = vec![];
+            for _ in 0..length {
+                elements.push(read_payload(reader, element_id, depth + 1)?);
+            }
+            Tag::List(elements)
+        }
+        10 => {
+            let mut children = BTreeMap::new();
+            loop {
+                let child_id = read_array::<_, 1>(reader)?[0];
+                if child_id == 0 {
+                    break;
+                }
+
+                let name = read_string(reader)?;
+                children.insert(name, read_payload(reader, child_id, depth + 1)?);
+            }
+            Tag::Compound(children)
+        }
+        11 => Tag::IntArray(read_elements(reader, 4, |b| {
+            i32::from_be_bytes(b.try_into().unwrap())
+        })?),
+        12 => Tag::LongArray(read_elements(reader, 8, |b| {
+            i64::from_be_bytes(b.try_into().unwrap())
+        })?),
+        _ => return Err(invalid_data(&format!("unknown tag type {}", id))),
+    })
+}
+
+/// reads an uncompressed named root tag
+pub fn read<R: Read>(reader: &mut R) -> io::Result<(String, Tag)> {
+    let id = read_array::<_, 1>(reader)?[0];
+    if id == 0 {
+        return Err(invalid_data("root tag is an end tag"));
+    }
+
+    let name = read_string(reader)?;
+    Ok((name, read_payload(reader, id, 0)?))
+}
+
+/// reads a named root tag that is gzip compressed, zlib
+/// compressed or not compressed at all
+pub fn read_compressed(bytes: &[u8]) -> io::Result<(String, Tag)> {
+    match bytes {
+        [0x1f, 0x8b, ..] => read(&mut GzDecoder::new(bytes)),
+        [0x78, ..] => read(&mut ZlibDecoder::new(bytes)),
+        _ => read(&mut &bytes[..]),
+    }
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    if string.len() > u16::MAX as usize {
+        return Err(invalid_data("string is too long"));
+    }
+
+    writer.write_all(&(string.len() as u16).to_be_bytes())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn write_length<W: Write>(writer: &mut W, length: usize) -> io::Result<()> {
+    if length > i32::MAX as usize {
+        return Err(invalid_data("array is too long"));
+    }
+
+    writer.write_all(&(length as i32).to_be_bytes())
+}
+
+fn write_payload<W: Write>(writer: &mut W, tag: &Tag) -> io::Result<()> {
+    match tag {
+        Tag::Byte(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Short(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Int(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Long(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Float(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::Double(v) => writer.write_all(&v.to_be_bytes()),
+        Tag::ByteArray(bytes) => {
+            write_length(writer, bytes.len())?;
+            writer.write_all(bytes)
+        }
+        Tag::String(s) => write_string(writer, s),
+        Tag::List(elements) => {
+            let element_id = elements.first().map(|e| e.id()).unwrap_or(0);
+            if elements.iter().any(|e| e.id() != element_id) {
+                return Err(invalid_data("list elements have different types"));
+            }
+
+            writer.write_all(&[element_id])?;
+            write_length(writer, elements.len())?;
+            for element in elements.iter() {
+                write_payload(writer, element)?;
+            }
+            Ok(())
+        }
+        Tag::Compound(children) => {
+            for (name, child) in children.iter() {
+                writer.write_all(&[child.id()])?;
+                write_string(writer, name)?;
+                write_payload(writer, child)?;
+            }
+            writer.write_all(&[0])
+        }
+        Tag::IntArray(values) => {
+            write_length(writer, values.len())?;
+            for v in values.iter() {
+                writer.write_all(&v.to_be_bytes())?;
+            }
+            Ok(())
+        }
+        Tag::LongArray(values) => {
+            write_length(writer, values.len())?;
+            for v in values.iter() {
+                writer.write_all(&v.to_be_bytes())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// writes an uncompressed named root tag
+pub fn write<W: Write>(writer: &mut W, name: &str, tag: &Tag) -> io::Result<()> {
+    writer.write_all(&[tag.id()])?;
+    write_string(writer, name)?;
+    write_payload(writer, tag)
+}
+
+/// writes a gzip compressed named root tag, which is what
+/// most nbt files on disk look like
+pub fn write_gzip(name: &str, tag: &Tag) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    write(&mut encoder, name, tag)?;
+    encoder.finish()
+}
@@ -0,0 +1,242 @@
+//! sponge schematic (`.schem`) files, versions 1 to 3 can be read,
+//! versions 2 and 3 can be written. block entities, entities and
+//! biomes are not supported and are skipped when reading.
+//!
+//! see https://github.com/SpongePowered/Schematic-Specification
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::block_names::BlockNameTable;
+use super::nbt::{self, Tag};
+use crate::volume::Volume;
+
+// the minecraft versions written into exported files, 1.16.5 for
+// version 2 and 1.20.1 for version 3
+const DATA_VERSION_V2: i32 = 2586;
+const DATA_VERSION_V3: i32 = 3465;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SchematicVersion {
+    V2,
+    V3,
+}
+
+pub struct Schematic {
+    pub volume: Volume,
+    /// where the schematic is placed relative to the position it is pasted at
+    pub offset: [i32; 3],
+}
+
+/// reads the little endian base 128 varints the block data is stored as
+fn decode_varints(bytes: &[u8], count: usize) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut values = Vec::with_capacity(count);
+    let mut value = 0usize;
+    let mut shift = 0;
+
+    for &byte in bytes {
+        if shift > 28 {
+            return Err("varint is too long".into());
+        }
+
+        value |= ((byte & 0x7f) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            values.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+
+    if shift != 0 {
+        return Err("block data ends in the middle of a varint".into());
+    }
+
+    if values.len() != count {
+        return Err(format!("expected {} blocks, found {}", count, values.len()).into());
+    }
+
+    Ok(values)
+}
+
+fn encode_varint(mut value: usize, bytes: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn required<'a>(tag: &'a Tag, name: &str) -> Result<&'a Tag, Box<dyn Error>> {
+    match tag.get(name) {
+        Some(child) => Ok(child),
+        None => Err(format!("schematic is missing \"{}\"", name).into()),
+    }
+}
+
+fn dimension(schematic: &Tag, name: &str) -> Result<usize, Box<dyn Error>> {
+    match required(schematic, name)?.as_i64() {
+        // dimensions are unsigned shorts stored as signed ones
+        Some(v) => Ok(v as u16 as usize),
+        None => Err(format!("\"{}\" is not a number", name).into()),
+    }
+}
+
+impl Schematic {
+    pub fn parse(bytes: &[u8], table: &BlockNameTable) -> Result<Self, Box<dyn Error>> {
+        let (_, root) = nbt::read_compressed(bytes)?;
+
+        // version 3 wraps everything inside of a "Schematic" compound
+        let schematic = match root.get("Schematic") {
+            Some(inner) if inner.as_compound().is_some() => inner,
+            _ => &root,
+        };
+
+        let version = required(schematic, "Version")?
+            .as_i64()
+            .ok_or("\"Version\" is not a number")?;
+
+        let (palette, data) = match version {
+            1 | 2 => (
+                required(schematic, "Palette")?,
+                required(schematic, "BlockData")?,
+            ),
+            3 => {
+                let blocks = required(schematic, "Blocks")?;
+                (required(blocks, "Palette")?, required(blocks, "Data")?)
+            }
+            _ => return Err(format!("unsupported schematic version {}", version).into()),
+        };
+
+        let size = [
+            dimension(schematic, "Width")?,
+            dimension(schematic, "Height")?,
+            dimension(schematic, "Length")?,
+        ];
+
+        let offset = match schematic.get("Offset").and_then(|o| o.as_int_array()) {
+            Some(&[x, y, z]) => [x, y, z],
+            _ => [0, 0, 0],
+        };
+
+        let mut block_types = BTreeMap::new();
+        for (name, index) in palette.as_compound().ok_or("palette is not a compound")? {
+            let index = index.as_i64().ok_or("palette index is not a number")?;
+            block_types.insert(index as usize, table.to_internal(name));
+        }
+
+        let data = data.as_bytes().ok_or("block data is not a byte array")?;
+        let indices = decode_varints(data, size[0] * size[1] * size[2])?;
+
+        let mut volume = Volume::new(size);
+        for (i, index) in indices.into_iter().enumerate() {
+            volume.blocks[i] = match block_types.get(&index) {
+                Some(&block_type) => block_type,
+                None => {
+                    return Err(format!("block data uses unknown palette index {}", index).into())
+                }
+            };
+        }
+
+        Ok(Schematic {
+            volume: volume,
+            offset: offset,
+        })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P, table: &BlockNameTable) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read(path)?, table)
+    }
+
+    /// builds the nbt of the schematic, returns the name of the root tag and the tag
+    pub fn to_nbt(
+        &self,
+        table: &BlockNameTable,
+        version: SchematicVersion,
+    ) -> Result<(String, Tag), Box<dyn Error>> {
+        if self.volume.size.iter().any(|&s| s > u16::MAX as usize) {
+            return Err("schematics can't be larger than 65535 blocks on any axis".into());
+        }
+
+        let mut indices = BTreeMap::new();
+        let mut data = vec![];
+
+        for block_type in self.volume.blocks.iter() {
+            let next_index = indices.len();
+            let index = *indices
+                .entry(table.to_external(*block_type))
+                .or_insert(next_index);
+
+            encode_varint(index, &mut data);
+        }
+
+        let palette: BTreeMap<String, Tag> = indices
+            .into_iter()
+            .map(|(name, index)| (name, Tag::Int(index as i32)))
+            .collect();
+
+        let mut schematic = BTreeMap::new();
+        schematic.insert(
+            "Width".to_string(),
+            Tag::Short(self.volume.size[0] as u16 as i16),
+        );
+        schematic.insert(
+            "Height".to_string(),
+            Tag::Short(self.volume.size[1] as u16 as i16),
+        );
+        schematic.insert(
+            "Length".to_string(),
+            Tag::Short(self.volume.size[2] as u16 as i16),
+        );
+        schematic.insert("Offset".to_string(), Tag::IntArray(self.offset.to_vec()));
+
+        Ok(match version {
+            SchematicVersion::V2 => {
+                schematic.insert("Version".to_string(), Tag::Int(2));
+                schematic.insert("DataVersion".to_string(), Tag::Int(DATA_VERSION_V2));
+                schematic.insert("PaletteMax".to_string(), Tag::Int(palette.len() as i32));
+                schematic.insert("Palette".to_string(), Tag::Compound(palette));
+                schematic.insert("BlockData".to_string(), Tag::ByteArray(data));
+                schematic.insert("BlockEntities".to_string(), Tag::List(vec![]));
+
+                ("Schematic".to_string(), Tag::Compound(schematic))
+            }
+            SchematicVersion::V3 => {
+                let mut blocks = BTreeMap::new();
+                blocks.insert("Palette".to_string(), Tag::Compound(palette));
+                blocks.insert("Data".to_string(), Tag::ByteArray(data));
+                blocks.insert("BlockEntities".to_string(), Tag::List(vec![]));
+
+                schematic.insert("Version".to_string(), Tag::Int(3));
+                schematic.insert("DataVersion".to_string(), Tag::Int(DATA_VERSION_V3));
+                schematic.insert("Blocks".to_string(), Tag::Compound(blocks));
+
+                let mut root = BTreeMap::new();
+                root.insert("Schematic".to_string(), Tag::Compound(schematic));
+
+                (String::new(), Tag::Compound(root))
+            }
+        })
+    }
+
+    pub fn write<P: AsRef<Path>>(
+        &self,
+        path: P,
+        table: &BlockNameTable,
+        version: SchematicVersion,
+    ) -> Result<(), Box<dyn Error>> {
+        let (name, tag) = self.to_nbt(table, version)?;
+        fs::write(path, nbt::write_gzip(&name, &tag)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! reading the triangles of wavefront obj and stl meshes, everything
+//! but the vertex positions (normals, uvs, materials) is ignored
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub type Triangle = [[f32; 3]; 3];
+
+/// reads the faces of an obj file, polygons with more than three
+/// vertices are split into a fan of triangles
+pub fn parse_obj(text: &str) -> Result<Vec<Triangle>, Box<dyn Error>> {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut triangles = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+
+        match parts.next() {
+            Some("v") => {
+                let mut position = [0.0; 3];
+                for axis in position.iter_mut() {
+                    *axis = parts
+                        .next()
+                        .ok_or_else(|| {
+                            format!("line {}: vertex has too few coordinates", number + 1)
+                        })?
+                        .parse()?;
+                }
+                positions.push(position);
+            }
+            Some("f") => {
+                let mut face = vec![];
+                for vertex in parts {
+                    // faces look like `f 1 2 3` or `f 1/1/1 2/2/2 3/3/3`,
+                    // negative indices count back from the latest vertex
+                    let index: i64 = vertex.split('/').next().unwrap_or("").parse()?;
+                    let index = if index < 0 {
+                        positions.len() as i64 + index
+                    } else {
+                        index - 1
+                    };
+
+                    match positions.get(index as usize) {
+                        Some(&position) if index >= 0 => face.push(position),
+                        _ => {
+                            return Err(
+                                format!("line {}: vertex index out of range", number + 1).into()
+                            )
+                        }
+                    }
+                }
+
+                for i in 1..face.len().saturating_sub(1) {
+                    triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(triangles)
+}
+
+fn parse_ascii_stl(text: &str) -> Result<Vec<Triangle>, Box<dyn Error>> {
+    let mut vertices = vec![];
+
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        if parts.next() == Some("vertex") {
+            let mut position = [0.0; 3];
+            for axis in position.iter_mut() {
+                *axis = parts
+                    .next()
+                    .ok_or("vertex has too few coordinates")?
+                    .parse()?;
+            }
+            vertices.push(position);
+        }
+    }
+
+    if vertices.len() % 3 != 0 {
+        return Err("stl facets need exactly three vertices".into());
+    }
+
+    Ok(vertices
+        .chunks_exact(3)
+        .map(|v| [v[0], v[1], v[2]])
+        .collect())
+}
+
+/// reads an ascii or binary stl file
+pub fn parse_stl(bytes: &[u8]) -> Result<Vec<Triangle>, Box<dyn Error>> {
+    // binary files may start with "solid" too, so they're
+    // recognized by their length matching the triangle count
+    let binary = bytes.len() >= 84 && {
+        let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+        bytes.len() == 84 + count * 50
+    };
+
+    if !binary {
+        return match std::str::from_utf8(bytes) {
+            Ok(text) if text.trim_start().starts_with("solid") => parse_ascii_stl(text),
+            _ => Err("not an stl file".into()),
+        };
+    }
+
+    // every triangle is a normal, three vertices and two bytes of attributes
+    Ok(bytes[84..]
+        .chunks_exact(50)
+        .map(|facet| {
+            let float =
+                |offset: usize| f32::from_le_bytes(facet[offset..offset + 4].try_into().unwrap());
+            let vertex = |v: usize| {
+                let offset = 12 + v * 12;
+                [float(offset), float(offset + 4), float(offset + 8)]
+            };
+            [vertex(0), vertex(1), vertex(2)]
+        })
+        .collect())
+}
+
+/// reads an obj or stl file depending on its extension
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Triangle>, Box<dyn Error>> {
+    let path = path.as_ref();
+
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("obj") => parse_obj(&fs::read_to_string(path)?),
+        Some("stl") => parse_stl(&fs::read(path)?),
+        _ => Err(format!("can't read a mesh from {}", path.display()).into()),
+    }
+}
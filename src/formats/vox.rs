@@ -0,0 +1,370 @@
+//! magicavoxel (`.vox`) models
+//!
+//! a vox file is a tree of chunks, each with a four letter id, the size
+//! of its own content and the size of its children. the `MAIN` chunk holds
+//! `SIZE` and `XYZI` chunks for every model and an `RGBA` palette, the
+//! scene graph chunks next to them are skipped so only the first model is
+//! imported. files without a palette use magicavoxel's default one.
+//! magicavoxel's z axis points up, which is our y axis.
+//!
+//! see https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use log::*;
+
+use crate::block::colors::BlockColors;
+use crate::block::types::*;
+use crate::block::BlockType;
+use crate::volume::Volume;
+
+const VERSION: i32 = 150;
+const MAX_SIZE: usize = 256;
+
+/// the palette magicavoxel uses for files without an `RGBA` chunk, as
+/// 0xAABBGGRR and indexed directly by the palette index, 0 is empty
+#[rustfmt::skip]
+const DEFAULT_PALETTE: [u32; 256] = [
+    0x00000000, 0xffffffff, 0xffccffff, 0xff99ffff, 0xff66ffff, 0xff33ffff,
+    0xff00ffff, 0xffffccff, 0xffccccff, 0xff99ccff, 0xff66ccff, 0xff33ccff,
+    0xff00ccff, 0xffff99ff, 0xffcc99ff, 0xff9999ff, 0xff6699ff, 0xff3399ff,
+    0xff0099ff, 0xffff66ff, 0xffcc66ff, 0xff9966ff, 0xff6666ff, 0xff3366ff,
+    0xff0066ff, 0xffff33ff, 0xffcc33ff, 0xff9933ff, 0xff6633ff, 0xff3333ff,
+    0xff0033ff, 0xffff00ff, 0xffcc00ff, 0xff9900ff, 0xff6600ff, 0xff3300ff,
+    0xff0000ff, 0xffffffcc, 0xffccffcc, 0xff99ffcc, 0xff66ffcc, 0xff33ffcc,
+    0xff00ffcc, 0xffffcccc, 0xffcccccc, 0xff99cccc, 0xff66cccc, 0xff33cccc,
+    0xff00cccc, 0xffff99cc, 0xffcc99cc, 0xff9999cc, 0xff6699cc, 0xff3399cc,
+    0xff0099cc, 0xffff66cc, 0xffcc66cc, 0xff9966cc, 0xff6666cc, 0xff3366cc,
+    0xff0066cc, 0xffff33cc, 0xffcc33cc, 0xff9933cc, 0xff6633cc, 0xff3333cc,
+    0xff0033cc, 0xffff00cc, 0xffcc00cc, 0xff9900cc, 0xff6600cc, 0xff3300cc,
+    0xff0000cc, 0xffffff99, 0xffccff99, 0xff99ff99, 0xff66ff99, 0xff33ff99,
+    0xff00ff99, 0xffffcc99, 0xffcccc99, 0xff99cc99, 0xff66cc99, 0xff33cc99,
+    0xff00cc99, 0xffff9999, 0xffcc9999, 0xff999999, 0xff669999, 0xff339999,
+    0xff009999, 0xffff6699, 0xffcc6699, 0xff996699, 0xff666699, 0xff336699,
+    0xff006699, 0xffff3399, 0xffcc3399, 0xff993399, 0xff663399, 0xff333399,
+    0xff003399, 0xffff0099, 0xffcc0099, 0xff990099, 0xff660099, 0xff330099,
+    0xff000099, 0xffffff66, 0xffccff66, 0xff99ff66, 0xff66ff66, 0xff33ff66,
+    0xff00ff66, 0xffffcc66, 0xffcccc66, 0xff99cc66, 0xff66cc66, 0xff33cc66,
+    0xff00cc66, 0xffff9966, 0xffcc9966, 0xff999966, 0xff669966, 0xff339966,
+    0xff009966, 0xffff6666, 0xffcc6666, 0xff996666, 0xff666666, 0xff336666,
+    0xff006666, 0xffff3366, 0xffcc3366, 0xff993366, 0xff663366, 0xff333366,
+    0xff003366, 0xffff0066, 0xffcc0066, 0xff990066, 0xff660066, 0xff330066,
+    0xff000066, 0xffffff33, 0xffccff33, 0xff99ff33, 0xff66ff33, 0xff33ff33,
+    0xff00ff33, 0xffffcc33, 0xffcccc33, 0xff99cc33, 0xff66cc33, 0xff33cc33,
+    0xff00cc33, 0xffff9933, 0xffcc9933, 0xff999933, 0xff669933, 0xff339933,
+    0xff009933, 0xffff6633, 0xffcc6633, 0xff996633, 0xff666633, 0xff336633,
+    0xff006633, 0xffff3333, 0xffcc3333, 0xff993333, 0xff663333, 0xff333333,
+    0xff003333, 0xffff0033, 0xffcc0033, 0xff990033, 0xff660033, 0xff330033,
+    0xff000033, 0xffffff00, 0xffccff00, 0xff99ff00, 0xff66ff00, 0xff33ff00,
+    0xff00ff00, 0xffffcc00, 0xffcccc00, 0xff99cc00, 0xff66cc00, 0xff33cc00,
+    0xff00cc00, 0xffff9900, 0xffcc9900, 0xff999900, 0xff669900, 0xff339900,
+    0xff009900, 0xffff6600, 0xffcc6600, 0xff996600, 0xff666600, 0xff336600,
+    0xff006600, 0xffff3300, 0xffcc3300, 0xff993300, 0xff663300, 0xff333300,
+    0xff003300, 0xffff0000, 0xffcc0000, 0xff990000, 0xff660000, 0xff330000,
+    0xff0000ee, 0xff0000dd, 0xff0000bb, 0xff0000aa, 0xff000088, 0xff000077,
+    0xff000055, 0xff000044, 0xff000022, 0xff000011, 0xff00ee00, 0xff00dd00,
+    0xff00bb00, 0xff00aa00, 0xff008800, 0xff007700, 0xff005500, 0xff004400,
+    0xff002200, 0xff001100, 0xffee0000, 0xffdd0000, 0xffbb0000, 0xffaa0000,
+    0xff880000, 0xff770000, 0xff550000, 0xff440000, 0xff220000, 0xff110000,
+    0xffeeeeee, 0xffdddddd, 0xffbbbbbb, 0xffaaaaaa, 0xff888888, 0xff777777,
+    0xff555555, 0xff444444, 0xff222222, 0xff111111,
+];
+
+/// explicit block types for palette indices, the rest
+/// get the block type whose colour is the closest
+pub type PaletteOverrides = HashMap<u8, Option<&'static BlockType>>;
+
+/// where the game looks for palette overrides, see `parse_overrides`
+pub const PALETTE_OVERRIDES_FILE: &str = "vox_palette.txt";
+
+struct VoxChunk<'a> {
+    id: &'a [u8],
+    content: &'a [u8],
+    children: &'a [u8],
+}
+
+fn read_i32(bytes: &[u8], offset: usize) -> Result<i32, Box<dyn Error>> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(i32::from_le_bytes(b.try_into().unwrap())),
+        None => Err("vox file is truncated".into()),
+    }
+}
+
+/// splits a run of sibling chunks
+fn read_chunks(mut bytes: &[u8]) -> Result<Vec<VoxChunk<'_>>, Box<dyn Error>> {
+    let mut chunks = vec![];
+
+    while !bytes.is_empty() {
+        let content_len = read_i32(bytes, 4)?.max(0) as usize;
+        let children_len = read_i32(bytes, 8)?.max(0) as usize;
+        let end = 12 + content_len + children_len;
+
+        if bytes.len() < end {
+            return Err("vox chunk is truncated".into());
+        }
+
+        chunks.push(VoxChunk {
+            id: &bytes[0..4],
+            content: &bytes[12..12 + content_len],
+            children: &bytes[12 + content_len..end],
+        });
+        bytes = &bytes[end..];
+    }
+
+    Ok(chunks)
+}
+
+fn color_bytes(color: [f32; 4]) -> [u8; 4] {
+    [
+        (color[0] * 255.0).round() as u8,
+        (color[1] * 255.0).round() as u8,
+        (color[2] * 255.0).round() as u8,
+        (color[3] * 255.0).round() as u8,
+    ]
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    out.extend(id);
+    out.extend((content.len() as i32).to_le_bytes());
+    out.extend((children.len() as i32).to_le_bytes());
+    out.extend(content);
+    out.extend(children);
+}
+
+/// parses `palette index = block type` lines, where the block type
+/// can be `air`. lines starting with `#` are comments
+pub fn parse_overrides(text: &str) -> Result<PaletteOverrides, Box<dyn Error>> {
+    let mut overrides = HashMap::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let (index, name) = match (parts.next(), parts.next()) {
+            (Some(index), Some(name)) => (index.trim(), name.trim()),
+            _ => return Err(format!("line {}: expected `index = block type`", number + 1).into()),
+        };
+
+        let index: u8 = index
+            .parse()
+            .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        let block_type = match name {
+            "air" => None,
+            name => match block_type_by_name(name) {
+                Some(block_type) => Some(block_type),
+                None => {
+                    return Err(
+                        format!("line {}: unknown block type \"{}\"", number + 1, name).into(),
+                    )
+                }
+            },
+        };
+
+        overrides.insert(index, block_type);
+    }
+
+    Ok(overrides)
+}
+
+/// loads the overrides at the path, there are none when the file doesn't exist
+pub fn load_overrides<P: AsRef<Path>>(path: P) -> Result<PaletteOverrides, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse_overrides(&text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// reads the first model of a vox file, palette colours are
+/// turned into the closest block type unless overridden
+pub fn parse(
+    bytes: &[u8],
+    colors: &BlockColors,
+    overrides: &PaletteOverrides,
+) -> Result<Volume, Box<dyn Error>> {
+    if bytes.len() < 8 || !bytes.starts_with(b"VOX ") {
+        return Err("not a vox file".into());
+    }
+
+    let main = read_chunks(&bytes[8..])?;
+    let main = match main.first() {
+        Some(chunk) if chunk.id == b"MAIN" => chunk,
+        _ => return Err("vox file has no MAIN chunk".into()),
+    };
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = None;
+    let mut models = 0;
+
+    for chunk in read_chunks(main.children)? {
+        match chunk.id {
+            b"SIZE" => {
+                models += 1;
+                if size.is_none() {
+                    size = Some([
+                        read_i32(chunk.content, 0)?.max(0) as usize,
+                        read_i32(chunk.content, 4)?.max(0) as usize,
+                        read_i32(chunk.content, 8)?.max(0) as usize,
+                    ]);
+                }
+            }
+            b"XYZI" if voxels.is_none() => {
+                let count = read_i32(chunk.content, 0)?.max(0) as usize;
+                match chunk.content.get(4..4 + count * 4) {
+                    Some(v) => voxels = Some(v),
+                    None => return Err("vox model is truncated".into()),
+                }
+            }
+            b"RGBA" => palette = Some(chunk.content),
+            _ => (),
+        }
+    }
+
+    if models > 1 {
+        warn!(
+            "vox file contains {} models, only the first is imported",
+            models
+        );
+    }
+
+    let (size, voxels) = match (size, voxels) {
+        (Some(size), Some(voxels)) => (size, voxels),
+        _ => return Err("vox file contains no model".into()),
+    };
+
+    if size.iter().any(|&s| s > MAX_SIZE) {
+        return Err(format!("vox model is too large: {:?}", size).into());
+    }
+
+    // laid out like an `RGBA` chunk, which starts at index 1
+    let default_palette: Vec<u8> = DEFAULT_PALETTE[1..]
+        .iter()
+        .flat_map(|color| color.to_le_bytes().to_vec())
+        .collect();
+    let palette = match palette {
+        Some(palette) => palette,
+        None => {
+            debug!("vox file has no palette, using the default one");
+            &default_palette
+        }
+    };
+
+    let in_use = block_types();
+    // palette index i is stored at i - 1, index 0 means empty
+    let mut block_types = vec![None; 256];
+    for index in 1..=255u8 {
+        block_types[index as usize] = match overrides.get(&index) {
+            Some(&block_type) => block_type,
+            None => {
+                let offset = (index as usize - 1) * 4;
+                match palette.get(offset..offset + 4) {
+                    // colours written by `to_bytes` turn back into the
+                    // exact block type, even transparent ones
+                    Some(rgba) => match in_use
+                        .iter()
+                        .find(|t| color_bytes(colors.get(t))[..] == *rgba)
+                    {
+                        Some(&block_type) => Some(block_type),
+                        None => Some(colors.closest([
+                            rgba[0] as f32 / 255.0,
+                            rgba[1] as f32 / 255.0,
+                            rgba[2] as f32 / 255.0,
+                        ])),
+                    },
+                    None => Some(colors.closest([0.5; 3])),
+                }
+            }
+        };
+    }
+
+    // z up becomes y up, and y is flipped to keep the model from being mirrored
+    let mut volume = Volume::new([size[0], size[2], size[1]]);
+    for voxel in voxels.chunks_exact(4) {
+        let (x, y, z) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize);
+        if x >= size[0] || y >= size[1] || z >= size[2] {
+            continue;
+        }
+
+        volume.set([x, z, size[1] - 1 - y], block_types[voxel[3] as usize]);
+    }
+
+    Ok(volume)
+}
+
+pub fn read<P: AsRef<Path>>(
+    path: P,
+    colors: &BlockColors,
+    overrides: &PaletteOverrides,
+) -> Result<Volume, Box<dyn Error>> {
+    parse(&fs::read(path)?, colors, overrides)
+}
+
+/// writes a volume as a vox model, every block type gets its own
+/// palette entry coloured with the block's average colour
+pub fn to_bytes(volume: &Volume, colors: &BlockColors) -> Result<Vec<u8>, Box<dyn Error>> {
+    if volume.size.iter().any(|&s| s > MAX_SIZE) {
+        return Err("vox models can't be larger than 256 blocks on any axis".into());
+    }
+
+    let block_types = block_types();
+    let index_of = |block_type: &BlockType| -> u8 {
+        block_types
+            .iter()
+            .position(|t| t.name == block_type.name)
+            .map_or(255, |i| i as u8 + 1)
+    };
+
+    let mut size = vec![];
+    for s in [volume.size[0], volume.size[2], volume.size[1]] {
+        size.extend((s as i32).to_le_bytes());
+    }
+
+    let mut voxels = vec![];
+    let mut count = 0i32;
+    for x in 0..volume.size[0] {
+        for y in 0..volume.size[1] {
+            for z in 0..volume.size[2] {
+                if let Some(block_type) = volume.get([x, y, z]) {
+                    let vox_y = volume.size[2] - 1 - z;
+                    voxels.extend([x as u8, vox_y as u8, y as u8, index_of(block_type)]);
+                    count += 1;
+                }
+            }
+        }
+    }
+    let mut xyzi = count.to_le_bytes().to_vec();
+    xyzi.extend(voxels);
+
+    let mut rgba = vec![255; 256 * 4];
+    for (i, block_type) in block_types.iter().enumerate() {
+        rgba[i * 4..i * 4 + 4].copy_from_slice(&color_bytes(colors.get(block_type)));
+    }
+
+    let mut children = vec![];
+    write_chunk(&mut children, b"SIZE", &size, &[]);
+    write_chunk(&mut children, b"XYZI", &xyzi, &[]);
+    write_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    write_chunk(&mut bytes, b"MAIN", &[], &children);
+
+    Ok(bytes)
+}
+
+pub fn write<P: AsRef<Path>>(
+    path: P,
+    volume: &Volume,
+    colors: &BlockColors,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(path, to_bytes(volume, colors)?)?;
+    Ok(())
+}
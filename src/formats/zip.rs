@@ -0,0 +1,187 @@
+//! reading zip archives, as much of the format as resource packs need
+//!
+//! the central directory at the end of an archive lists every file along
+//! with the offset of its local header, which is followed by its contents.
+//! files are either stored as they are or compressed with deflate, zip64,
+//! encryption and archives split over several disks aren't supported.
+//!
+//! see https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::DeflateDecoder;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const LOCAL_HEADER: u32 = 0x04034b50;
+
+// the end of central directory record without its comment,
+// which can be up to u16::MAX bytes long
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const ENCRYPTED: u16 = 1;
+
+struct Entry {
+    method: u16,
+    crc32: u32,
+    compressed_size: usize,
+    size: usize,
+    local_header: usize,
+}
+
+pub struct ZipArchive {
+    bytes: Vec<u8>,
+    entries: BTreeMap<String, Entry>,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    match bytes.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_le_bytes(b.try_into().unwrap())),
+        None => Err("zip archive is truncated".into()),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes(b.try_into().unwrap())),
+        None => Err("zip archive is truncated".into()),
+    }
+}
+
+impl ZipArchive {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::parse(fs::read(path)?)
+    }
+
+    /// reads the central directory, the files are only
+    /// decompressed once they're asked for
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+        if bytes.len() < END_OF_CENTRAL_DIRECTORY_LEN {
+            return Err("not a zip archive".into());
+        }
+
+        // the record is found by searching backwards past the comment
+        let last = bytes.len() - END_OF_CENTRAL_DIRECTORY_LEN;
+        let first = last.saturating_sub(u16::MAX as usize);
+        let end = (first..=last)
+            .rev()
+            .find(|&i| read_u32(&bytes, i).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+            .ok_or("not a zip archive")?;
+
+        let count = read_u16(&bytes, end + 10)?;
+        let mut offset = read_u32(&bytes, end + 16)? as usize;
+
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            if read_u32(&bytes, offset)? != CENTRAL_DIRECTORY_HEADER {
+                return Err("zip central directory is damaged".into());
+            }
+
+            let name_len = read_u16(&bytes, offset + 28)? as usize;
+            let extra_len = read_u16(&bytes, offset + 30)? as usize;
+            let comment_len = read_u16(&bytes, offset + 32)? as usize;
+            let name = match bytes.get(offset + 46..offset + 46 + name_len) {
+                Some(name) => String::from_utf8_lossy(name).into_owned(),
+                None => return Err("zip archive is truncated".into()),
+            };
+
+            let entry = Entry {
+                method: match read_u16(&bytes, offset + 8)? & ENCRYPTED {
+                    0 => read_u16(&bytes, offset + 10)?,
+                    _ => return Err(format!("{} is encrypted", name).into()),
+                },
+                crc32: read_u32(&bytes, offset + 16)?,
+                compressed_size: read_u32(&bytes, offset + 20)? as usize,
+                size: read_u32(&bytes, offset + 24)? as usize,
+                local_header: read_u32(&bytes, offset + 42)? as usize,
+            };
+            offset += 46 + name_len + extra_len + comment_len;
+
+            // directories are entries of their own, their names end in a slash
+            if !name.ends_with('/') {
+                entries.insert(name, entry);
+            }
+        }
+
+        Ok(ZipArchive {
+            bytes: bytes,
+            entries: entries,
+        })
+    }
+
+    /// the paths of every file in the archive, in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    /// decompresses a file, None if the archive doesn't have it
+    pub fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let entry = match self.entries.get(name) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // the local header has a name and extra field of its own
+        let header = entry.local_header;
+        if read_u32(&self.bytes, header)? != LOCAL_HEADER {
+            return Err(format!("the local header of {} is damaged", name).into());
+        }
+        let start = header
+            + 30
+            + read_u16(&self.bytes, header + 26)? as usize
+            + read_u16(&self.bytes, header + 28)? as usize;
+        let compressed = self
+            .bytes
+            .get(start..start + entry.compressed_size)
+            .ok_or_else(|| format!("{} is truncated", name))?;
+
+        let contents = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => {
+                // the size comes from the archive, so it's only
+                // trusted once the file is decompressed
+                let mut contents = Vec::new();
+                DeflateDecoder::new(compressed)
+                    .take(entry.size as u64 + 1)
+                    .read_to_end(&mut contents)
+                    .map_err(|e| format!("failed to decompress {}: {}", name, e))?;
+                contents
+            }
+            method => {
+                return Err(format!(
+                    "{} uses compression method {}, only stored and deflated files are supported",
+                    name, method
+                )
+                .into())
+            }
+        };
+
+        if contents.len() != entry.size {
+            return Err(format!(
+                "{} is damaged, it isn't {} bytes long like the archive says",
+                name, entry.size
+            )
+            .into());
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&contents);
+        let actual = hasher.finalize();
+        if actual != entry.crc32 {
+            return Err(format!(
+                "{} is damaged, checksum mismatch, expected {:08x} but got {:08x}",
+                name, entry.crc32, actual
+            )
+            .into());
+        }
+
+        Ok(Some(contents))
+    }
+}
@@ -12,6 +12,68 @@ pub struct Vertex {
     pub normal: [f32; 3],
 }
 
+/// a vertex of a chunk mesh packed into 8 bytes, it's unpacked again in
+/// shaders/chunk/vertex.vert. the bits of the first word are
+/// - 0-17: the position in the chunk, 6 bits per axis
+/// - 18-20: the face, in the order -x, +x, -y, +y, -z, +z
+/// - 21-22: which corner of the texture the vertex is at
+/// - 23-24: the ambient occlusion, 3 isn't occluded at all
+/// - 25-28: the light
+///
+/// and the second word is the layer of the block texture array the face uses
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkVertex {
+    pub data: [u32; 2],
+}
+
+impl ChunkVertex {
+    pub fn new(
+        position: [u32; 3],
+        face: u32,
+        corner: [u32; 2],
+        layer: u32,
+        ao: u32,
+        light: u32,
+    ) -> Self {
+        ChunkVertex {
+            data: [
+                position[0]
+                    | position[1] << 6
+                    | position[2] << 12
+                    | face << 18
+                    | corner[0] << 21
+                    | corner[1] << 22
+                    | ao << 23
+                    | light << 25,
+                layer,
+            ],
+        }
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        [
+            (self.data[0] & 63) as f32,
+            (self.data[0] >> 6 & 63) as f32,
+            (self.data[0] >> 12 & 63) as f32,
+        ]
+    }
+
+    /// moves the vertex up, or down for negative amounts, by whole blocks.
+    /// it has to stay inside the 6 bits of its position
+    pub fn move_up(&mut self, blocks: i32) {
+        let y = (self.data[0] >> 6 & 63) as i32 + blocks;
+        debug_assert!((0..64).contains(&y), "vertex moved out of its chunk");
+
+        self.data[0] = self.data[0] & !(63 << 6) | (y as u32) << 6;
+    }
+}
+
+/// where the chunk a mesh belongs to starts, see arena.rs
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkInstance {
+    pub chunk_offset: [f32; 3],
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex2d {
     pub position: [f32; 2],
@@ -23,6 +85,8 @@ pub struct Vertex3d {
 }
 
 implement_vertex!(Vertex, position, uv, normal);
+implement_vertex!(ChunkVertex, data);
+implement_vertex!(ChunkInstance, chunk_offset);
 implement_vertex!(Vertex2d, position);
 implement_vertex!(Vertex3d, position);
 
@@ -34,7 +98,7 @@ pub struct Mesh<V: Copy> {
 /// uniforms that are shared by multiple chunks
 #[derive(Clone, Copy)]
 pub struct WorldUniforms<'a> {
-    pub texture_atlas: Sampler<'a, CompressedSrgbTexture2d>,
+    pub block_textures: Sampler<'a, SrgbTexture2dArray>,
     pub render_distance: f32,
 }
 
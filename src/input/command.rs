@@ -0,0 +1,71 @@
+//! commands typed into the game after pressing `/`, there is no text on
+//! screen yet so the results are logged
+
+use std::error::Error;
+
+use glium::Display;
+use log::*;
+
+use crate::resource_pack::{self, RESOURCE_PACKS_DIR};
+use crate::world::World;
+
+const HELP: &str = "commands:
+    snapshot <name>  saves the world and takes a snapshot of it
+    snapshots        lists the snapshots of the world
+    restore <name>   puts the world back the way it was in a snapshot
+    packs            lists the resource packs and which ones are in use
+    packs <pack>...  uses the resource packs, earlier ones override later ones
+    packs none       goes back to the assets the game comes with";
+
+pub fn run(world: &mut World, display: &Display, line: &str) -> Result<(), Box<dyn Error>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words[..] {
+        [] => (),
+        ["help"] => info!("{}", HELP),
+        ["snapshot", name] => {
+            let stats = world.create_snapshot(name)?;
+            info!(
+                "took snapshot \"{}\", {} files were stored and {} were already stored",
+                name, stats.stored, stats.reused
+            );
+        }
+        ["snapshots"] => {
+            let snapshots = world.snapshots()?;
+            if snapshots.is_empty() {
+                info!("there are no snapshots");
+            }
+            for snapshot in snapshots {
+                info!("{} ({})", snapshot.name, snapshot.age());
+            }
+        }
+        ["restore", name] => {
+            world.restore_snapshot(display, name)?;
+            info!("restored snapshot \"{}\"", name);
+        }
+        ["packs"] => {
+            let in_use = world.resource_packs().names();
+            let available = resource_pack::available()?;
+            if available.is_empty() {
+                info!("there are no resource packs in {}", RESOURCE_PACKS_DIR);
+            }
+            for name in available {
+                match in_use.iter().position(|&n| n == name) {
+                    Some(priority) => info!("{} (in use, priority {})", name, priority + 1),
+                    None => info!("{}", name),
+                }
+            }
+        }
+        ["packs", "none"] => {
+            world.set_resource_packs(display, &[])?;
+            info!("using the assets the game comes with");
+        }
+        ["packs", ref names @ ..] => {
+            world.set_resource_packs(display, names)?;
+            info!("using the resource packs {}", names.join(", "));
+        }
+        _ => return Err(format!("unknown command \"{}\"\n{}", line, HELP).into()),
+    }
+
+    Ok(())
+}
@@ -11,12 +11,13 @@ use glium::glutin::event_loop::ControlFlow;
 use glium::Display;
 use glutin::window::Window;
 
-use crate::block::{types::*, Block};
+use crate::block::types::*;
 use crate::camera::{self, Camera};
-use crate::chunk::{Chunk, CHUNK_SIZE_U8};
+use crate::formats::schematic::SchematicVersion;
 use crate::world::World;
 
-mod raycast;
+pub mod command;
+pub mod raycast;
 
 // how many turns a single pixel of movement corresponds to
 const MOUSE_SENSITIVITY: f64 = 0.0001;
@@ -28,12 +29,29 @@ const RIGHT_KEY: VirtualKeyCode = VirtualKeyCode::E;
 const UP_KEY: VirtualKeyCode = VirtualKeyCode::Space;
 const DOWN_KEY: VirtualKeyCode = VirtualKeyCode::LControl;
 
+const ANISOTROPIC_FILTERING_KEY: VirtualKeyCode = VirtualKeyCode::F3;
+const TRANSPARENCY_MODE_KEY: VirtualKeyCode = VirtualKeyCode::F4;
 const RELOAD_KEY: VirtualKeyCode = VirtualKeyCode::F5;
+const EXPORT_SCHEMATIC_KEY: VirtualKeyCode = VirtualKeyCode::F6;
+const IMPORT_SCHEMATIC_KEY: VirtualKeyCode = VirtualKeyCode::F7;
+const EXPORT_SELECTION_MESH_KEY: VirtualKeyCode = VirtualKeyCode::F8;
+const EXPORT_WORLD_MESH_KEY: VirtualKeyCode = VirtualKeyCode::F9;
+const EXPORT_VOX_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+const IMPORT_VOX_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+
+// the selection keys work on a cube of this many blocks around the camera
+const SELECTION_SIZE: usize = 32;
+const SCHEMATIC_PATH: &str = "selection.schem";
+const SELECTION_MESH_PATH: &str = "selection.obj";
+const WORLD_MESH_PATH: &str = "world.glb";
+const VOX_PATH: &str = "selection.vox";
 
 pub struct InputHandler {
     world: Rc<RefCell<World>>,
     capturing_mouse: bool,
     camera_controller: camera::controller::Controller,
+    /// what has been typed since `/` was pressed, if it was
+    command: Option<String>,
 }
 
 impl InputHandler {
@@ -50,34 +68,20 @@ impl InputHandler {
             world: world,
             capturing_mouse: capturing_mouse,
             camera_controller: Default::default(),
+            command: None,
         }
     }
 
-    /// invalidates the correct chunks when the specified block
-    /// changes
-    fn invalidate_block_chunkmeshes(world: &mut World, global_coords: [i32; 3]) {
-        let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(global_coords);
+    /// the lowest corner of the box the selection keys work on
+    fn selection_origin(world: &World) -> [i32; 3] {
+        let position = world.camera.get_position();
+        let half = (SELECTION_SIZE / 2) as f32;
 
-        // the chunk in which the block changed will always be invalid
-        world.flag_chunkmesh_dirty(chunk_coords);
-
-        if block_coords[0] == 0 {
-            world.flag_chunkmesh_dirty([chunk_coords[0] - 1, chunk_coords[1], chunk_coords[2]]);
-        } else if block_coords[0] == CHUNK_SIZE_U8 - 1 {
-            world.flag_chunkmesh_dirty([chunk_coords[0] + 1, chunk_coords[1], chunk_coords[2]]);
-        }
-
-        if block_coords[1] == 0 {
-            world.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1] - 1, chunk_coords[2]]);
-        } else if block_coords[1] == CHUNK_SIZE_U8 - 1 {
-            world.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1] + 1, chunk_coords[2]]);
-        }
-
-        if block_coords[2] == 0 {
-            world.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1], chunk_coords[2] - 1]);
-        } else if block_coords[2] == CHUNK_SIZE_U8 - 1 {
-            world.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1], chunk_coords[2] + 1]);
-        }
+        [
+            (position[0] - half).floor() as i32,
+            (position[1] - half).floor() as i32,
+            (position[2] - half).floor() as i32,
+        ]
     }
 
     fn handle_keyboard_event(
@@ -96,12 +100,74 @@ impl InputHandler {
             BACKWARD_KEY => self.camera_controller.moving_back = pressed,
             RIGHT_KEY => self.camera_controller.moving_right = pressed,
 
+            ANISOTROPIC_FILTERING_KEY if pressed => {
+                let enabled = self.world.borrow_mut().toggle_anisotropic_filtering();
+                info!("anisotropic filtering: {}", enabled);
+            }
+            TRANSPARENCY_MODE_KEY if pressed => {
+                let mode = self.world.borrow_mut().toggle_transparency_mode();
+                info!("transparency mode: {:?}", mode);
+            }
             RELOAD_KEY => match self.world.borrow_mut().reload_assets(display) {
                 Ok(_) => (),
                 Err(e) => {
                     error!("{}", e);
                 }
             },
+            EXPORT_SCHEMATIC_KEY if pressed => {
+                let world = self.world.borrow();
+                let origin = Self::selection_origin(&world);
+
+                if let Err(e) = world.export_schematic(
+                    SCHEMATIC_PATH,
+                    origin,
+                    [SELECTION_SIZE; 3],
+                    &world.block_name_table(),
+                    SchematicVersion::V2,
+                ) {
+                    error!("failed to export schematic: {}", e);
+                }
+            }
+            IMPORT_SCHEMATIC_KEY if pressed => {
+                let mut world = self.world.borrow_mut();
+                let origin = Self::selection_origin(&world);
+                let table = world.block_name_table();
+
+                if let Err(e) = world.import_schematic(SCHEMATIC_PATH, origin, &table, true) {
+                    error!("failed to import schematic: {}", e);
+                }
+            }
+            EXPORT_SELECTION_MESH_KEY if pressed => {
+                let world = self.world.borrow();
+                let origin = Self::selection_origin(&world);
+
+                if let Err(e) =
+                    world.export_mesh(SELECTION_MESH_PATH, Some((origin, [SELECTION_SIZE; 3])))
+                {
+                    error!("failed to export mesh: {}", e);
+                }
+            }
+            EXPORT_WORLD_MESH_KEY if pressed => {
+                if let Err(e) = self.world.borrow().export_mesh(WORLD_MESH_PATH, None) {
+                    error!("failed to export mesh: {}", e);
+                }
+            }
+            EXPORT_VOX_KEY if pressed => {
+                let world = self.world.borrow();
+                let origin = Self::selection_origin(&world);
+
+                if let Err(e) = world.export_vox(VOX_PATH, origin, [SELECTION_SIZE; 3]) {
+                    error!("failed to export vox model: {}", e);
+                }
+            }
+            IMPORT_VOX_KEY if pressed => {
+                let mut world = self.world.borrow_mut();
+                let origin = Self::selection_origin(&world);
+
+                if let Err(e) = world.import_vox(VOX_PATH, origin) {
+                    error!("failed to import vox model: {}", e);
+                }
+            }
             VirtualKeyCode::Escape => {
                 self.capturing_mouse = false;
                 Self::set_mouse_capture_state(display.gl_window().window(), false);
@@ -111,6 +177,55 @@ impl InputHandler {
         None
     }
 
+    /// keys that edit the command line while it is open
+    fn handle_command_key(
+        &mut self,
+        display: &Display,
+        input: &glutin::event::KeyboardInput,
+    ) -> Option<ControlFlow> {
+        if input.state != event::ElementState::Pressed {
+            return None;
+        }
+
+        match input.virtual_keycode? {
+            VirtualKeyCode::Return => {
+                let line = self.command.take().unwrap_or_default();
+                info!("/{}", line);
+
+                if let Err(e) = command::run(&mut self.world.borrow_mut(), display, &line) {
+                    error!("{}", e);
+                }
+            }
+            VirtualKeyCode::Back => {
+                if let Some(command) = self.command.as_mut() {
+                    command.pop();
+                }
+            }
+            VirtualKeyCode::Escape => {
+                self.command = None;
+                info!("closed the command line");
+            }
+            _ => (),
+        }
+        None
+    }
+
+    fn handle_character(&mut self, character: char) -> Option<ControlFlow> {
+        match self.command.as_mut() {
+            Some(command) if !character.is_control() => command.push(character),
+            Some(_) => (),
+            None if character == '/' => {
+                // keys that are held down now won't be released as far
+                // as the camera is concerned, so it stops moving
+                self.camera_controller = Default::default();
+                self.command = Some(String::new());
+                info!("type a command and press enter, \"help\" lists them");
+            }
+            None => (),
+        }
+        None
+    }
+
     fn handle_mouse_movement(&mut self, delta: &(f64, f64)) -> Option<ControlFlow> {
         if self.capturing_mouse {
             let mut world = self.world.borrow_mut();
@@ -136,17 +251,8 @@ impl InputHandler {
         } else if self.capturing_mouse {
             let mut world = self.world.borrow_mut();
 
-            let block_occupation_checker = |coord: [i32; 3]| -> bool {
-                let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(coord);
-
-                match world.chunks.iter().find(|c| c.coordinates == chunk_coords) {
-                    Some(chunk) => match chunk.get_block(block_coords) {
-                        Some(_) => true,
-                        None => false,
-                    },
-                    None => false,
-                }
-            };
+            let block_occupation_checker =
+                |coord: [i32; 3]| -> bool { world.get_block(coord).is_some() };
 
             if *button == MouseButton::Left && *state == ElementState::Pressed {
                 match raycast::raycast(
@@ -156,21 +262,8 @@ impl InputHandler {
                     6.0,
                 ) {
                     Some(coordinates) => {
-                        let (chunk_coords, block_coords) =
-                            Chunk::get_local_coord_from_world_coord(coordinates);
-                        let chunk = world
-                            .chunks
-                            .iter_mut()
-                            .find(|c| c.coordinates == chunk_coords)
-                            .unwrap();
-                        let block = chunk.get_block_mut(block_coords);
-                        *block = None;
-                        debug!(
-                            "destroyed block: chunk: {:?} block: {:?}",
-                            chunk_coords, block_coords
-                        );
-
-                        Self::invalidate_block_chunkmeshes(&mut world, coordinates);
+                        world.set_block(coordinates, None);
+                        debug!("destroyed block: {:?}", coordinates);
                     }
                     None => (),
                 }
@@ -186,21 +279,7 @@ impl InputHandler {
                     if path.len() >= 2 {
                         // check that the player isn't inside a block when trying to place
                         let place_location = path[path.len() - 2]; // second last block in the path is the block before collision
-                        let (chunk_coords, block_coordinates) =
-                            Chunk::get_local_coord_from_world_coord(place_location);
-
-                        match world
-                            .chunks
-                            .iter_mut()
-                            .find(|c| c.coordinates == chunk_coords)
-                        {
-                            Some(chunk) => {
-                                let block = chunk.get_block_mut(block_coordinates);
-                                *block = Some(Block::new(block_coordinates, &GLASS_BLOCK));
-                                Self::invalidate_block_chunkmeshes(&mut world, place_location);
-                            }
-                            None => (), // the chunk doesn't exist
-                        }
+                        world.set_block(place_location, Some(&GLASS_BLOCK));
                     }
                 }
             }
@@ -222,9 +301,13 @@ impl InputHandler {
                 WindowEvent::Resized(newsize) => {
                     self.handle_window_resize((newsize.width, newsize.height))
                 }
+                WindowEvent::KeyboardInput { input, .. } if self.command.is_some() => {
+                    self.handle_command_key(display, input)
+                }
                 WindowEvent::KeyboardInput { input, .. } => {
                     self.handle_keyboard_event(display, input)
                 }
+                WindowEvent::ReceivedCharacter(character) => self.handle_character(*character),
                 WindowEvent::MouseInput { state, button, .. } => {
                     self.handle_mouse_button(display, state, button)
                 }
@@ -8,81 +8,13 @@ pub fn raycast<F>(
 where
     F: Fn([i32; 3]) -> bool,
 {
-    let unit_step_size = [
-        (1.0 + (direction[1] / direction[0]).powi(2) + (direction[2] / direction[0]).powi(2))
-            .sqrt(),
-        (1.0 + (direction[0] / direction[1]).powi(2) + (direction[2] / direction[1]).powi(2))
-            .sqrt(),
-        (1.0 + (direction[0] / direction[2]).powi(2) + (direction[1] / direction[2]).powi(2))
-            .sqrt(),
-    ];
-
-    let mut current_block = [
-        start_position[0].floor() as i32,
-        start_position[1].floor() as i32,
-        start_position[2].floor() as i32,
-    ];
-
-    // check if starting point is already in block
-    if check_coordinate_occupied(current_block) {
-        return Some(current_block);
-    }
-
-    let mut travelled_ray: [f32; 3] = Default::default();
-    let mut step: [i32; 3] = Default::default();
-
-    // Establish Starting Conditions
-    if direction[0] < 0.0 {
-        step[0] = -1;
-        travelled_ray[0] = (start_position[0] - current_block[0] as f32) * unit_step_size[0];
-    } else {
-        step[0] = 1;
-        travelled_ray[0] = ((current_block[0] + 1) as f32 - start_position[0]) * unit_step_size[0];
-    }
-
-    if direction[1] < 0.0 {
-        step[1] = -1;
-        travelled_ray[1] = (start_position[1] - current_block[1] as f32) * unit_step_size[1];
-    } else {
-        step[1] = 1;
-        travelled_ray[1] = ((current_block[1] + 1) as f32 - start_position[1]) * unit_step_size[1];
-    }
-
-    if direction[2] < 0.0 {
-        step[2] = -1;
-        travelled_ray[2] = (start_position[2] - current_block[2] as f32) * unit_step_size[2];
-    } else {
-        step[2] = 1;
-        travelled_ray[2] = ((current_block[2] + 1) as f32 - start_position[2]) * unit_step_size[2];
-    }
-
-    // Perform "Walk" until collision or range check
-
-    let mut total_distance = 0.0;
-
-    while total_distance < max_distance {
-        // Walk along shortest path
-        if travelled_ray[0] < travelled_ray[1] && travelled_ray[0] < travelled_ray[2] {
-            current_block[0] += step[0];
-            total_distance = travelled_ray[0];
-            travelled_ray[0] += unit_step_size[0];
-        } else if travelled_ray[1] < travelled_ray[2] {
-            current_block[1] += step[1];
-            total_distance = travelled_ray[1];
-            travelled_ray[1] += unit_step_size[1];
-        } else {
-            current_block[2] += step[2];
-            total_distance = travelled_ray[2];
-            travelled_ray[2] += unit_step_size[2];
-        }
-
-        // Test tile at new test point
-        if check_coordinate_occupied(current_block) {
-            return Some(current_block);
-        }
-    }
-
-    None
+    raycast_hit(
+        check_coordinate_occupied,
+        start_position,
+        direction,
+        max_distance,
+    )
+    .map(|hit| hit.block)
 }
 
 /// marches a ray in the given direction and returns a list of all the block spaces it travelled through
@@ -99,83 +31,197 @@ pub fn raycast_path<F>(
 where
     F: Fn([i32; 3]) -> bool,
 {
-    let unit_step_size = [
-        (1.0 + (direction[1] / direction[0]).powi(2) + (direction[2] / direction[0]).powi(2))
-            .sqrt(),
-        (1.0 + (direction[0] / direction[1]).powi(2) + (direction[2] / direction[1]).powi(2))
-            .sqrt(),
-        (1.0 + (direction[0] / direction[2]).powi(2) + (direction[1] / direction[2]).powi(2))
-            .sqrt(),
-    ];
-
-    let mut current_block = [
-        start_position[0].floor() as i32,
-        start_position[1].floor() as i32,
-        start_position[2].floor() as i32,
-    ];
-
-    // check if starting point is already in block
-    if check_coordinate_occupied(current_block) {
-        return (true, vec![current_block]);
+    let mut encountered_blocks = vec![];
+
+    for step in Ray::new(start_position, direction, max_distance) {
+        encountered_blocks.push(step.block);
+
+        if check_coordinate_occupied(step.block) {
+            return (true, encountered_blocks);
+        }
     }
 
-    let mut encountered_blocks = vec![current_block];
+    (false, encountered_blocks)
+}
 
-    let mut travelled_ray: [f32; 3] = Default::default();
-    let mut step: [i32; 3] = Default::default();
+/// where a ray hit a block
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit {
+    pub block: [i32; 3],
+    /// the normal of the face that was hit, zero when the ray started inside the block
+    pub normal: [i32; 3],
+    /// how far from the start the ray entered the block
+    pub distance: f32,
+}
 
-    // Establish Starting Conditions
-    if direction[0] < 0.0 {
-        step[0] = -1;
-        travelled_ray[0] = (start_position[0] - current_block[0] as f32) * unit_step_size[0];
-    } else {
-        step[0] = 1;
-        travelled_ray[0] = ((current_block[0] + 1) as f32 - start_position[0]) * unit_step_size[0];
-    }
+/// marches a ray in the given direction like `raycast`, but also
+/// tells which face of the block was hit and how far away it is
+pub fn raycast_hit<F>(
+    check_coordinate_occupied: F,
+    start_position: [f32; 3],
+    direction: [f32; 3],
+    max_distance: f32,
+) -> Option<RayHit>
+where
+    F: Fn([i32; 3]) -> bool,
+{
+    Ray::new(start_position, direction, max_distance)
+        .find(|step| check_coordinate_occupied(step.block))
+}
 
-    if direction[1] < 0.0 {
-        step[1] = -1;
-        travelled_ray[1] = (start_position[1] - current_block[1] as f32) * unit_step_size[1];
-    } else {
-        step[1] = 1;
-        travelled_ray[1] = ((current_block[1] + 1) as f32 - start_position[1]) * unit_step_size[1];
-    }
+/// walks through every block a ray passes, in order, starting with the
+/// block it starts in. every step is the hit the ray would make if that
+/// block was occupied. stops once the ray has travelled `max_distance`
+pub struct Ray {
+    block: [i32; 3],
+    step: [i32; 3],
+    unit_step_size: [f32; 3],
+    travelled_ray: [f32; 3],
+    distance: f32,
+    max_distance: f32,
+    started: bool,
+}
+
+impl Ray {
+    pub fn new(start_position: [f32; 3], direction: [f32; 3], max_distance: f32) -> Self {
+        // how far the ray goes to move one block along each axis, this is
+        // infinite instead of NaN for the axes the ray doesn't move along
+        let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        let unit_step_size = [
+            length / direction[0].abs(),
+            length / direction[1].abs(),
+            length / direction[2].abs(),
+        ];
+
+        let block = [
+            start_position[0].floor() as i32,
+            start_position[1].floor() as i32,
+            start_position[2].floor() as i32,
+        ];
+
+        let mut travelled_ray: [f32; 3] = Default::default();
+        let mut step: [i32; 3] = Default::default();
+
+        // Establish Starting Conditions
+        for axis in 0..3 {
+            if direction[axis] < 0.0 {
+                step[axis] = -1;
+                travelled_ray[axis] =
+                    (start_position[axis] - block[axis] as f32) * unit_step_size[axis];
+            } else {
+                step[axis] = 1;
+                travelled_ray[axis] =
+                    ((block[axis] + 1) as f32 - start_position[axis]) * unit_step_size[axis];
+            }
+        }
 
-    if direction[2] < 0.0 {
-        step[2] = -1;
-        travelled_ray[2] = (start_position[2] - current_block[2] as f32) * unit_step_size[2];
-    } else {
-        step[2] = 1;
-        travelled_ray[2] = ((current_block[2] + 1) as f32 - start_position[2]) * unit_step_size[2];
+        Ray {
+            block: block,
+            step: step,
+            unit_step_size: unit_step_size,
+            travelled_ray: travelled_ray,
+            distance: 0.0,
+            max_distance: max_distance,
+            started: false,
+        }
     }
+}
 
-    // Perform "Walk" until collision or range check
+impl Iterator for Ray {
+    type Item = RayHit;
+
+    fn next(&mut self) -> Option<RayHit> {
+        // the block the ray starts in isn't entered through a face
+        if !self.started {
+            self.started = true;
+            return Some(RayHit {
+                block: self.block,
+                normal: [0, 0, 0],
+                distance: 0.0,
+            });
+        }
 
-    let mut total_distance = 0.0;
+        if self.distance >= self.max_distance {
+            return None;
+        }
 
-    while total_distance < max_distance {
         // Walk along shortest path
-        if travelled_ray[0] < travelled_ray[1] && travelled_ray[0] < travelled_ray[2] {
-            current_block[0] += step[0];
-            total_distance = travelled_ray[0];
-            travelled_ray[0] += unit_step_size[0];
+        let travelled_ray = self.travelled_ray;
+        let axis = if travelled_ray[0] < travelled_ray[1] && travelled_ray[0] < travelled_ray[2] {
+            0
         } else if travelled_ray[1] < travelled_ray[2] {
-            current_block[1] += step[1];
-            total_distance = travelled_ray[1];
-            travelled_ray[1] += unit_step_size[1];
+            1
         } else {
-            current_block[2] += step[2];
-            total_distance = travelled_ray[2];
-            travelled_ray[2] += unit_step_size[2];
-        }
+            2
+        };
 
-        encountered_blocks.push(current_block);
+        self.block[axis] += self.step[axis];
+        self.distance = self.travelled_ray[axis];
+        self.travelled_ray[axis] += self.unit_step_size[axis];
 
-        // Test tile at new test point
-        if check_coordinate_occupied(current_block) {
-            return (true, encountered_blocks);
+        let mut normal = [0; 3];
+        normal[axis] = -self.step[axis];
+
+        Some(RayHit {
+            block: self.block,
+            normal: normal,
+            distance: self.distance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall_at_x(x: i32) -> impl Fn([i32; 3]) -> bool {
+        move |block| block[0] == x
+    }
+
+    #[test]
+    fn path_ends_at_the_hit() {
+        let start = [0.5, 0.5, 0.5];
+        let direction = [1.0, 0.3, 0.2];
+
+        let (hit, path) = raycast_path(wall_at_x(4), start, direction, 10.0);
+        let ray_hit = raycast_hit(wall_at_x(4), start, direction, 10.0).unwrap();
+
+        assert!(hit);
+        assert_eq!(path[0], [0, 0, 0]);
+        assert_eq!(*path.last().unwrap(), ray_hit.block);
+        assert_eq!(ray_hit.normal, [-1, 0, 0]);
+        assert!((ray_hit.distance - 3.5 * (1.0f32 + 0.09 + 0.04).sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn every_step_moves_to_a_neighbour() {
+        let ray = Ray::new([0.2, 5.7, -3.1], [-0.4, -1.0, 0.7], 20.0);
+        let steps: Vec<RayHit> = ray.collect();
+
+        for pair in steps.windows(2) {
+            let moved: i32 = (0..3)
+                .map(|axis| (pair[1].block[axis] - pair[0].block[axis]).abs())
+                .sum();
+            assert_eq!(moved, 1);
+            assert!(pair[1].distance >= pair[0].distance);
         }
     }
 
-    (false, encountered_blocks)
+    #[test]
+    fn misses_stop_at_the_max_distance() {
+        let (hit, path) = raycast_path(wall_at_x(100), [0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 5.0);
+
+        assert!(!hit);
+        assert_eq!(path.len(), 7);
+        assert!(raycast_hit(wall_at_x(100), [0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 5.0).is_none());
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it() {
+        let hit = raycast_hit(wall_at_x(0), [0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 5.0).unwrap();
+
+        assert_eq!(hit.block, [0, 0, 0]);
+        assert_eq!(hit.normal, [0, 0, 0]);
+        assert_eq!(hit.distance, 0.0);
+    }
 }
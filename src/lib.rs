@@ -0,0 +1,20 @@
+//! the game and its tools share everything but their `main`, the
+//! game is `main.rs` and the tools live in `bin`
+
+pub mod arena;
+pub mod block;
+pub mod camera;
+pub mod chunk;
+pub mod formats;
+pub mod graphics;
+pub mod hud;
+pub mod input;
+pub mod map;
+pub mod pathtrace;
+pub mod pixel_art;
+pub mod resource_pack;
+pub mod save;
+pub mod terraingen;
+pub mod volume;
+pub mod voxelize;
+pub mod world;
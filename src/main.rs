@@ -1,23 +1,16 @@
-mod block;
-mod camera;
-mod chunk;
-mod graphics;
-mod hud;
-mod input;
-mod terraingen;
-mod world;
-
 use std::cell::RefCell;
+use std::error::Error;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use log::*;
 
-use glium::{glutin, implement_vertex, Frame, Surface};
+use glium::{glutin, implement_vertex, Display, Frame, Surface};
 
 use glutin::event::Event;
 
-use world::World;
+use rmc1::world::World;
+use rmc1::{block, input, pixel_art, terraingen, voxelize, world};
 
 #[derive(Clone, Copy, Debug)]
 struct FbVert {
@@ -26,14 +19,72 @@ struct FbVert {
 
 implement_vertex!(FbVert, position);
 
-fn draw(mut frame: Frame, world: &mut World) -> Result<(), glium::SwapBuffersError> {
+fn draw(
+    display: &Display,
+    mut frame: Frame,
+    world: &mut World,
+) -> Result<(), glium::SwapBuffersError> {
     frame.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
 
-    world.render(&mut frame);
+    world.render(display, &mut frame);
 
     frame.finish()
 }
 
+/// places a voxelized mesh described by the arguments
+/// `<mesh> <scale> <solid|shell> <block type> <x> <y> <z>`
+fn voxelize_from_args(world: &mut World, args: &[String]) -> Result<(), Box<dyn Error>> {
+    if args.len() != 7 {
+        return Err("wrong number of arguments".into());
+    }
+
+    let fill = match args[2].as_str() {
+        "solid" => voxelize::Fill::Solid,
+        "shell" => voxelize::Fill::Shell,
+        other => return Err(format!("unknown fill \"{}\"", other).into()),
+    };
+    let block_type = match block::types::block_type_by_name(&args[3]) {
+        Some(block_type) => block_type,
+        None => return Err(format!("unknown block type \"{}\"", args[3]).into()),
+    };
+    let position = [args[4].parse()?, args[5].parse()?, args[6].parse()?];
+
+    world.import_mesh(&args[0], args[1].parse()?, fill, block_type, position)
+}
+
+/// places an image described by the arguments
+/// `<png> <floor|wall> <dither|nodither> <x> <y> <z>`
+fn pixel_art_from_args(world: &mut World, args: &[String]) -> Result<(), Box<dyn Error>> {
+    if args.len() != 6 {
+        return Err("wrong number of arguments".into());
+    }
+
+    let orientation = match args[1].as_str() {
+        "floor" => pixel_art::Orientation::Floor,
+        "wall" => pixel_art::Orientation::Wall,
+        other => return Err(format!("unknown orientation \"{}\"", other).into()),
+    };
+    let dither = match args[2].as_str() {
+        "dither" => true,
+        "nodither" => false,
+        other => return Err(format!("expected dither or nodither, got \"{}\"", other).into()),
+    };
+    let position = [args[3].parse()?, args[4].parse()?, args[5].parse()?];
+
+    world.import_image(&args[0], orientation, dither, position)
+}
+
+/// opens the world in the `world` directory, logging why it couldn't be
+fn open_world(display: &Display, settings: terraingen::GeneratorSettings) -> Option<World> {
+    match World::open(display, "world", settings, 256, 256) {
+        Ok(world) => Some(world),
+        Err(e) => {
+            error!("failed to open the world: {}", e);
+            None
+        }
+    }
+}
+
 fn main() {
     env_logger::init();
 
@@ -42,19 +93,68 @@ fn main() {
     let cb = glutin::ContextBuilder::new().with_depth_buffer(24);
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
 
-    let world_generator = terraingen::opensimplex::OpensimplexGenerator::new(
-        Some(453209875342987),
-        (0.03, 0.03),
-        30.0,
-        30.0,
-    );
-
-    let world = Rc::new(RefCell::new(world::World::generate(
-        &display,
-        &world_generator,
-        256,
-        256,
-    )));
+    let generator_settings = terraingen::GeneratorSettings::Opensimplex {
+        seed: Some(453209875342987),
+        coord_scaling: (0.03, 0.03),
+        offset: 30.0,
+        amplitude: 30.0,
+    };
+
+    // `rmc1 --import-anvil <region dir> [x z]` shows the terrain of a
+    // minecraft world instead of our own, starting at the given block
+    let args: Vec<String> = std::env::args().collect();
+    let world = match args.get(1).map(|a| a.as_str()) {
+        Some("--import-anvil") => {
+            let region_dir = match args.get(2) {
+                Some(dir) => dir,
+                None => {
+                    error!("usage: rmc1 --import-anvil <region dir> [x z]");
+                    return;
+                }
+            };
+            let origin = [
+                args.get(3).and_then(|x| x.parse().ok()).unwrap_or(0),
+                args.get(4).and_then(|z| z.parse().ok()).unwrap_or(0),
+            ];
+
+            match world::World::import_anvil(&display, region_dir, origin, 256, 256) {
+                Ok(world) => world,
+                Err(e) => {
+                    error!("failed to import minecraft world: {}", e);
+                    return;
+                }
+            }
+        }
+        Some("--voxelize") => {
+            let mut world = match open_world(&display, generator_settings) {
+                Some(world) => world,
+                None => return,
+            };
+            if let Err(e) = voxelize_from_args(&mut world, &args[2..]) {
+                error!("failed to voxelize mesh: {}", e);
+                error!("usage: rmc1 --voxelize <obj or stl> <scale> <solid|shell> <block type> <x> <y> <z>");
+                return;
+            }
+            world
+        }
+        Some("--pixel-art") => {
+            let mut world = match open_world(&display, generator_settings) {
+                Some(world) => world,
+                None => return,
+            };
+            if let Err(e) = pixel_art_from_args(&mut world, &args[2..]) {
+                error!("failed to place image: {}", e);
+                error!("usage: rmc1 --pixel-art <png> <floor|wall> <dither|nodither> <x> <y> <z>");
+                return;
+            }
+            world
+        }
+        _ => match open_world(&display, generator_settings) {
+            Some(world) => world,
+            None => return,
+        },
+    };
+    let world = Rc::new(RefCell::new(world));
 
     let mut inputhandler =
         input::InputHandler::new(world.clone(), display.gl_window().window(), true);
@@ -81,10 +181,14 @@ fn main() {
 
         if clock1.borrow().duration_since(*start.borrow()).as_secs() != last_frame_printout_time {
             last_frame_printout_time = clock1.borrow().duration_since(*start.borrow()).as_secs();
+            let stats = world.borrow().render_stats();
             info!(
-                "[FPS: {}] [frame time: {}]",
+                "[FPS: {}] [frame time: {}] [chunks drawn: {}] [culled: {}] [occluded: {}]",
                 render_count,
-                (1.0 / worst_frame_time.as_secs_f64()) as i32
+                (1.0 / worst_frame_time.as_secs_f64()) as i32,
+                stats.drawn,
+                stats.culled,
+                stats.occluded
             );
             render_count = 0;
             worst_frame_time = Duration::from_secs(0);
@@ -109,6 +213,9 @@ fn main() {
             glutin::event::Event::WindowEvent { event, .. } => match event {
                 // Break from the main loop when the window is closed.
                 glutin::event::WindowEvent::CloseRequested => {
+                    if let Err(e) = world.borrow_mut().save() {
+                        error!("failed to save world: {}", e);
+                    }
                     *control_flow = glutin::event_loop::ControlFlow::Exit
                 }
                 glutin::event::WindowEvent::Resized(_) => {
@@ -129,7 +236,7 @@ fn main() {
                 .borrow_mut()
                 .update(&display, last_frame_time.as_secs_f32());
 
-            draw(display.draw(), &mut world.borrow_mut()).unwrap();
+            draw(&display, display.draw(), &mut world.borrow_mut()).unwrap();
 
             if last_frame_time > worst_frame_time {
                 worst_frame_time = last_frame_time;
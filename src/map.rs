@@ -0,0 +1,343 @@
+//! drawing maps of the world on the cpu, without a gl context
+//!
+//! the top down map has a pixel for every column of blocks, coloured by
+//! the top face of the highest block. the isometric map draws the top and
+//! the +x and +z sides of every block that can be seen from above, back to
+//! front. both are shaded by height so hills and valleys stand out.
+
+use std::collections::HashMap;
+
+use image::{Rgba, RgbaImage};
+
+use crate::block::colors::BlockColors;
+use crate::block::BlockType;
+use crate::chunk::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// one pixel per column, north (-z) is up
+    TopDown,
+    /// looking along -x -y -z, `scale` pixels is half the height of a block
+    Isometric { scale: u32 },
+}
+
+// looks up blocks without searching through every chunk
+struct ChunkLookup<'a> {
+    chunks: HashMap<[i32; 3], &'a Chunk>,
+}
+
+impl<'a> ChunkLookup<'a> {
+    fn new(chunks: &'a [Chunk]) -> Self {
+        ChunkLookup {
+            chunks: chunks.iter().map(|c| (c.coordinates, c)).collect(),
+        }
+    }
+
+    fn get(&self, coords: [i32; 3]) -> Option<&'static BlockType> {
+        let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(coords);
+
+        self.chunks
+            .get(&chunk_coords)
+            .and_then(|chunk| *chunk.get_block(block_coords))
+            .map(|block| block.block_type)
+    }
+}
+
+/// darkens low blocks and brightens high ones
+fn height_shade(y: i32, lowest: i32, highest: i32) -> f32 {
+    if highest > lowest {
+        0.7 + 0.3 * (y - lowest) as f32 / (highest - lowest) as f32
+    } else {
+        1.0
+    }
+}
+
+fn shaded(color: [f32; 4], shade: f32) -> [f32; 4] {
+    [
+        (color[0] * shade).min(1.0),
+        (color[1] * shade).min(1.0),
+        (color[2] * shade).min(1.0),
+        color[3],
+    ]
+}
+
+/// draws `color` on top of a pixel
+fn blend(pixel: &mut Rgba<u8>, color: [f32; 4]) {
+    let below = pixel.0;
+    let below_alpha = below[3] as f32 / 255.0;
+    let alpha = color[3] + below_alpha * (1.0 - color[3]);
+
+    if alpha <= 0.0 {
+        return;
+    }
+
+    for i in 0..3 {
+        let value = (color[i] * color[3]
+            + below[i] as f32 / 255.0 * below_alpha * (1.0 - color[3]))
+            / alpha;
+        pixel.0[i] = (value * 255.0).round() as u8;
+    }
+    pixel.0[3] = (alpha * 255.0).round() as u8;
+}
+
+/// the lowest and highest block coordinates covered by the chunks
+fn block_bounds(chunks: &[Chunk]) -> Option<([i32; 3], [i32; 3])> {
+    let first = chunks.first()?.coordinates;
+    let (mut min, mut max) = (first, first);
+
+    for chunk in chunks.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(chunk.coordinates[axis]);
+            max[axis] = max[axis].max(chunk.coordinates[axis]);
+        }
+    }
+
+    Some((
+        [
+            min[0] * CHUNK_SIZE_I32,
+            min[1] * CHUNK_SIZE_I32,
+            min[2] * CHUNK_SIZE_I32,
+        ],
+        [
+            (max[0] + 1) * CHUNK_SIZE_I32,
+            (max[1] + 1) * CHUNK_SIZE_I32,
+            (max[2] + 1) * CHUNK_SIZE_I32,
+        ],
+    ))
+}
+
+pub fn render(chunks: &[Chunk], colors: &BlockColors, projection: Projection) -> RgbaImage {
+    match projection {
+        Projection::TopDown => render_top_down(chunks, colors),
+        Projection::Isometric { scale } => render_isometric(chunks, colors, scale.max(1)),
+    }
+}
+
+/// looks down every column of blocks, transparent blocks are blended
+/// over whatever is below them. columns without blocks stay transparent
+pub fn render_top_down(chunks: &[Chunk], colors: &BlockColors) -> RgbaImage {
+    let (min, max) = match block_bounds(chunks) {
+        Some(bounds) => bounds,
+        None => return RgbaImage::new(0, 0),
+    };
+    let lookup = ChunkLookup::new(chunks);
+
+    let width = (max[0] - min[0]) as usize;
+    let depth = (max[2] - min[2]) as usize;
+
+    // the colour and height of every column, from the top down
+    let mut columns: Vec<Option<(Vec<[f32; 4]>, i32)>> = vec![None; width * depth];
+
+    for z in 0..depth {
+        for x in 0..width {
+            let mut layers = vec![];
+            let mut height = None;
+
+            for y in (min[1]..max[1]).rev() {
+                let block_type = match lookup.get([min[0] + x as i32, y, min[2] + z as i32]) {
+                    Some(block_type) => block_type,
+                    None => continue,
+                };
+
+                height.get_or_insert(y);
+                let mut color = colors.faces(block_type).0;
+                if !block_type.transparent {
+                    color[3] = 1.0;
+                    layers.push(color);
+                    height = Some(y);
+                    break;
+                }
+                layers.push(color);
+            }
+
+            columns[z * width + x] = height.map(|h| (layers, h));
+        }
+    }
+
+    let heights = columns.iter().filter_map(|c| c.as_ref().map(|c| c.1));
+    let lowest = heights.clone().min().unwrap_or(0);
+    let highest = heights.max().unwrap_or(0);
+
+    let mut image = RgbaImage::new(width as u32, depth as u32);
+
+    for z in 0..depth {
+        for x in 0..width {
+            let (layers, height) = match &columns[z * width + x] {
+                Some(column) => column,
+                None => continue,
+            };
+
+            // like on paper maps, slopes facing north are lit
+            // and the ones facing away from it are in shadow
+            let mut shade = height_shade(*height, lowest, highest);
+            if z > 0 {
+                if let Some((_, north)) = &columns[(z - 1) * width + x] {
+                    if height > north {
+                        shade *= 1.1;
+                    } else if height < north {
+                        shade *= 0.9;
+                    }
+                }
+            }
+
+            let pixel = image.get_pixel_mut(x as u32, z as u32);
+            for &color in layers.iter().rev() {
+                blend(pixel, shaded(color, shade));
+            }
+        }
+    }
+
+    image
+}
+
+/// fills the parallelogram spanned by `a` and `b` at `origin`
+fn fill_parallelogram(
+    image: &mut RgbaImage,
+    origin: [f32; 2],
+    a: [f32; 2],
+    b: [f32; 2],
+    color: [f32; 4],
+) {
+    let corners = [
+        origin,
+        [origin[0] + a[0], origin[1] + a[1]],
+        [origin[0] + b[0], origin[1] + b[1]],
+        [origin[0] + a[0] + b[0], origin[1] + a[1] + b[1]],
+    ];
+    let mut min = [f32::MAX; 2];
+    let mut max = [f32::MIN; 2];
+    for corner in corners.iter() {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(corner[axis]);
+            max[axis] = max[axis].max(corner[axis]);
+        }
+    }
+
+    let (min_x, min_y) = (min[0].max(0.0) as u32, min[1].max(0.0) as u32);
+    let max_x = (max[0].ceil().max(0.0) as u32).min(image.width());
+    let max_y = (max[1].ceil().max(0.0) as u32).min(image.height());
+
+    let determinant = a[0] * b[1] - a[1] * b[0];
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            // the pixel's center in the parallelogram's own coordinates,
+            // the far edges belong to the neighbouring faces
+            let p = [x as f32 + 0.5 - origin[0], y as f32 + 0.5 - origin[1]];
+            let u = (p[0] * b[1] - p[1] * b[0]) / determinant;
+            let v = (a[0] * p[1] - a[1] * p[0]) / determinant;
+
+            if (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v) {
+                blend(image.get_pixel_mut(x, y), color);
+            }
+        }
+    }
+}
+
+/// the top, +x and +z faces of every block that has at least one of
+/// them exposed are drawn back to front, so nearer blocks cover the
+/// ones behind them
+pub fn render_isometric(chunks: &[Chunk], colors: &BlockColors, scale: u32) -> RgbaImage {
+    let (min, max) = match block_bounds(chunks) {
+        Some(bounds) => bounds,
+        None => return RgbaImage::new(0, 0),
+    };
+    let lookup = ChunkLookup::new(chunks);
+
+    let shows_face = |block_type: &BlockType, neighbour: [i32; 3]| match lookup.get(neighbour) {
+        Some(other) => other.transparent && other.name != block_type.name,
+        None => true,
+    };
+
+    // the block, and whether its top, +x and +z faces are visible
+    let mut visible = vec![];
+    for chunk in chunks.iter() {
+        for x in 0..CHUNK_SIZE_U8 {
+            for y in 0..CHUNK_SIZE_U8 {
+                for z in 0..CHUNK_SIZE_U8 {
+                    let block = match chunk.get_block([x, y, z]) {
+                        Some(block) => block,
+                        None => continue,
+                    };
+                    let [gx, gy, gz] =
+                        Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, y, z]);
+
+                    let faces = [
+                        shows_face(block.block_type, [gx, gy + 1, gz]),
+                        shows_face(block.block_type, [gx + 1, gy, gz]),
+                        shows_face(block.block_type, [gx, gy, gz + 1]),
+                    ];
+                    if faces.iter().any(|&f| f) {
+                        visible.push(([gx, gy, gz], block.block_type, faces));
+                    }
+                }
+            }
+        }
+    }
+
+    if visible.is_empty() {
+        return RgbaImage::new(0, 0);
+    }
+
+    let lowest = visible.iter().map(|b| b.0[1]).min().unwrap();
+    let highest = visible.iter().map(|b| b.0[1]).max().unwrap();
+
+    // a block is 4 * scale pixels wide and 4 * scale pixels tall, the
+    // direction of view (1, 1, 1) projects onto a single point
+    let s = scale as f32;
+    let project = |p: [i32; 3]| {
+        [
+            (p[0] - p[2]) as f32 * 2.0 * s,
+            (p[0] + p[2]) as f32 * s - p[1] as f32 * 2.0 * s,
+        ]
+    };
+
+    let screen_min = [
+        project([min[0], 0, max[2]])[0],
+        project([min[0], highest + 1, min[2]])[1],
+    ];
+    let screen_max = [
+        project([max[0], 0, min[2]])[0],
+        project([max[0], lowest, max[2]])[1],
+    ];
+
+    let mut image = RgbaImage::new(
+        (screen_max[0] - screen_min[0]).ceil() as u32,
+        (screen_max[1] - screen_min[1]).ceil() as u32,
+    );
+
+    visible.sort_by_key(|b| b.0[0] + b.0[1] + b.0[2]);
+
+    let x_edge = [2.0 * s, s];
+    let y_edge = [0.0, -2.0 * s];
+    let z_edge = [-2.0 * s, s];
+
+    for ([x, y, z], block_type, faces) in visible {
+        let (mut top, mut side) = colors.faces(block_type);
+        if !block_type.transparent {
+            top[3] = 1.0;
+            side[3] = 1.0;
+        }
+        let shade = height_shade(y, lowest, highest);
+
+        let corner = |p: [i32; 3]| {
+            let p = project(p);
+            [p[0] - screen_min[0], p[1] - screen_min[1]]
+        };
+
+        if faces[0] {
+            let color = shaded(top, shade);
+            fill_parallelogram(&mut image, corner([x, y + 1, z]), x_edge, z_edge, color);
+        }
+        if faces[1] {
+            let color = shaded(side, shade * 0.8);
+            fill_parallelogram(&mut image, corner([x + 1, y, z]), y_edge, z_edge, color);
+        }
+        if faces[2] {
+            let color = shaded(side, shade * 0.65);
+            fill_parallelogram(&mut image, corner([x, y, z + 1]), x_edge, y_edge, color);
+        }
+    }
+
+    image
+}
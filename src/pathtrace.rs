@@ -0,0 +1,511 @@
+//! an offline renderer that path traces the blocks on the cpu
+//!
+//! rays are marched through the blocks with `raycast_hit` and surfaces are
+//! textured with the block textures. light comes from the sun, whose disc
+//! has a size so shadows get soft edges, and from the same sky gradient the
+//! game draws in `sky_shader.frag`. every pixel averages many random paths,
+//! more samples means less noise.
+
+use std::f32::consts::TAU;
+use std::thread;
+
+use image::RgbaImage;
+
+use crate::block::atlas::BlockAtlas;
+use crate::block::types::block_types;
+use crate::block::BlockType;
+use crate::camera::Camera;
+use crate::chunk::*;
+use crate::input::raycast::raycast_hit;
+
+// the colours of the sky gradient, the same as in sky_shader.frag
+const SKY_COLOR: [f32; 3] = [0.2, 0.4, 0.85];
+const HORIZON_COLOR: [f32; 3] = [0.6, 0.7, 0.92];
+
+const SUN_COLOR: [f32; 3] = [3.0, 2.85, 2.55];
+
+// rays start this far off the surface they leave so they don't hit it again
+const EPSILON: f32 = 1e-3;
+
+// rays give up after passing through this many transparent blocks
+const MAX_TRANSPARENT_HITS: usize = 16;
+
+pub struct TraceSettings {
+    pub width: u32,
+    pub height: u32,
+    /// paths traced for every pixel
+    pub samples: u32,
+    /// how many times light can bounce off blocks before reaching the camera
+    pub bounces: u32,
+    /// points towards the sun
+    pub sun_direction: [f32; 3],
+    /// the angular radius of the sun in turns, larger suns cast softer shadows
+    pub sun_radius: f32,
+    /// the vertical field of view in turns
+    pub fov: f32,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        TraceSettings {
+            width: 1920,
+            height: 1080,
+            samples: 64,
+            bounces: 2,
+            sun_direction: [0.4, 0.8, 0.3],
+            sun_radius: 0.01,
+            fov: 1.0 / 4.0,
+        }
+    }
+}
+
+/// where the picture is taken from
+pub struct View {
+    pub position: [f32; 3],
+    pub forward: [f32; 3],
+    pub right: [f32; 3],
+}
+
+impl View {
+    pub fn from_camera<C: Camera>(camera: &C) -> Self {
+        // the translation moves the world away from the camera
+        let translation = camera.get_view_translation()[3];
+        View {
+            position: [-translation[0], -translation[1], -translation[2]],
+            forward: camera.get_forward_direction(),
+            right: camera.get_right_direction(),
+        }
+    }
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn mul(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    scale(a, 1.0 / dot(a, a).sqrt())
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    c.powf(2.2)
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    c.max(0.0).powf(1.0 / 2.2).min(1.0)
+}
+
+/// two directions perpendicular to `n` and each other
+fn basis(n: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+    let helper = if n[0].abs() > 0.9 {
+        [0.0, 1.0, 0.0]
+    } else {
+        [1.0, 0.0, 0.0]
+    };
+    let t = normalize(cross(n, helper));
+    (t, cross(n, t))
+}
+
+// xorshift64*, good enough for picking directions
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1)
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545F4914F6CDD1D) >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// a direction around `n`, more likely the closer it is to `n`
+    fn cosine_weighted(&mut self, n: [f32; 3]) -> [f32; 3] {
+        let (t, b) = basis(n);
+        let (r1, r2) = (self.next(), self.next());
+        let r = r1.sqrt();
+        let (sin, cos) = (TAU * r2).sin_cos();
+
+        normalize(add(
+            add(scale(t, r * cos), scale(b, r * sin)),
+            scale(n, (1.0 - r1).sqrt()),
+        ))
+    }
+
+    /// a direction inside the cone around `axis` with the given cosine
+    fn in_cone(&mut self, axis: [f32; 3], cos_max: f32) -> [f32; 3] {
+        let (t, b) = basis(axis);
+        let cos_theta = 1.0 - self.next() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let (sin, cos) = (TAU * self.next()).sin_cos();
+
+        normalize(add(
+            add(scale(t, sin_theta * cos), scale(b, sin_theta * sin)),
+            scale(axis, cos_theta),
+        ))
+    }
+}
+
+// the blocks in the smallest box around all of them, stored as their
+// index in block_types() plus one so 0 can mean air
+struct Grid {
+    min: [i32; 3],
+    size: [i32; 3],
+    cells: Vec<u8>,
+}
+
+impl Grid {
+    fn from_chunks(chunks: &[Chunk]) -> Option<Grid> {
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+
+        let blocks = chunks.iter().flat_map(|chunk| {
+            chunk
+                .blocks
+                .iter()
+                .flatten()
+                .flatten()
+                .filter_map(move |block| {
+                    block.map(|block| {
+                        let coords = Chunk::get_global_coords_from_local_coord(
+                            chunk.coordinates,
+                            block.in_chunk_position,
+                        );
+                        (coords, block.block_type)
+                    })
+                })
+        });
+
+        for (coords, _) in blocks.clone() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(coords[axis]);
+                max[axis] = max[axis].max(coords[axis] + 1);
+            }
+        }
+
+        if min[0] == i32::MAX {
+            return None;
+        }
+
+        let size = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+        let mut grid = Grid {
+            min: min,
+            size: size,
+            cells: vec![0; size[0] as usize * size[1] as usize * size[2] as usize],
+        };
+
+        let block_types = block_types();
+        for (coords, block_type) in blocks {
+            let index = grid.index(coords).unwrap();
+            grid.cells[index] = block_types
+                .iter()
+                .position(|t| t.name == block_type.name)
+                .map_or(0, |i| i as u8 + 1);
+        }
+
+        Some(grid)
+    }
+
+    fn index(&self, coords: [i32; 3]) -> Option<usize> {
+        let p = [
+            coords[0] - self.min[0],
+            coords[1] - self.min[1],
+            coords[2] - self.min[2],
+        ];
+        if (0..3).any(|axis| p[axis] < 0 || p[axis] >= self.size[axis]) {
+            return None;
+        }
+
+        Some(((p[1] * self.size[2] + p[2]) * self.size[0] + p[0]) as usize)
+    }
+
+    fn get(&self, coords: [i32; 3]) -> Option<&'static BlockType> {
+        match self.index(coords).map(|i| self.cells[i]) {
+            Some(cell) if cell > 0 => Some(block_types()[cell as usize - 1]),
+            _ => None,
+        }
+    }
+
+    /// how far along the ray it enters and leaves the grid's box
+    fn clip(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<(f32, f32)> {
+        let mut enter = 0.0f32;
+        let mut exit = f32::MAX;
+
+        for axis in 0..3 {
+            let low = self.min[axis] as f32;
+            let high = (self.min[axis] + self.size[axis]) as f32;
+
+            if direction[axis] == 0.0 {
+                if origin[axis] < low || origin[axis] > high {
+                    return None;
+                }
+                continue;
+            }
+
+            let a = (low - origin[axis]) / direction[axis];
+            let b = (high - origin[axis]) / direction[axis];
+            enter = enter.max(a.min(b));
+            exit = exit.min(a.max(b));
+        }
+
+        if enter <= exit {
+            Some((enter, exit))
+        } else {
+            None
+        }
+    }
+}
+
+struct Hit {
+    position: [f32; 3],
+    normal: [f32; 3],
+    albedo: [f32; 3],
+}
+
+struct Tracer<'a> {
+    grid: Option<Grid>,
+    atlas: &'a BlockAtlas,
+    settings: &'a TraceSettings,
+    sun_direction: [f32; 3],
+}
+
+impl<'a> Tracer<'a> {
+    /// the texture colour of a point on a block face, in linear rgb and alpha.
+    /// the uvs are laid out the same way as in `generate_chunkmesh_geometry`
+    fn sample(&self, block_type: &BlockType, normal: [i32; 3], local: [f32; 3]) -> [f32; 4] {
+        let textures = block_type.textures;
+
+        // the texture, whether it's mirrored and where on the face
+        let (name, mirrored, u, v) = match normal {
+            [1, 0, 0] => (textures.right, true, local[2], local[1]),
+            [-1, 0, 0] => (textures.left, false, local[2], local[1]),
+            [0, -1, 0] => (textures.bottom, false, local[2], local[0]),
+            [0, 0, 1] => (textures.front, false, local[0], local[1]),
+            [0, 0, -1] => (textures.back, true, local[0], local[1]),
+            _ => (textures.top, false, local[2], local[0]),
+        };
+        let u = match mirrored {
+            true => 1.0 - u,
+            false => u,
+        };
+
+        let layer = self.atlas.layers().layer(name).unwrap();
+        let tile = &self.atlas.tiles()[layer as usize];
+
+        // the textures are flipped when they're uploaded, so v = 1 is the top row
+        let size = self.atlas.tile_size();
+        let x = ((u * size as f32) as u32).min(size - 1);
+        let y = (((1.0 - v) * size as f32) as u32).min(size - 1);
+
+        let pixel = tile.get_pixel(x, y).0;
+        [
+            srgb_to_linear(pixel[0] as f32 / 255.0),
+            srgb_to_linear(pixel[1] as f32 / 255.0),
+            srgb_to_linear(pixel[2] as f32 / 255.0),
+            pixel[3] as f32 / 255.0,
+        ]
+    }
+
+    /// follows a ray to the first block it hits, see-through pixels of
+    /// transparent blocks let it pass with a chance given by their alpha
+    fn cast(&self, origin: [f32; 3], direction: [f32; 3], rng: &mut Rng) -> Option<Hit> {
+        let grid = self.grid.as_ref()?;
+        let (enter, exit) = grid.clip(origin, direction)?;
+
+        let mut start = add(origin, scale(direction, enter));
+        let mut travelled = enter;
+        let mut skipped: Option<&BlockType> = None;
+
+        for _ in 0..MAX_TRANSPARENT_HITS {
+            let hit = raycast_hit(
+                |p| match grid.get(p) {
+                    Some(t) => skipped.is_none_or(|s| s.name != t.name),
+                    None => false,
+                },
+                start,
+                direction,
+                exit - travelled,
+            )?;
+
+            let block_type = grid.get(hit.block).unwrap();
+            let position = add(start, scale(direction, hit.distance));
+
+            // rays starting inside of a block hit the face they came through
+            let mut normal = hit.normal;
+            if normal == [0, 0, 0] {
+                let axis = (0..3)
+                    .max_by(|&a, &b| direction[a].abs().partial_cmp(&direction[b].abs()).unwrap())
+                    .unwrap();
+                normal[axis] = if direction[axis] > 0.0 { -1 } else { 1 };
+            }
+
+            let local = [
+                (position[0] - hit.block[0] as f32).clamp(0.0, 1.0),
+                (position[1] - hit.block[1] as f32).clamp(0.0, 1.0),
+                (position[2] - hit.block[2] as f32).clamp(0.0, 1.0),
+            ];
+            let color = self.sample(block_type, normal, local);
+
+            if block_type.transparent && rng.next() >= color[3] {
+                start = add(position, scale(direction, EPSILON));
+                travelled += hit.distance + EPSILON;
+                skipped = Some(block_type);
+                continue;
+            }
+
+            return Some(Hit {
+                position: position,
+                normal: [normal[0] as f32, normal[1] as f32, normal[2] as f32],
+                albedo: [color[0], color[1], color[2]],
+            });
+        }
+
+        None
+    }
+
+    fn sky(&self, direction: [f32; 3]) -> [f32; 3] {
+        let t = direction[1].clamp(0.0, 1.0);
+        let mut color = [0.0; 3];
+        for i in 0..3 {
+            color[i] = srgb_to_linear(HORIZON_COLOR[i] + (SKY_COLOR[i] - HORIZON_COLOR[i]) * t);
+        }
+        color
+    }
+
+    /// the light arriving at `origin` from `direction`
+    fn radiance(
+        &self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        rng: &mut Rng,
+        depth: u32,
+    ) -> [f32; 3] {
+        let hit = match self.cast(origin, direction, rng) {
+            Some(hit) => hit,
+            None => return self.sky(direction),
+        };
+
+        let surface = add(hit.position, scale(hit.normal, EPSILON));
+        let mut light = [0.0; 3];
+
+        // picking a random point on the sun's disc for the shadow
+        // ray blurs the edges of shadows
+        let cos_max = (self.settings.sun_radius * TAU).cos();
+        let to_sun = rng.in_cone(self.sun_direction, cos_max);
+        let cos = dot(hit.normal, to_sun);
+        if cos > 0.0 && self.cast(surface, to_sun, rng).is_none() {
+            light = add(light, scale(SUN_COLOR, cos));
+        }
+
+        if depth < self.settings.bounces {
+            let bounce = rng.cosine_weighted(hit.normal);
+            light = add(light, self.radiance(surface, bounce, rng, depth + 1));
+        }
+
+        mul(hit.albedo, light)
+    }
+
+    fn render_row(&self, view: &View, y: u32) -> Vec<[u8; 4]> {
+        let settings = self.settings;
+        let mut rng = Rng::new(y as u64 + 1);
+
+        let forward = normalize(view.forward);
+        let right = normalize(view.right);
+        let up = cross(forward, right);
+
+        let half_height = (settings.fov * TAU / 2.0).tan();
+        let half_width = half_height * settings.width as f32 / settings.height as f32;
+
+        (0..settings.width)
+            .map(|x| {
+                let mut sum = [0.0; 3];
+
+                for _ in 0..settings.samples {
+                    let sx = ((x as f32 + rng.next()) / settings.width as f32) * 2.0 - 1.0;
+                    let sy = 1.0 - ((y as f32 + rng.next()) / settings.height as f32) * 2.0;
+
+                    let direction = normalize(add(
+                        forward,
+                        add(scale(right, sx * half_width), scale(up, sy * half_height)),
+                    ));
+                    sum = add(sum, self.radiance(view.position, direction, &mut rng, 0));
+                }
+
+                let color = scale(sum, 1.0 / settings.samples as f32);
+                [
+                    (linear_to_srgb(color[0]) * 255.0).round() as u8,
+                    (linear_to_srgb(color[1]) * 255.0).round() as u8,
+                    (linear_to_srgb(color[2]) * 255.0).round() as u8,
+                    255,
+                ]
+            })
+            .collect()
+    }
+}
+
+/// renders the chunks as seen from `view`, the rows of the
+/// image are spread over all of the cpu's cores
+pub fn render(
+    chunks: &[Chunk],
+    atlas: &BlockAtlas,
+    view: &View,
+    settings: &TraceSettings,
+) -> RgbaImage {
+    let tracer = Tracer {
+        grid: Grid::from_chunks(chunks),
+        atlas: atlas,
+        settings: settings,
+        sun_direction: normalize(settings.sun_direction),
+    };
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()) as u32;
+    let mut image = RgbaImage::new(settings.width, settings.height);
+
+    let rows: Vec<(u32, Vec<[u8; 4]>)> = thread::scope(|scope| {
+        let tracer = &tracer;
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                scope.spawn(move || {
+                    (thread..settings.height)
+                        .step_by(threads as usize)
+                        .map(|y| (y, tracer.render_row(view, y)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    for (y, row) in rows {
+        for (x, pixel) in row.into_iter().enumerate() {
+            image.put_pixel(x as u32, y, image::Rgba(pixel));
+        }
+    }
+
+    image
+}
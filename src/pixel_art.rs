@@ -0,0 +1,96 @@
+//! turning images into walls and floors of blocks, every pixel becomes
+//! the block whose average colour is the closest to it
+
+use image::RgbaImage;
+
+use crate::block::colors::BlockColors;
+use crate::volume::Volume;
+
+// pixels that are more see-through than this are left empty
+const ALPHA_THRESHOLD: u8 = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// lying on the ground, the top of the image points towards -z
+    Floor,
+    /// standing up along the x axis, the top of the image points up
+    Wall,
+}
+
+/// converts the image into blocks, with `dither` the difference between
+/// a pixel and its block is spread onto the pixels next to it using
+/// floyd-steinberg dithering so gradients keep their shading
+pub fn image_to_volume(
+    image: &RgbaImage,
+    colors: &BlockColors,
+    orientation: Orientation,
+    dither: bool,
+) -> Volume {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    let mut pixels: Vec<[f32; 3]> = image
+        .pixels()
+        .map(|p| {
+            [
+                p.0[0] as f32 / 255.0,
+                p.0[1] as f32 / 255.0,
+                p.0[2] as f32 / 255.0,
+            ]
+        })
+        .collect();
+
+    let mut volume = match orientation {
+        Orientation::Floor => Volume::new([width, 1, height]),
+        Orientation::Wall => Volume::new([width, height, 1]),
+    };
+
+    for row in 0..height {
+        for column in 0..width {
+            if image.get_pixel(column as u32, row as u32).0[3] < ALPHA_THRESHOLD {
+                continue;
+            }
+
+            let pixel = pixels[row * width + column];
+            let block_type = colors.closest(pixel);
+
+            let coords = match orientation {
+                Orientation::Floor => [column, 0, row],
+                Orientation::Wall => [column, height - 1 - row, 0],
+            };
+            volume.set(coords, Some(block_type));
+
+            if !dither {
+                continue;
+            }
+
+            let color = colors.get(block_type);
+            let error = [
+                pixel[0] - color[0],
+                pixel[1] - color[1],
+                pixel[2] - color[2],
+            ];
+
+            for &(dx, dy, weight) in [
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0),
+                (0, 1, 5.0 / 16.0),
+                (1, 1, 1.0 / 16.0),
+            ]
+            .iter()
+            {
+                let x = column as i64 + dx;
+                let y = row as i64 + dy;
+                if x < 0 || x >= width as i64 || y >= height as i64 {
+                    continue;
+                }
+
+                let neighbour = &mut pixels[y as usize * width + x as usize];
+                for channel in 0..3 {
+                    neighbour[channel] += error[channel] * weight;
+                }
+            }
+        }
+    }
+
+    volume
+}
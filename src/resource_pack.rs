@@ -0,0 +1,306 @@
+//! resource packs, directories or zip archives with assets that replace
+//! the ones the game comes with
+//!
+//! a pack keeps its assets at the paths they have in the game directory,
+//! like `shaders/chunk/color.frag`, `textures/blocks/dirt.png` or
+//! `block_names.txt`, at the top of the archive for zipped packs. packs
+//! are ordered from the highest priority to the lowest and every asset is
+//! taken from the first one that has it. the game directory comes after
+//! the packs, and after that the assets built into the game. zip archives
+//! are read when the packs are opened, so changes to them only show up
+//! once the packs are opened again.
+//!
+//! packs replace block textures, shaders and the block name table used for
+//! imports and exports, and change the properties of the block types and
+//! which textures they use with the definitions in `blocks.txt`, see
+//! block/definitions.rs. unlike the other assets, definitions aren't taken
+//! from the first pack that has them, each property comes from the highest
+//! priority pack that sets it. packs can't add block types of their own.
+
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use log::*;
+
+use crate::formats::zip::ZipArchive;
+
+/// where packs are looked for, each one is a directory or a `.zip`
+pub const RESOURCE_PACKS_DIR: &str = "resourcepacks";
+
+/// the names of the packs in use, one per line from the highest priority
+pub const RESOURCE_PACKS_FILE: &str = "resource_packs.txt";
+
+enum PackSource {
+    Directory(PathBuf),
+    Zip(ZipArchive),
+}
+
+pub struct ResourcePack {
+    name: String,
+    source: PackSource,
+}
+
+impl ResourcePack {
+    /// opens a directory or a zip archive in the resource packs
+    /// directory, zip archives are named without their extension
+    pub fn open(name: &str) -> Result<Self, Box<dyn Error>> {
+        check_name(name)?;
+
+        let dir = Path::new(RESOURCE_PACKS_DIR).join(name);
+        let zip = Path::new(RESOURCE_PACKS_DIR).join(format!("{}.zip", name));
+
+        let source = if dir.is_dir() {
+            PackSource::Directory(dir)
+        } else if zip.is_file() {
+            let archive =
+                ZipArchive::read(&zip).map_err(|e| format!("{}: {}", zip.display(), e))?;
+            PackSource::Zip(archive)
+        } else {
+            return Err(format!(
+                "there is no resource pack called \"{}\" in {}",
+                name, RESOURCE_PACKS_DIR
+            )
+            .into());
+        };
+
+        Ok(ResourcePack {
+            name: name.to_string(),
+            source: source,
+        })
+    }
+
+    /// the lowest priority pack, the assets next to the game
+    fn game_directory() -> Self {
+        ResourcePack {
+            name: "the game directory".to_string(),
+            source: PackSource::Directory(PathBuf::from(".")),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// None if the pack doesn't have the asset
+    pub fn read(&self, asset: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match &self.source {
+            PackSource::Directory(dir) => match fs::read(dir.join(asset)) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            PackSource::Zip(archive) => archive.read_file(asset),
+        }
+    }
+
+    /// the names of the files directly inside of a directory of the pack
+    pub fn files(&self, dir: &str) -> Vec<String> {
+        match &self.source {
+            PackSource::Directory(pack_dir) => match fs::read_dir(pack_dir.join(dir)) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_file())
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .collect(),
+                Err(_) => vec![],
+            },
+            PackSource::Zip(archive) => {
+                let prefix = format!("{}/", dir.trim_end_matches('/'));
+                archive
+                    .names()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .filter(|name| !name.contains('/'))
+                    .map(|name| name.to_string())
+                    .collect()
+            }
+        }
+    }
+}
+
+/// packs are looked up in the resource packs directory, so their
+/// names can't lead out of it
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains("..")
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c == ':' || c.is_control());
+
+    if !valid {
+        return Err(format!(
+            "invalid resource pack name \"{}\", it can't be empty, start with '.' \
+             or have path separators or \"..\" in it",
+            name
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// the names of every pack in the resource packs directory
+pub fn available() -> Result<Vec<String>, Box<dyn Error>> {
+    let entries = match fs::read_dir(RESOURCE_PACKS_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut names = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if path.is_dir() => name,
+            Some(name) if path.extension().is_some_and(|e| e == "zip") => {
+                name.trim_end_matches(".zip")
+            }
+            _ => continue,
+        };
+        names.push(name.to_string());
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+/// the packs in use, the game directory always comes last
+pub struct ResourcePacks {
+    packs: Vec<ResourcePack>,
+}
+
+impl Default for ResourcePacks {
+    fn default() -> Self {
+        ResourcePacks {
+            packs: vec![ResourcePack::game_directory()],
+        }
+    }
+}
+
+impl ResourcePacks {
+    /// opens the packs with the given names, the first has the highest priority
+    pub fn open(names: &[&str]) -> Result<Self, Box<dyn Error>> {
+        let mut packs = vec![];
+        for &name in names {
+            packs.push(ResourcePack::open(name)?);
+        }
+        packs.push(ResourcePack::game_directory());
+
+        Ok(ResourcePacks { packs: packs })
+    }
+
+    /// opens the packs listed in the resource packs file, packs
+    /// that can't be opened are left out
+    pub fn load() -> Self {
+        let list = match fs::read_to_string(RESOURCE_PACKS_FILE) {
+            Ok(list) => list,
+            Err(_) => return Self::default(),
+        };
+
+        let mut packs = vec![];
+        for name in list.lines().map(|line| line.trim()) {
+            if name.is_empty() || name.starts_with('#') {
+                continue;
+            }
+
+            match ResourcePack::open(name) {
+                Ok(pack) => packs.push(pack),
+                Err(e) => error!("{}", e),
+            }
+        }
+        packs.push(ResourcePack::game_directory());
+
+        ResourcePacks { packs: packs }
+    }
+
+    /// writes the names of the packs in use to the resource packs file
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let mut list = String::new();
+        for name in self.names() {
+            list += name;
+            list += "\n";
+        }
+
+        fs::write(RESOURCE_PACKS_FILE, list)?;
+        Ok(())
+    }
+
+    /// opens the same packs again, to pick up changes to zip archives
+    pub fn reopen(&self) -> Result<Self, Box<dyn Error>> {
+        Self::open(&self.names())
+    }
+
+    /// the names of the packs, without the game directory
+    pub fn names(&self) -> Vec<&str> {
+        self.packs[..self.packs.len() - 1]
+            .iter()
+            .map(|pack| pack.name())
+            .collect()
+    }
+
+    /// every pack, ending with the game directory
+    pub fn packs(&self) -> &[ResourcePack] {
+        &self.packs
+    }
+
+    /// reads an asset from the first pack that has it, None if none
+    /// of them do. packs that fail to read it are skipped
+    pub fn read(&self, asset: &str) -> Option<Vec<u8>> {
+        for pack in self.packs.iter() {
+            match pack.read(asset) {
+                Ok(Some(bytes)) => {
+                    debug!("loaded {} from {}", asset, pack.name());
+                    return Some(bytes);
+                }
+                Ok(None) => (),
+                Err(e) => error!("failed to read {} from {}: {}", asset, pack.name(), e),
+            }
+        }
+
+        None
+    }
+
+    pub fn read_to_string(&self, asset: &str) -> Option<String> {
+        let bytes = self.read(asset)?;
+        match String::from_utf8(bytes) {
+            Ok(text) => Some(text),
+            Err(e) => {
+                error!("{}: {}", asset, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_names_stay_in_the_resource_packs_directory() {
+        for name in [
+            "",
+            ".",
+            "..",
+            ".hidden",
+            "../saves",
+            "a/../../b",
+            "nested/pack",
+            "nested\\pack",
+            "/etc",
+            "C:pack",
+        ] {
+            assert!(check_name(name).is_err(), "{:?}", name);
+            assert!(ResourcePack::open(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn ordinary_pack_names_are_allowed() {
+        for name in ["faithful", "Faithful 32x", "pack-v1.2_final"] {
+            assert!(check_name(name).is_ok(), "{:?}", name);
+        }
+    }
+}
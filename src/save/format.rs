@@ -0,0 +1,413 @@
+//! the binary format of a single chunk record
+//!
+//! every record starts with a header, all integers are little endian:
+//! - the magic bytes `RC`
+//! - `version: u16`, the format version the body was written with
+//!
+//! the body of the current version (1) is:
+//! - `chunk_size: u8`, the edge length of the chunk in blocks
+//! - `status: u8`, see `status_to_byte`
+//! - `palette_len: u16`, followed by that many block type names, each
+//!   one stored as a `u8` length and that many utf-8 bytes
+//! - `chunk_size³` `u16` block entries in x, y, z order (z changes the
+//!   fastest), 0 is air and `n` is the `n - 1`th palette entry
+//! - `chunk_size³` `u8` skylight levels in the same order
+//!
+//! the coordinates of the chunk are not part of the record since
+//! they are implied by where the record is stored.
+//!
+//! records from older versions are brought up to date by running them
+//! through `MIGRATIONS` one version at a time before they are decoded.
+//! whenever the body layout changes, bump `FORMAT_VERSION` and add a
+//! migration from the previous version.
+
+use std::convert::TryInto;
+use std::error::Error;
+
+use crate::block::types::block_type_by_name;
+use crate::block::{Block, BlockType};
+use crate::chunk::*;
+
+const BLOCK_COUNT: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+const RECORD_MAGIC: &[u8; 2] = b"RC";
+pub const FORMAT_VERSION: u16 = 1;
+
+/// turns a record body into the body of the next version
+pub type Upgrade = fn(Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>>;
+
+/// upgrades a record body from version `from` to `from + 1`
+pub struct Migration {
+    pub from: u16,
+    pub upgrade: Upgrade,
+}
+
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    upgrade: upgrade_v0,
+}];
+
+/// version 0 records were written before records had a header,
+/// their body is the version 1 body without the chunk size, which
+/// was always 32 at the time
+fn upgrade_v0(body: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut upgraded = Vec::with_capacity(body.len() + 1);
+    upgraded.push(32);
+    upgraded.extend(body);
+    Ok(upgraded)
+}
+
+fn status_to_byte(status: ChunkStatus) -> u8 {
+    match status {
+        ChunkStatus::Empty => 0,
+        ChunkStatus::Shaped => 1,
+        ChunkStatus::Carved => 2,
+        ChunkStatus::Surfaced => 3,
+        ChunkStatus::Decorated => 4,
+        // meshes aren't saved, so a meshed chunk has to be meshed again on load
+        ChunkStatus::Lit | ChunkStatus::Meshed => 5,
+    }
+}
+
+fn status_from_byte(byte: u8) -> Result<ChunkStatus, Box<dyn Error>> {
+    Ok(match byte {
+        0 => ChunkStatus::Empty,
+        1 => ChunkStatus::Shaped,
+        2 => ChunkStatus::Carved,
+        3 => ChunkStatus::Surfaced,
+        4 => ChunkStatus::Decorated,
+        5 => ChunkStatus::Lit,
+        _ => return Err(format!("invalid chunk status {}", byte).into()),
+    })
+}
+
+/// serializes the contents of a chunk in the current format version
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut palette: Vec<&'static BlockType> = vec![];
+    let mut indices = Vec::with_capacity(BLOCK_COUNT * 2);
+
+    for plane in chunk.blocks.iter() {
+        for row in plane.iter() {
+            for block in row.iter() {
+                // 0 is reserved for air
+                let index = match block {
+                    None => 0,
+                    Some(block) => {
+                        match palette.iter().position(|t| t.name == block.block_type.name) {
+                            Some(i) => i + 1,
+                            None => {
+                                palette.push(block.block_type);
+                                palette.len()
+                            }
+                        }
+                    }
+                };
+
+                indices.extend_from_slice(&(index as u16).to_le_bytes());
+            }
+        }
+    }
+
+    let mut bytes = RECORD_MAGIC.to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+    bytes.push(CHUNK_SIZE_U8);
+    bytes.push(status_to_byte(chunk.status));
+
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for block_type in palette.iter() {
+        bytes.push(block_type.name.len() as u8);
+        bytes.extend_from_slice(block_type.name.as_bytes());
+    }
+
+    bytes.extend(indices);
+
+    for plane in chunk.skylight.iter() {
+        for row in plane.iter() {
+            bytes.extend_from_slice(row);
+        }
+    }
+
+    bytes
+}
+
+/// reads a little endian u16 from the front of the
+/// slice and advances the slice past it
+fn take_u16(bytes: &mut &[u8]) -> Result<u16, Box<dyn Error>> {
+    let value = take(bytes, 2)?;
+    Ok(u16::from_le_bytes(value.try_into().unwrap()))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    if bytes.len() < count {
+        return Err("unexpected end of chunk data".into());
+    }
+
+    let (taken, rest) = bytes.split_at(count);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// splits a record into its format version and body
+fn split_header(record: &[u8]) -> Result<(u16, &[u8]), Box<dyn Error>> {
+    if !record.starts_with(RECORD_MAGIC) {
+        // records without a header start with their status, which is
+        // never the first byte of the magic
+        return Ok((0, record));
+    }
+
+    let mut rest = &record[RECORD_MAGIC.len()..];
+    let version = take_u16(&mut rest)?;
+    Ok((version, rest))
+}
+
+/// upgrades a record body to the current format version
+fn migrate(version: u16, body: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "chunk was saved with format version {}, newer than the supported version {}",
+            version, FORMAT_VERSION
+        )
+        .into());
+    }
+
+    let mut body = body.to_vec();
+
+    for from in version..FORMAT_VERSION {
+        let migration = match MIGRATIONS.iter().find(|m| m.from == from) {
+            Some(migration) => migration,
+            None => return Err(format!("no migration from format version {}", from).into()),
+        };
+
+        body = (migration.upgrade)(body)?;
+    }
+
+    Ok(body)
+}
+
+/// deserializes a chunk record of any known format version
+pub fn decode_chunk(coords: [i32; 3], record: &[u8]) -> Result<Chunk, Box<dyn Error>> {
+    let (version, body) = split_header(record)?;
+    let body = migrate(version, body)?;
+
+    let bytes = &mut &body[..];
+    let mut chunk = Chunk::empty(coords);
+
+    let chunk_size = take(bytes, 1)?[0];
+    if chunk_size != CHUNK_SIZE_U8 {
+        return Err(format!(
+            "chunk has a size of {} blocks, expected {}",
+            chunk_size, CHUNK_SIZE
+        )
+        .into());
+    }
+
+    chunk.status = status_from_byte(take(bytes, 1)?[0])?;
+
+    let palette_len = take_u16(bytes)?;
+    let mut palette = vec![];
+    for _ in 0..palette_len {
+        let name_len = take(bytes, 1)?[0] as usize;
+        let name = std::str::from_utf8(take(bytes, name_len)?)?;
+
+        match block_type_by_name(name) {
+            Some(block_type) => palette.push(block_type),
+            None => return Err(format!("unknown block type \"{}\"", name).into()),
+        }
+    }
+
+    for x in 0..CHUNK_SIZE_U8 {
+        for y in 0..CHUNK_SIZE_U8 {
+            for z in 0..CHUNK_SIZE_U8 {
+                let index = take_u16(bytes)? as usize;
+
+                *chunk.get_block_mut([x, y, z]) = match index {
+                    0 => None,
+                    i => match palette.get(i - 1) {
+                        Some(block_type) => Some(Block::new([x, y, z], block_type)),
+                        None => return Err(format!("invalid palette index {}", i).into()),
+                    },
+                };
+            }
+        }
+    }
+
+    for plane in chunk.skylight.iter_mut() {
+        for row in plane.iter_mut() {
+            row.copy_from_slice(take(bytes, CHUNK_SIZE)?);
+        }
+    }
+
+    if !bytes.is_empty() {
+        return Err(format!("{} trailing bytes after chunk data", bytes.len()).into());
+    }
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::types::*;
+
+    fn block_name(chunk: &Chunk, coords: [u8; 3]) -> Option<&'static str> {
+        chunk.get_block(coords).map(|b| b.block_type.name)
+    }
+
+    fn assert_same_contents(a: &Chunk, b: &Chunk) {
+        assert_eq!(a.coordinates, b.coordinates);
+        for x in 0..CHUNK_SIZE_U8 {
+            for y in 0..CHUNK_SIZE_U8 {
+                for z in 0..CHUNK_SIZE_U8 {
+                    assert_eq!(block_name(a, [x, y, z]), block_name(b, [x, y, z]));
+                    assert_eq!(
+                        a.skylight[x as usize][y as usize][z as usize],
+                        b.skylight[x as usize][y as usize][z as usize]
+                    );
+                }
+            }
+        }
+    }
+
+    fn mixed_chunk() -> Chunk {
+        let mut chunk = Chunk::empty([3, -1, 7]);
+        chunk.status = ChunkStatus::Decorated;
+
+        for x in 0..CHUNK_SIZE_U8 {
+            for y in 0..CHUNK_SIZE_U8 {
+                for z in 0..CHUNK_SIZE_U8 {
+                    let block_type = match (x as usize + y as usize * 3 + z as usize * 7) % 5 {
+                        0 => continue,
+                        1 => &GRASS_BLOCK,
+                        2 => &DIRT_BLOCK,
+                        3 => &STONE_BLOCK,
+                        _ => &GLASS_BLOCK,
+                    };
+                    *chunk.get_block_mut([x, y, z]) = Some(Block::new([x, y, z], block_type));
+                    chunk.skylight[x as usize][y as usize][z as usize] = (x ^ y ^ z) % 16;
+                }
+            }
+        }
+
+        chunk
+    }
+
+    /// builds a record the way builds before the version header wrote them
+    fn encode_v0(chunk: &Chunk) -> Vec<u8> {
+        let current = encode_chunk(chunk);
+        // drop the header and the chunk size
+        current[RECORD_MAGIC.len() + 2 + 1..].to_vec()
+    }
+
+    #[test]
+    fn empty_chunk_round_trips() {
+        let chunk = Chunk::empty([0, 0, 0]);
+        let decoded = decode_chunk(chunk.coordinates, &encode_chunk(&chunk)).unwrap();
+
+        assert_eq!(decoded.status, ChunkStatus::Empty);
+        assert_same_contents(&chunk, &decoded);
+    }
+
+    #[test]
+    fn filled_chunk_round_trips() {
+        let chunk = Chunk::filled([-4, 2, 9], &STONE_BLOCK);
+        let decoded = decode_chunk(chunk.coordinates, &encode_chunk(&chunk)).unwrap();
+
+        assert_eq!(decoded.status, chunk.status);
+        assert_same_contents(&chunk, &decoded);
+    }
+
+    #[test]
+    fn mixed_chunk_round_trips() {
+        let chunk = mixed_chunk();
+        let decoded = decode_chunk(chunk.coordinates, &encode_chunk(&chunk)).unwrap();
+
+        assert_eq!(decoded.status, ChunkStatus::Decorated);
+        assert_same_contents(&chunk, &decoded);
+    }
+
+    #[test]
+    fn block_positions_are_restored() {
+        let decoded = decode_chunk([0, 0, 0], &encode_chunk(&mixed_chunk())).unwrap();
+
+        let block = decoded.get_block([4, 5, 6]).unwrap();
+        assert_eq!(block.in_chunk_position, [4, 5, 6]);
+    }
+
+    #[test]
+    fn every_status_round_trips() {
+        let mut status = Some(ChunkStatus::Empty);
+
+        while let Some(current) = status {
+            let mut chunk = Chunk::empty([0, 0, 0]);
+            chunk.status = current;
+
+            let decoded = decode_chunk([0, 0, 0], &encode_chunk(&chunk)).unwrap();
+            let expected = match current {
+                ChunkStatus::Meshed => ChunkStatus::Lit,
+                other => other,
+            };
+            assert_eq!(decoded.status, expected);
+
+            status = current.next();
+        }
+    }
+
+    #[test]
+    fn records_start_with_the_current_version() {
+        let record = encode_chunk(&Chunk::empty([0, 0, 0]));
+        let (version, _) = split_header(&record).unwrap();
+
+        assert_eq!(version, FORMAT_VERSION);
+    }
+
+    #[test]
+    fn every_old_version_has_a_migration() {
+        for from in 0..FORMAT_VERSION {
+            assert!(MIGRATIONS.iter().any(|m| m.from == from));
+        }
+    }
+
+    #[test]
+    fn version_0_records_are_migrated() {
+        let chunk = mixed_chunk();
+        let decoded = decode_chunk(chunk.coordinates, &encode_v0(&chunk)).unwrap();
+
+        assert_eq!(decoded.status, chunk.status);
+        assert_same_contents(&chunk, &decoded);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut record = encode_chunk(&Chunk::empty([0, 0, 0]));
+        record[2..4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        assert!(decode_chunk([0, 0, 0], &record).is_err());
+    }
+
+    #[test]
+    fn truncated_records_are_rejected() {
+        let record = encode_chunk(&mixed_chunk());
+
+        assert!(decode_chunk([0, 0, 0], &record[..record.len() - 1]).is_err());
+        assert!(decode_chunk([0, 0, 0], &record[..10]).is_err());
+        assert!(decode_chunk([0, 0, 0], &[]).is_err());
+    }
+
+    #[test]
+    fn unknown_block_types_are_rejected() {
+        let mut record = encode_chunk(&Chunk::filled([0, 0, 0], &STONE_BLOCK));
+        let name_start = RECORD_MAGIC.len() + 2 + 1 + 1 + 2 + 1;
+        record[name_start] = b'x';
+
+        assert!(decode_chunk([0, 0, 0], &record).is_err());
+    }
+
+    #[test]
+    fn mismatched_chunk_sizes_are_rejected() {
+        let mut record = encode_chunk(&Chunk::empty([0, 0, 0]));
+        record[RECORD_MAGIC.len() + 2] = 16;
+
+        assert!(decode_chunk([0, 0, 0], &record).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! the world metadata file is a plain `key=value` text file so
+//! that it can be inspected and edited by hand
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::terraingen::GeneratorSettings;
+
+pub struct WorldMetadata {
+    pub generator: GeneratorSettings,
+    /// width and depth of the generated area in blocks
+    pub size: (usize, usize),
+    pub camera_position: [f32; 3],
+    pub camera_rotation: [f32; 3],
+}
+
+fn format_list<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_list<T: FromStr>(value: &str) -> Result<Vec<T>, Box<dyn Error>>
+where
+    T::Err: Error + 'static,
+{
+    let mut parsed = vec![];
+    for v in value.split_whitespace() {
+        parsed.push(v.parse()?);
+    }
+    Ok(parsed)
+}
+
+fn parse_array3(value: &str) -> Result<[f32; 3], Box<dyn Error>> {
+    match parse_list::<f32>(value)?[..] {
+        [x, y, z] => Ok([x, y, z]),
+        _ => Err(format!("expected 3 values, got \"{}\"", value).into()),
+    }
+}
+
+impl WorldMetadata {
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut lines = vec![];
+
+        match &self.generator {
+            GeneratorSettings::Flat { height } => {
+                lines.push("generator=flat".to_string());
+                lines.push(format!("height={}", height));
+            }
+            GeneratorSettings::Opensimplex {
+                seed,
+                coord_scaling,
+                offset,
+                amplitude,
+            } => {
+                lines.push("generator=opensimplex".to_string());
+                if let Some(seed) = seed {
+                    lines.push(format!("seed={}", seed));
+                }
+                lines.push(format!(
+                    "coord_scaling={}",
+                    format_list(&[coord_scaling.0, coord_scaling.1])
+                ));
+                lines.push(format!("offset={}", offset));
+                lines.push(format!("amplitude={}", amplitude));
+            }
+        }
+
+        lines.push(format!("size={}", format_list(&[self.size.0, self.size.1])));
+        lines.push(format!(
+            "camera_position={}",
+            format_list(&self.camera_position)
+        ));
+        lines.push(format!(
+            "camera_rotation={}",
+            format_list(&self.camera_rotation)
+        ));
+
+        super::write_atomically(path, (lines.join("\n") + "\n").as_bytes())
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+
+        let values: HashMap<&str, &str> = contents
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, '=');
+                Some((parts.next()?.trim(), parts.next()?.trim()))
+            })
+            .collect();
+
+        let get = |key: &str| -> Result<&str, Box<dyn Error>> {
+            match values.get(key) {
+                Some(v) => Ok(v),
+                None => Err(format!("world metadata is missing \"{}\"", key).into()),
+            }
+        };
+
+        let generator = match get("generator")? {
+            "flat" => GeneratorSettings::Flat {
+                height: get("height")?.parse()?,
+            },
+            "opensimplex" => {
+                let coord_scaling = parse_list::<f64>(get("coord_scaling")?)?;
+                if coord_scaling.len() != 2 {
+                    return Err("coord_scaling needs 2 values".into());
+                }
+
+                GeneratorSettings::Opensimplex {
+                    seed: match values.get("seed") {
+                        Some(seed) => Some(seed.parse()?),
+                        None => None,
+                    },
+                    coord_scaling: (coord_scaling[0], coord_scaling[1]),
+                    offset: get("offset")?.parse()?,
+                    amplitude: get("amplitude")?.parse()?,
+                }
+            }
+            other => return Err(format!("unknown generator \"{}\"", other).into()),
+        };
+
+        let size = parse_list::<usize>(get("size")?)?;
+        if size.len() != 2 {
+            return Err("size needs 2 values".into());
+        }
+
+        Ok(WorldMetadata {
+            generator: generator,
+            size: (size[0], size[1]),
+            camera_position: parse_array3(get("camera_position")?)?,
+            camera_rotation: parse_array3(get("camera_rotation")?)?,
+        })
+    }
+}
@@ -0,0 +1,328 @@
+//! saving and loading worlds
+//!
+//! a world is a directory containing a metadata file and a directory
+//! of region files, see `region` for the layout of those
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use log::*;
+
+use crate::chunk::{Chunk, ChunkStatus};
+use crate::world::generation;
+
+pub mod format;
+pub mod metadata;
+pub mod patch;
+pub mod region;
+pub mod snapshot;
+
+use metadata::WorldMetadata;
+use region::{region_from_path, region_of, region_path, Region};
+
+const METADATA_FILE: &str = "world.txt";
+const REGION_DIR: &str = "region";
+
+/// writes a file by writing a temporary file next to it and renaming that
+/// into place, so a crash while saving never leaves a half written file
+pub fn write_atomically<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    {
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&temporary, path)
+}
+
+pub struct WorldSave {
+    dir: PathBuf,
+}
+
+impl WorldSave {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        WorldSave {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// checks if there is a world saved in the directory
+    pub fn exists(&self) -> bool {
+        self.dir.join(METADATA_FILE).is_file()
+    }
+
+    pub fn read_metadata(&self) -> Result<WorldMetadata, Box<dyn Error>> {
+        WorldMetadata::read(self.dir.join(METADATA_FILE))
+    }
+
+    pub fn write_metadata(&self, metadata: &WorldMetadata) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        metadata.write(self.dir.join(METADATA_FILE))
+    }
+
+    /// writes every chunk that lies inside one of the given regions,
+    /// the region files are rewritten completely
+    pub fn save_regions(
+        &self,
+        chunks: &[Chunk],
+        regions: &HashSet<[i32; 3]>,
+    ) -> Result<(), Box<dyn Error>> {
+        let region_dir = self.dir.join(REGION_DIR);
+        fs::create_dir_all(&region_dir)?;
+
+        for &region_coords in regions.iter() {
+            let mut region = Region::empty();
+
+            for chunk in chunks
+                .iter()
+                .filter(|c| region_of(c.coordinates) == region_coords)
+            {
+                region.set(chunk.coordinates, format::encode_chunk(chunk));
+            }
+
+            debug!("writing region {:?}", region_coords);
+            region.write(region_path(&region_dir, region_coords))?;
+        }
+
+        Ok(())
+    }
+
+    fn read_region(&self, region_coords: [i32; 3]) -> Option<Region> {
+        let path = region_path(self.dir.join(REGION_DIR), region_coords);
+        if !path.is_file() {
+            return None;
+        }
+
+        match Region::read(&path) {
+            Ok(region) => Some(region),
+            Err(e) => {
+                warn!(
+                    "failed to read {}, its chunks will be regenerated: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// loads the chunks at the given coordinates, the ones that aren't
+    /// saved are returned as None. so are corrupted ones, after logging a
+    /// warning, so that they get generated again
+    pub fn load_chunks(&self, coords: &[[i32; 3]]) -> Vec<Option<Chunk>> {
+        let mut regions: HashMap<[i32; 3], Option<Region>> = HashMap::new();
+        let mut chunks = vec![];
+
+        for &chunk_coords in coords.iter() {
+            let region = regions
+                .entry(region_of(chunk_coords))
+                .or_insert_with(|| self.read_region(region_of(chunk_coords)));
+
+            let record = match region.as_ref().map(|r| r.get(chunk_coords)) {
+                Some(Ok(Some(record))) => record,
+                Some(Err(e)) => {
+                    warn!(
+                        "chunk {:?} is corrupted, it will be regenerated: {}",
+                        chunk_coords, e
+                    );
+                    chunks.push(None);
+                    continue;
+                }
+                Some(Ok(None)) | None => {
+                    chunks.push(None);
+                    continue;
+                }
+            };
+
+            match format::decode_chunk(chunk_coords, record) {
+                Ok(chunk) => chunks.push(Some(chunk)),
+                Err(e) => {
+                    warn!(
+                        "failed to load chunk {:?}, it will be regenerated: {}",
+                        chunk_coords, e
+                    );
+                    chunks.push(None);
+                }
+            }
+        }
+
+        chunks
+    }
+
+    /// the coordinates of every region that has a region file
+    fn saved_regions(&self) -> Result<Vec<[i32; 3]>, Box<dyn Error>> {
+        let region_dir = self.dir.join(REGION_DIR);
+        if !region_dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut regions = vec![];
+        for entry in fs::read_dir(region_dir)? {
+            if let Some(region_coords) = region_from_path(entry?.path()) {
+                regions.push(region_coords);
+            }
+        }
+
+        Ok(regions)
+    }
+
+    /// unlike `read_region` this fails if the region file can't be read
+    fn open_region(&self, region_coords: [i32; 3]) -> Result<Option<Region>, Box<dyn Error>> {
+        let path = region_path(self.dir.join(REGION_DIR), region_coords);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        match Region::read(&path) {
+            Ok(region) => Ok(Some(region)),
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e).into()),
+        }
+    }
+
+    /// the coordinates of every chunk stored in the region files
+    pub fn saved_chunks(&self) -> Result<Vec<[i32; 3]>, Box<dyn Error>> {
+        let mut chunks = vec![];
+
+        for region_coords in self.saved_regions()? {
+            if let Some(region) = self.open_region(region_coords)? {
+                chunks.extend(region.stored_chunks(region_coords));
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// decodes every saved chunk one at a time, so the whole world never
+    /// has to be in memory at once. corrupted chunks are skipped
+    pub fn for_each_saved_chunk<F: FnMut(Chunk)>(&self, mut f: F) -> Result<(), Box<dyn Error>> {
+        for region_coords in self.saved_regions()? {
+            let region = match self.open_region(region_coords)? {
+                Some(region) => region,
+                None => continue,
+            };
+
+            for chunk_coords in region.stored_chunks(region_coords) {
+                let decoded = match region.get(chunk_coords) {
+                    Ok(Some(record)) => format::decode_chunk(chunk_coords, record),
+                    Ok(None) => continue,
+                    Err(e) => Err(e.into()),
+                };
+
+                match decoded {
+                    Ok(chunk) => f(chunk),
+                    Err(e) => warn!("skipping corrupted chunk {:?}: {}", chunk_coords, e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// writes the given chunks into their region files, unlike
+    /// `save_regions` the other chunks stored there are kept
+    pub fn save_chunks(&self, chunks: &[&Chunk]) -> Result<(), Box<dyn Error>> {
+        let region_dir = self.dir.join(REGION_DIR);
+        fs::create_dir_all(&region_dir)?;
+
+        let mut regions: HashMap<[i32; 3], Region> = HashMap::new();
+        for chunk in chunks.iter() {
+            let region_coords = region_of(chunk.coordinates);
+            let region = match regions.entry(region_coords) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    // an unreadable region file would lose all of its
+                    // chunks if it was overwritten, so it's left alone
+                    let path = region_path(&region_dir, region_coords);
+                    let region = if path.is_file() {
+                        Region::read(&path)
+                            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+                    } else {
+                        Region::empty()
+                    };
+                    entry.insert(region)
+                }
+            };
+
+            region.set(chunk.coordinates, format::encode_chunk(chunk));
+        }
+
+        for (region_coords, region) in regions {
+            debug!("writing region {:?}", region_coords);
+            region.write(region_path(&region_dir, region_coords))?;
+        }
+
+        Ok(())
+    }
+
+    /// drops the saved chunks whose light depends on the chunks at the
+    /// given coordinates back to being decorated, after their blocks were
+    /// changed outside of the game, so the game lights them again
+    pub fn invalidate_light(&self, coords: &[[i32; 3]]) -> Result<(), Box<dyn Error>> {
+        let dependent: Vec<[i32; 3]> = self
+            .saved_chunks()?
+            .into_iter()
+            .filter(|&saved| {
+                coords
+                    .iter()
+                    .any(|&changed| generation::light_depends_on(saved, changed))
+            })
+            .collect();
+
+        let mut unlit = vec![];
+        for mut chunk in self.load_chunks(&dependent).into_iter().flatten() {
+            if chunk.status > ChunkStatus::Decorated {
+                chunk.status = ChunkStatus::Decorated;
+                unlit.push(chunk);
+            }
+        }
+
+        self.save_chunks(&unlit.iter().collect::<Vec<_>>())
+    }
+
+    /// removes chunks from their region files so they're generated
+    /// again, region files without any chunks left are deleted
+    pub fn remove_chunks(&self, coords: &[[i32; 3]]) -> Result<(), Box<dyn Error>> {
+        let region_dir = self.dir.join(REGION_DIR);
+
+        let mut regions: HashMap<[i32; 3], Vec<[i32; 3]>> = HashMap::new();
+        for &chunk_coords in coords.iter() {
+            regions
+                .entry(region_of(chunk_coords))
+                .or_default()
+                .push(chunk_coords);
+        }
+
+        for (region_coords, chunks) in regions {
+            let path = region_path(&region_dir, region_coords);
+            if !path.is_file() {
+                continue;
+            }
+
+            let mut region = Region::read(&path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            for chunk_coords in chunks {
+                region.remove(chunk_coords);
+            }
+
+            if region.stored_chunks(region_coords).is_empty() {
+                debug!("deleting region {:?}", region_coords);
+                fs::remove_file(&path)?;
+            } else {
+                debug!("writing region {:?}", region_coords);
+                region.write(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
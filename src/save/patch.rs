@@ -0,0 +1,615 @@
+//! comparing two saved worlds and carrying the differences over to
+//! another world
+//!
+//! a patch file starts with the magic bytes `RMC1PTCH`, followed by a
+//! zlib compressed body, all integers are little endian:
+//! - `palette_len: u16`, followed by that many block type names, each
+//!   one stored as a `u8` length and that many utf-8 bytes
+//! - `chunk_count: u32`, followed by that many chunks. a chunk is its
+//!   coordinates as three `i32`s, `change_count: u32` and that many
+//!   `(block: u16, palette_entry: u16)` pairs. `block` is the index of the
+//!   block in x, y, z order (z changes the fastest), a palette entry of 0
+//!   is air and `n` is the `n - 1`th palette entry
+//!
+//! patches only contain what the blocks became, not what they were, so
+//! they can be applied to any world, not just the one they were made from
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use log::*;
+
+use super::region::{region_of, Region};
+use super::{format, write_atomically, WorldSave};
+use crate::block::types::block_type_by_name;
+use crate::chunk::*;
+use crate::terraingen::TerrainGenerator;
+use crate::world::generation;
+
+const PATCH_MAGIC: &[u8; 8] = b"RMC1PTCH";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// a block that differs between two worlds, None is air
+#[derive(Copy, Clone, Debug)]
+pub struct BlockChange {
+    pub block: [u8; 3],
+    pub before: Option<&'static BlockType>,
+    pub after: Option<&'static BlockType>,
+}
+
+impl BlockChange {
+    pub fn kind(&self) -> ChangeKind {
+        match (self.before, self.after) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+            _ => ChangeKind::Changed,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChangeCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+impl ChangeCounts {
+    fn add(&mut self, kind: ChangeKind) {
+        match kind {
+            ChangeKind::Added => self.added += 1,
+            ChangeKind::Removed => self.removed += 1,
+            ChangeKind::Changed => self.changed += 1,
+        }
+    }
+}
+
+pub struct ChunkDiff {
+    pub coordinates: [i32; 3],
+    pub changes: Vec<BlockChange>,
+}
+
+impl ChunkDiff {
+    pub fn counts(&self) -> ChangeCounts {
+        let mut counts = ChangeCounts::default();
+        for change in self.changes.iter() {
+            counts.add(change.kind());
+        }
+        counts
+    }
+}
+
+pub struct WorldDiff {
+    /// the chunks with changes, sorted by their coordinates
+    pub chunks: Vec<ChunkDiff>,
+    /// how many chunks were skipped because their blocks are the same
+    pub unchanged: usize,
+}
+
+impl WorldDiff {
+    pub fn counts(&self) -> ChangeCounts {
+        let mut counts = ChangeCounts::default();
+        for change in self.chunks.iter().flat_map(|c| c.changes.iter()) {
+            counts.add(change.kind());
+        }
+        counts
+    }
+
+    /// a patch that turns the old world into the new one
+    pub fn to_patch(&self) -> Patch {
+        Patch {
+            chunks: self
+                .chunks
+                .iter()
+                .map(|chunk| ChunkPatch {
+                    coordinates: chunk.coordinates,
+                    blocks: chunk.changes.iter().map(|c| (c.block, c.after)).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// the record of a chunk, corrupted records are treated like missing ones
+/// since the game generates those chunks again too
+fn get_record(region: &Option<Region>, chunk_coords: [i32; 3]) -> Option<&[u8]> {
+    match region.as_ref()?.get(chunk_coords) {
+        Ok(record) => record,
+        Err(e) => {
+            warn!(
+                "chunk {:?} is corrupted, comparing it as generated: {}",
+                chunk_coords, e
+            );
+            None
+        }
+    }
+}
+
+fn generate(chunk_coords: [i32; 3], generator: &dyn TerrainGenerator) -> Chunk {
+    let mut chunks = vec![Chunk::empty(chunk_coords)];
+    generation::regenerate(&mut chunks, 0, generator);
+    chunks.pop().unwrap()
+}
+
+fn decode_or_generate(
+    record: Option<&[u8]>,
+    chunk_coords: [i32; 3],
+    generator: &dyn TerrainGenerator,
+) -> Chunk {
+    match record.map(|r| format::decode_chunk(chunk_coords, r)) {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => {
+            warn!(
+                "failed to load chunk {:?}, comparing it as generated: {}",
+                chunk_coords, e
+            );
+            generate(chunk_coords, generator)
+        }
+        None => generate(chunk_coords, generator),
+    }
+}
+
+fn diff_chunks(old: &Chunk, new: &Chunk) -> ChunkDiff {
+    let mut changes = vec![];
+
+    for x in 0..CHUNK_SIZE_U8 {
+        for y in 0..CHUNK_SIZE_U8 {
+            for z in 0..CHUNK_SIZE_U8 {
+                let before = old.get_block([x, y, z]).map(|b| b.block_type);
+                let after = new.get_block([x, y, z]).map(|b| b.block_type);
+
+                if before.map(|t| t.name) != after.map(|t| t.name) {
+                    changes.push(BlockChange {
+                        block: [x, y, z],
+                        before: before,
+                        after: after,
+                    });
+                }
+            }
+        }
+    }
+
+    ChunkDiff {
+        coordinates: old.coordinates,
+        changes: changes,
+    }
+}
+
+/// compares every chunk that is saved in at least one of the worlds, one
+/// region at a time. a chunk that only one world has saved is compared
+/// against what the other world's generator makes there
+pub fn diff(old: &WorldSave, new: &WorldSave) -> Result<WorldDiff, Box<dyn Error>> {
+    let old_generator = old.read_metadata()?.generator.build();
+    let new_generator = new.read_metadata()?.generator.build();
+
+    let mut regions = old.saved_regions()?;
+    regions.extend(new.saved_regions()?);
+    regions.sort();
+    regions.dedup();
+
+    let mut diff = WorldDiff {
+        chunks: vec![],
+        unchanged: 0,
+    };
+
+    for region_coords in regions {
+        debug!("comparing region {:?}", region_coords);
+
+        let old_region = old.open_region(region_coords)?;
+        let new_region = new.open_region(region_coords)?;
+
+        let mut chunks: Vec<[i32; 3]> = old_region
+            .iter()
+            .chain(new_region.iter())
+            .flat_map(|r| r.stored_chunks(region_coords))
+            .collect();
+        chunks.sort();
+        chunks.dedup();
+
+        for chunk_coords in chunks {
+            let old_record = get_record(&old_region, chunk_coords);
+            let new_record = get_record(&new_region, chunk_coords);
+
+            // identical records don't even need to be decoded
+            if old_record.is_some() && old_record == new_record {
+                diff.unchanged += 1;
+                continue;
+            }
+
+            let old_chunk = decode_or_generate(old_record, chunk_coords, old_generator.as_ref());
+            let new_chunk = decode_or_generate(new_record, chunk_coords, new_generator.as_ref());
+
+            // light and status don't matter, only the blocks do
+            if old_chunk.content_hash() == new_chunk.content_hash() {
+                diff.unchanged += 1;
+                continue;
+            }
+
+            let chunk_diff = diff_chunks(&old_chunk, &new_chunk);
+            if chunk_diff.changes.is_empty() {
+                diff.unchanged += 1;
+            } else {
+                diff.chunks.push(chunk_diff);
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+pub struct ChunkPatch {
+    pub coordinates: [i32; 3],
+    /// the blocks to replace and what to replace them with, None is air
+    pub blocks: Vec<([u8; 3], Option<&'static BlockType>)>,
+}
+
+pub struct Patch {
+    pub chunks: Vec<ChunkPatch>,
+}
+
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    if bytes.len() < count {
+        return Err("unexpected end of patch".into());
+    }
+
+    let (taken, rest) = bytes.split_at(count);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn take_u16(bytes: &mut &[u8]) -> Result<u16, Box<dyn Error>> {
+    Ok(u16::from_le_bytes(take(bytes, 2)?.try_into().unwrap()))
+}
+
+fn take_u32(bytes: &mut &[u8]) -> Result<u32, Box<dyn Error>> {
+    Ok(u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()))
+}
+
+fn take_i32(bytes: &mut &[u8]) -> Result<i32, Box<dyn Error>> {
+    Ok(i32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()))
+}
+
+impl Patch {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        write_atomically(path, &self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut palette: Vec<&'static BlockType> = vec![];
+        let mut chunks = vec![];
+
+        chunks.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        for chunk in self.chunks.iter() {
+            for coordinate in chunk.coordinates.iter() {
+                chunks.extend_from_slice(&coordinate.to_le_bytes());
+            }
+            chunks.extend_from_slice(&(chunk.blocks.len() as u32).to_le_bytes());
+
+            for &([x, y, z], block_type) in chunk.blocks.iter() {
+                let block = (x as usize * CHUNK_SIZE + y as usize) * CHUNK_SIZE + z as usize;
+
+                // 0 is reserved for air
+                let entry = match block_type {
+                    None => 0,
+                    Some(block_type) => {
+                        match palette.iter().position(|t| t.name == block_type.name) {
+                            Some(i) => i + 1,
+                            None => {
+                                palette.push(block_type);
+                                palette.len()
+                            }
+                        }
+                    }
+                };
+
+                chunks.extend_from_slice(&(block as u16).to_le_bytes());
+                chunks.extend_from_slice(&(entry as u16).to_le_bytes());
+            }
+        }
+
+        let mut body = vec![];
+        body.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for block_type in palette.iter() {
+            body.push(block_type.name.len() as u8);
+            body.extend_from_slice(block_type.name.as_bytes());
+        }
+        body.extend(chunks);
+
+        let mut encoder = ZlibEncoder::new(PATCH_MAGIC.to_vec(), Compression::default());
+        encoder.write_all(&body)?;
+        Ok(encoder.finish()?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if !bytes.starts_with(PATCH_MAGIC) {
+            return Err("not a patch file".into());
+        }
+
+        let mut body = vec![];
+        ZlibDecoder::new(&bytes[PATCH_MAGIC.len()..]).read_to_end(&mut body)?;
+        let bytes = &mut &body[..];
+
+        let palette_len = take_u16(bytes)?;
+        let mut palette = vec![];
+        for _ in 0..palette_len {
+            let name_len = take(bytes, 1)?[0] as usize;
+            let name = std::str::from_utf8(take(bytes, name_len)?)?;
+
+            match block_type_by_name(name) {
+                Some(block_type) => palette.push(block_type),
+                None => return Err(format!("unknown block type \"{}\"", name).into()),
+            }
+        }
+
+        let chunk_count = take_u32(bytes)?;
+        let mut chunks = vec![];
+        for _ in 0..chunk_count {
+            let coordinates = [take_i32(bytes)?, take_i32(bytes)?, take_i32(bytes)?];
+            let change_count = take_u32(bytes)?;
+
+            let mut blocks = vec![];
+            for _ in 0..change_count {
+                let block = take_u16(bytes)? as usize;
+                if block >= CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+                    return Err(format!("invalid block index {}", block).into());
+                }
+                let block = [
+                    (block / (CHUNK_SIZE * CHUNK_SIZE)) as u8,
+                    (block / CHUNK_SIZE % CHUNK_SIZE) as u8,
+                    (block % CHUNK_SIZE) as u8,
+                ];
+
+                let block_type = match take_u16(bytes)? as usize {
+                    0 => None,
+                    i => match palette.get(i - 1) {
+                        Some(block_type) => Some(*block_type),
+                        None => return Err(format!("invalid palette entry {}", i).into()),
+                    },
+                };
+
+                blocks.push((block, block_type));
+            }
+
+            chunks.push(ChunkPatch {
+                coordinates: coordinates,
+                blocks: blocks,
+            });
+        }
+
+        if !bytes.is_empty() {
+            return Err(format!("{} trailing bytes after patch", bytes.len()).into());
+        }
+
+        Ok(Patch { chunks: chunks })
+    }
+
+    /// replaces the blocks in the world, one region at a time. chunks the
+    /// world hasn't saved yet are generated first.
+    ///
+    /// returns the amount of blocks that were replaced
+    pub fn apply(&self, save: &WorldSave) -> Result<usize, Box<dyn Error>> {
+        let generator = save.read_metadata()?.generator.build();
+
+        let mut regions: BTreeMap<[i32; 3], Vec<&ChunkPatch>> = BTreeMap::new();
+        for chunk in self.chunks.iter() {
+            regions
+                .entry(region_of(chunk.coordinates))
+                .or_default()
+                .push(chunk);
+        }
+
+        let mut replaced = 0;
+
+        for (region_coords, patches) in regions {
+            debug!("patching region {:?}", region_coords);
+
+            let coords: Vec<[i32; 3]> = patches.iter().map(|p| p.coordinates).collect();
+            let mut chunks = vec![];
+
+            for (patch, chunk) in patches.iter().zip(save.load_chunks(&coords)) {
+                let mut chunk =
+                    chunk.unwrap_or_else(|| generate(patch.coordinates, generator.as_ref()));
+
+                for &(block, block_type) in patch.blocks.iter() {
+                    *chunk.get_block_mut(block) = block_type.map(|t| Block::new(block, t));
+                }
+                replaced += patch.blocks.len();
+                chunks.push(chunk);
+            }
+
+            save.save_chunks(&chunks.iter().collect::<Vec<_>>())?;
+        }
+
+        // the saved light doesn't fit the new blocks anymore, going back a
+        // stage makes the game light the chunks, and the ones below, again
+        let patched: Vec<[i32; 3]> = self.chunks.iter().map(|c| c.coordinates).collect();
+        save.invalidate_light(&patched)?;
+
+        Ok(replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::types::*;
+    use crate::save::metadata::WorldMetadata;
+    use crate::terraingen::GeneratorSettings;
+
+    fn encode(body: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(PATCH_MAGIC.to_vec(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// a patch body with a stone palette and one change in chunk 0 0 0
+    fn body_with_change(block: u16, entry: u16) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.push(STONE_BLOCK.name.len() as u8);
+        body.extend_from_slice(STONE_BLOCK.name.as_bytes());
+        body.extend_from_slice(&1u32.to_le_bytes());
+        body.extend_from_slice(&[0; 12]);
+        body.extend_from_slice(&1u32.to_le_bytes());
+        body.extend_from_slice(&block.to_le_bytes());
+        body.extend_from_slice(&entry.to_le_bytes());
+        body
+    }
+
+    fn names(chunk: &ChunkPatch) -> Vec<([u8; 3], Option<&'static str>)> {
+        chunk
+            .blocks
+            .iter()
+            .map(|&(block, block_type)| (block, block_type.map(|t| t.name)))
+            .collect()
+    }
+
+    /// an empty world in the temporary directory, deleted again when dropped
+    struct TestWorld {
+        dir: std::path::PathBuf,
+        save: WorldSave,
+    }
+
+    impl TestWorld {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "rmc1-patch-test-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+
+            let save = WorldSave::new(&dir);
+            save.write_metadata(&WorldMetadata {
+                generator: GeneratorSettings::Flat { height: 10 },
+                size: (64, 64),
+                camera_position: [0.0; 3],
+                camera_rotation: [0.0; 3],
+            })
+            .unwrap();
+
+            TestWorld {
+                dir: dir,
+                save: save,
+            }
+        }
+    }
+
+    impl Drop for TestWorld {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn patches_round_trip() {
+        let patch = Patch {
+            chunks: vec![
+                ChunkPatch {
+                    coordinates: [-3, 0, 7],
+                    blocks: vec![([0, 0, 0], Some(&STONE_BLOCK)), ([31, 2, 9], None)],
+                },
+                ChunkPatch {
+                    coordinates: [1, 15, -1],
+                    blocks: vec![
+                        ([4, 5, 6], Some(&GLASS_BLOCK)),
+                        ([1, 1, 1], Some(&STONE_BLOCK)),
+                    ],
+                },
+            ],
+        };
+
+        let read = Patch::from_bytes(&patch.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(read.chunks.len(), 2);
+        for (read, written) in read.chunks.iter().zip(patch.chunks.iter()) {
+            assert_eq!(read.coordinates, written.coordinates);
+            assert_eq!(names(read), names(written));
+        }
+    }
+
+    #[test]
+    fn valid_changes_are_read() {
+        let patch = Patch::from_bytes(&encode(&body_with_change(32767, 1))).unwrap();
+
+        assert_eq!(
+            names(&patch.chunks[0]),
+            vec![([31, 31, 31], Some(STONE_BLOCK.name))]
+        );
+    }
+
+    #[test]
+    fn invalid_block_indices_are_rejected() {
+        assert!(Patch::from_bytes(&encode(&body_with_change(32768, 1))).is_err());
+        assert!(Patch::from_bytes(&encode(&body_with_change(u16::MAX, 0))).is_err());
+    }
+
+    #[test]
+    fn invalid_palette_entries_are_rejected() {
+        assert!(Patch::from_bytes(&encode(&body_with_change(0, 2))).is_err());
+
+        let mut body = body_with_change(0, 1);
+        body[3] = b'x';
+        assert!(Patch::from_bytes(&encode(&body)).is_err());
+    }
+
+    #[test]
+    fn damaged_patches_are_rejected() {
+        let body = body_with_change(0, 1);
+
+        assert!(Patch::from_bytes(&encode(&body[..body.len() - 1])).is_err());
+        assert!(Patch::from_bytes(&encode(&[&body[..], &[0]].concat())).is_err());
+        assert!(Patch::from_bytes(&body).is_err());
+    }
+
+    #[test]
+    fn applying_a_diff_turns_one_world_into_the_other() {
+        let old = TestWorld::new("old");
+        let new = TestWorld::new("new");
+        let generator = GeneratorSettings::Flat { height: 10 }.build();
+
+        let mut lit = generate([0, 0, 0], generator.as_ref());
+        lit.status = ChunkStatus::Lit;
+        let plain = generate([1, 0, 0], generator.as_ref());
+        old.save.save_chunks(&[&lit, &plain]).unwrap();
+
+        // a block placed, one dug out and a chunk only the new world has
+        let mut placed = generate([0, 0, 0], generator.as_ref());
+        *placed.get_block_mut([3, 12, 3]) = Some(Block::new([3, 12, 3], &GLASS_BLOCK));
+        let mut dug = generate([1, 0, 0], generator.as_ref());
+        *dug.get_block_mut([5, 5, 5]) = None;
+        let mut added = generate([0, 1, 0], generator.as_ref());
+        *added.get_block_mut([0, 0, 0]) = Some(Block::new([0, 0, 0], &STONE_BLOCK));
+        new.save.save_chunks(&[&placed, &dug, &added]).unwrap();
+
+        let patch = diff(&old.save, &new.save).unwrap().to_patch();
+        let patch = Patch::from_bytes(&patch.to_bytes().unwrap()).unwrap();
+        assert_eq!(patch.apply(&old.save).unwrap(), 3);
+
+        let remaining = diff(&old.save, &new.save).unwrap();
+        assert!(remaining.chunks.is_empty());
+        assert_eq!(remaining.unchanged, 3);
+
+        // the light of the patched world has to be worked out again
+        let patched = old.save.load_chunks(&[[0, 0, 0]]).pop().unwrap().unwrap();
+        assert_eq!(patched.status, ChunkStatus::Decorated);
+    }
+}
@@ -0,0 +1,333 @@
+//! region files group a block of neighbouring chunks into a single file.
+//!
+//! layout, all integers are little endian:
+//! - the magic bytes `RMC1REG2`
+//! - an offset table with one `(offset: u32, length: u32, crc32: u32)` entry
+//!   per chunk slot, an offset of 0 means that the chunk isn't stored
+//! - the zlib compressed chunk records the offset table points at, see
+//!   `format` for what a record contains. the checksum covers the
+//!   compressed bytes of the record
+//!
+//! region files written before checksums were added start with
+//! `RMC1REGN` and have table entries without the checksum.
+
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+pub const REGION_WIDTH: i32 = 32;
+pub const REGION_HEIGHT: i32 = 16;
+
+const REGION_SLOTS: usize = (REGION_WIDTH * REGION_HEIGHT * REGION_WIDTH) as usize;
+const REGION_MAGIC: &[u8; 8] = b"RMC1REG2";
+const UNCHECKED_REGION_MAGIC: &[u8; 8] = b"RMC1REGN";
+const ENTRY_SIZE: usize = 12;
+const UNCHECKED_ENTRY_SIZE: usize = 8;
+
+/// the coordinates of the region that contains the given chunk
+pub fn region_of(chunk: [i32; 3]) -> [i32; 3] {
+    [
+        chunk[0].div_euclid(REGION_WIDTH),
+        chunk[1].div_euclid(REGION_HEIGHT),
+        chunk[2].div_euclid(REGION_WIDTH),
+    ]
+}
+
+fn slot_of(chunk: [i32; 3]) -> usize {
+    let x = chunk[0].rem_euclid(REGION_WIDTH);
+    let y = chunk[1].rem_euclid(REGION_HEIGHT);
+    let z = chunk[2].rem_euclid(REGION_WIDTH);
+
+    ((x * REGION_HEIGHT + y) * REGION_WIDTH + z) as usize
+}
+
+/// the crc32 of a compressed record
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+pub fn region_path<P: AsRef<Path>>(region_dir: P, region: [i32; 3]) -> PathBuf {
+    region_dir
+        .as_ref()
+        .join(format!("r.{}.{}.{}.rmr", region[0], region[1], region[2]))
+}
+
+/// the coordinates of a region from the name of its file, see `region_path`
+pub fn region_from_path<P: AsRef<Path>>(path: P) -> Option<[i32; 3]> {
+    let name = path.as_ref().file_name()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.strip_suffix(".rmr")?.split('.');
+
+    let region = [
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    ];
+
+    match parts.next() {
+        Some(_) => None,
+        None => Some(region),
+    }
+}
+
+enum Record {
+    Missing,
+    Stored(Vec<u8>),
+    Corrupted(String),
+}
+
+/// the uncompressed chunk records of a single region
+pub struct Region {
+    records: Vec<Record>,
+}
+
+impl Region {
+    pub fn empty() -> Self {
+        let mut records = Vec::with_capacity(REGION_SLOTS);
+        records.resize_with(REGION_SLOTS, || Record::Missing);
+
+        Region { records: records }
+    }
+
+    /// returns the record of a chunk if it is stored, or
+    /// the reason it couldn't be read if it is corrupted
+    pub fn get(&self, chunk: [i32; 3]) -> Result<Option<&[u8]>, String> {
+        match &self.records[slot_of(chunk)] {
+            Record::Missing => Ok(None),
+            Record::Stored(record) => Ok(Some(record)),
+            Record::Corrupted(reason) => Err(reason.clone()),
+        }
+    }
+
+    pub fn set(&mut self, chunk: [i32; 3], record: Vec<u8>) {
+        self.records[slot_of(chunk)] = Record::Stored(record);
+    }
+
+    pub fn remove(&mut self, chunk: [i32; 3]) {
+        self.records[slot_of(chunk)] = Record::Missing;
+    }
+
+    /// the chunks of the region at the given coordinates that have a
+    /// record, corrupted records count too
+    pub fn stored_chunks(&self, region: [i32; 3]) -> Vec<[i32; 3]> {
+        let mut chunks = vec![];
+
+        for x in 0..REGION_WIDTH {
+            for y in 0..REGION_HEIGHT {
+                for z in 0..REGION_WIDTH {
+                    let chunk = [
+                        region[0] * REGION_WIDTH + x,
+                        region[1] * REGION_HEIGHT + y,
+                        region[2] * REGION_WIDTH + z,
+                    ];
+
+                    match self.records[slot_of(chunk)] {
+                        Record::Missing => (),
+                        _ => chunks.push(chunk),
+                    }
+                }
+            }
+        }
+
+        chunks
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        super::write_atomically(path, &self.to_bytes()?)
+    }
+
+    fn read_record(compressed: &[u8], checksum: Option<u32>) -> Record {
+        if let Some(checksum) = checksum {
+            let actual = crc32(compressed);
+            if actual != checksum {
+                return Record::Corrupted(format!(
+                    "checksum mismatch, expected {:08x} but got {:08x}",
+                    checksum, actual
+                ));
+            }
+        }
+
+        let mut record = vec![];
+        match ZlibDecoder::new(compressed).read_to_end(&mut record) {
+            Ok(_) => Record::Stored(record),
+            Err(e) => Record::Corrupted(format!("failed to decompress: {}", e)),
+        }
+    }
+
+    /// only fails if the header is unreadable, damaged
+    /// records are kept track of individually
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let entry_size = match bytes.get(..REGION_MAGIC.len()) {
+            Some(magic) if magic == REGION_MAGIC => ENTRY_SIZE,
+            Some(magic) if magic == UNCHECKED_REGION_MAGIC => UNCHECKED_ENTRY_SIZE,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a region file",
+                ))
+            }
+        };
+
+        let header_size = REGION_MAGIC.len() + REGION_SLOTS * entry_size;
+        if bytes.len() < header_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "region file header is truncated",
+            ));
+        }
+
+        let mut region = Self::empty();
+
+        for (slot, entry) in bytes[REGION_MAGIC.len()..header_size]
+            .chunks_exact(entry_size)
+            .enumerate()
+        {
+            let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap()) as usize;
+            let length = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+            let checksum = match entry_size {
+                ENTRY_SIZE => Some(u32::from_le_bytes(entry[8..12].try_into().unwrap())),
+                _ => None,
+            };
+
+            if offset == 0 {
+                continue;
+            }
+
+            region.records[slot] = match bytes.get(offset..offset + length) {
+                Some(compressed) => Self::read_record(compressed, checksum),
+                None => Record::Corrupted("record lies outside of the file".into()),
+            };
+        }
+
+        Ok(region)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let header_size = REGION_MAGIC.len() + REGION_SLOTS * ENTRY_SIZE;
+
+        let mut table = Vec::with_capacity(REGION_SLOTS * ENTRY_SIZE);
+        let mut payload = vec![];
+
+        for record in self.records.iter() {
+            match record {
+                Record::Stored(record) => {
+                    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                    encoder.write_all(record)?;
+                    let compressed = encoder.finish()?;
+
+                    let offset = header_size + payload.len();
+                    table.extend_from_slice(&(offset as u32).to_le_bytes());
+                    table.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                    table.extend_from_slice(&crc32(&compressed).to_le_bytes());
+                    payload.extend(compressed);
+                }
+                // a corrupted record is dropped so the chunk gets regenerated
+                Record::Missing | Record::Corrupted(_) => table.extend_from_slice(&[0; ENTRY_SIZE]),
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(header_size + payload.len());
+        bytes.extend_from_slice(REGION_MAGIC);
+        bytes.extend(table);
+        bytes.extend(payload);
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region_with(chunks: &[[i32; 3]]) -> Region {
+        let mut region = Region::empty();
+        for (i, &chunk) in chunks.iter().enumerate() {
+            region.set(chunk, vec![i as u8; 100 + i]);
+        }
+
+        region
+    }
+
+    /// where the record of a chunk starts in the bytes of a region
+    fn record_offset(bytes: &[u8], chunk: [i32; 3]) -> usize {
+        let entry = REGION_MAGIC.len() + slot_of(chunk) * ENTRY_SIZE;
+        u32::from_le_bytes(bytes[entry..entry + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let region = region_with(&[[0, 0, 0], [5, 3, 31]]);
+        let read = Region::from_bytes(&region.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(read.get([0, 0, 0]).unwrap(), Some(&[0; 100][..]));
+        assert_eq!(read.get([5, 3, 31]).unwrap(), Some(&[1; 101][..]));
+        assert_eq!(read.get([1, 0, 0]).unwrap(), None);
+        assert_eq!(read.stored_chunks([0, 0, 0]), vec![[0, 0, 0], [5, 3, 31]]);
+    }
+
+    #[test]
+    fn flipped_bytes_are_reported() {
+        let mut bytes = region_with(&[[0, 0, 0], [5, 3, 31]]).to_bytes().unwrap();
+        let offset = record_offset(&bytes, [5, 3, 31]);
+        bytes[offset + 3] ^= 0xff;
+
+        let read = Region::from_bytes(&bytes).unwrap();
+        assert!(read.get([5, 3, 31]).is_err());
+        assert_eq!(read.get([0, 0, 0]).unwrap(), Some(&[0; 100][..]));
+    }
+
+    #[test]
+    fn truncated_files_are_reported() {
+        let bytes = region_with(&[[0, 0, 0], [5, 3, 31]]).to_bytes().unwrap();
+
+        // the record that was cut off is lost, the rest can still be read
+        let read = Region::from_bytes(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(read.get([5, 3, 31]).is_err());
+        assert_eq!(read.get([0, 0, 0]).unwrap(), Some(&[0; 100][..]));
+
+        assert!(Region::from_bytes(&bytes[..100]).is_err());
+        assert!(Region::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn unchecked_regions_are_read() {
+        let bytes = region_with(&[[2, 1, 0]]).to_bytes().unwrap();
+
+        // the same region without the checksums in the table
+        let mut unchecked = UNCHECKED_REGION_MAGIC.to_vec();
+        let header_size = REGION_MAGIC.len() + REGION_SLOTS * ENTRY_SIZE;
+        let unchecked_header_size = REGION_MAGIC.len() + REGION_SLOTS * UNCHECKED_ENTRY_SIZE;
+        for entry in bytes[REGION_MAGIC.len()..header_size].chunks_exact(ENTRY_SIZE) {
+            let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap()) as usize;
+            let offset = match offset {
+                0 => 0,
+                offset => offset - header_size + unchecked_header_size,
+            };
+            unchecked.extend_from_slice(&(offset as u32).to_le_bytes());
+            unchecked.extend_from_slice(&entry[4..8]);
+        }
+        unchecked.extend_from_slice(&bytes[header_size..]);
+
+        let read = Region::from_bytes(&unchecked).unwrap();
+        assert_eq!(read.get([2, 1, 0]).unwrap(), Some(&[0; 100][..]));
+    }
+
+    #[test]
+    fn region_paths_round_trip() {
+        let path = region_path("regions", [-1, 0, 12]);
+
+        assert_eq!(region_from_path(&path), Some([-1, 0, 12]));
+        assert_eq!(region_from_path("regions/r.1.2.rmr"), None);
+        assert_eq!(region_from_path("regions/r.1.2.3.4.rmr"), None);
+    }
+}
@@ -0,0 +1,394 @@
+//! named snapshots of a saved world
+//!
+//! snapshots live in the `snapshots` directory of the world. every file a
+//! snapshot contains is stored once in `snapshots/objects`, named after the
+//! hash of its contents, so regions that didn't change since an earlier
+//! snapshot don't take up any space again.
+//!
+//! a snapshot itself is a `<name>.txt` file made of `key=value` lines like
+//! the metadata file:
+//! - `created=<seconds since the unix epoch>`
+//! - one `file=<object> <path>` line for every file, the object is the 16
+//!   hex digits of its hash and the path is relative to the world directory
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::*;
+
+use super::region::region_path;
+use super::{write_atomically, WorldSave, METADATA_FILE, REGION_DIR};
+
+const SNAPSHOT_DIR: &str = "snapshots";
+const OBJECT_DIR: &str = "objects";
+
+pub struct SnapshotInfo {
+    pub name: String,
+    /// seconds since the unix epoch
+    pub created: u64,
+    pub files: usize,
+}
+
+impl SnapshotInfo {
+    /// how long ago the snapshot was created, in the largest unit that fits
+    pub fn age(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let seconds = now.saturating_sub(self.created);
+
+        match seconds {
+            0..=59 => format!("{} seconds ago", seconds),
+            60..=3599 => format!("{} minutes ago", seconds / 60),
+            3600..=86399 => format!("{} hours ago", seconds / 3600),
+            _ => format!("{} days ago", seconds / 86400),
+        }
+    }
+}
+
+/// how many of the files in a new snapshot had to be stored and how
+/// many were already stored by earlier snapshots
+pub struct SnapshotStats {
+    pub stored: usize,
+    pub reused: usize,
+}
+
+struct Manifest {
+    created: u64,
+    /// (object, path relative to the world directory)
+    files: Vec<(String, String)>,
+}
+
+/// the name of a snapshot and its manifest, if it could be read
+type NamedManifest = (String, Result<Manifest, Box<dyn Error>>);
+
+impl Manifest {
+    fn write<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut lines = vec![format!("created={}", self.created)];
+        for (object, file) in self.files.iter() {
+            lines.push(format!("file={} {}", object, file));
+        }
+
+        write_atomically(path, (lines.join("\n") + "\n").as_bytes())
+    }
+
+    fn read<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+
+        let mut created = None;
+        let mut files = vec![];
+
+        for line in contents.lines() {
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => continue,
+            };
+
+            match key {
+                "created" => created = Some(value.parse()?),
+                "file" => {
+                    let mut parts = value.splitn(2, ' ');
+                    match (parts.next(), parts.next()) {
+                        // the object ends up in a path, so it has to look like a hash
+                        (Some(object), Some(file)) if is_object_name(object) => {
+                            files.push((object.into(), file.into()))
+                        }
+                        _ => return Err(format!("invalid file entry \"{}\"", value).into()),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        match created {
+            Some(created) => Ok(Manifest {
+                created: created,
+                files: files,
+            }),
+            None => Err("snapshot is missing \"created\"".into()),
+        }
+    }
+}
+
+/// FNV-1a, it only has to tell files apart, and unlike the hasher in the
+/// standard library it gives the same hash in every build of the game
+fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// objects are named after their `content_hash`, as 16 hex digits
+fn is_object_name(name: &str) -> bool {
+    name.len() == 16 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// snapshot names end up as file names, so they're kept simple
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+    if !valid {
+        return Err(format!(
+            "invalid snapshot name \"{}\", only letters, digits, '-', '_' and '.' are allowed",
+            name
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn snapshot_dir(save: &WorldSave) -> PathBuf {
+    save.dir.join(SNAPSHOT_DIR)
+}
+
+fn manifest_path(save: &WorldSave, name: &str) -> PathBuf {
+    snapshot_dir(save).join(format!("{}.txt", name))
+}
+
+fn object_path(save: &WorldSave, object: &str) -> PathBuf {
+    snapshot_dir(save).join(OBJECT_DIR).join(object)
+}
+
+/// the metadata file and every region file, relative to the world directory
+fn world_files(save: &WorldSave) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut files = vec![METADATA_FILE.to_string()];
+
+    for region_coords in save.saved_regions()? {
+        let path = region_path(REGION_DIR, region_coords);
+        files.push(path.to_string_lossy().replace('\\', "/"));
+    }
+
+    Ok(files)
+}
+
+/// stores the current files of the world under the given name
+pub fn create(save: &WorldSave, name: &str) -> Result<SnapshotStats, Box<dyn Error>> {
+    check_name(name)?;
+
+    let manifest_path = manifest_path(save, name);
+    if manifest_path.exists() {
+        return Err(format!("there already is a snapshot called \"{}\"", name).into());
+    }
+
+    fs::create_dir_all(snapshot_dir(save).join(OBJECT_DIR))?;
+
+    let mut stats = SnapshotStats {
+        stored: 0,
+        reused: 0,
+    };
+    let mut manifest = Manifest {
+        created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        files: vec![],
+    };
+
+    for file in world_files(save)? {
+        let contents = fs::read(save.dir.join(&file))?;
+        let object = format!("{:016x}", content_hash(&contents));
+        let path = object_path(save, &object);
+
+        // a different size means the hashes collided, which
+        // is unlikely enough that refusing is good enough
+        match fs::metadata(&path) {
+            Ok(existing) if existing.len() == contents.len() as u64 => stats.reused += 1,
+            Ok(_) => return Err(format!("{} collides with object {}", file, object).into()),
+            Err(_) => {
+                write_atomically(&path, &contents)?;
+                stats.stored += 1;
+            }
+        }
+
+        manifest.files.push((object, file));
+    }
+
+    manifest.write(manifest_path)?;
+    debug!(
+        "created snapshot \"{}\", {} files stored and {} reused",
+        name, stats.stored, stats.reused
+    );
+
+    Ok(stats)
+}
+
+/// the name and manifest of every snapshot, unreadable manifests
+/// are returned as errors so the callers can decide what to do
+fn manifests(save: &WorldSave) -> Result<Vec<NamedManifest>, Box<dyn Error>> {
+    let dir = snapshot_dir(save);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut manifests = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        if let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".txt"))
+        {
+            manifests.push((name.to_string(), Manifest::read(&path)));
+        }
+    }
+
+    Ok(manifests)
+}
+
+/// every snapshot of the world, the oldest one first
+pub fn list(save: &WorldSave) -> Result<Vec<SnapshotInfo>, Box<dyn Error>> {
+    let mut snapshots = vec![];
+
+    for (name, manifest) in manifests(save)? {
+        match manifest {
+            Ok(manifest) => snapshots.push(SnapshotInfo {
+                name: name,
+                created: manifest.created,
+                files: manifest.files.len(),
+            }),
+            Err(e) => warn!("skipping unreadable snapshot \"{}\": {}", name, e),
+        }
+    }
+
+    snapshots.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+    Ok(snapshots)
+}
+
+/// puts the files of a snapshot back in place, region files that didn't
+/// exist when the snapshot was created are removed
+pub fn restore(save: &WorldSave, name: &str) -> Result<(), Box<dyn Error>> {
+    check_name(name)?;
+
+    let manifest = Manifest::read(manifest_path(save, name))
+        .map_err(|e| format!("failed to read snapshot \"{}\": {}", name, e))?;
+
+    // everything is read before anything is written, so a damaged
+    // snapshot doesn't leave the world half restored
+    let mut files = vec![];
+    for (object, file) in manifest.files.iter() {
+        if Path::new(file).is_absolute() || file.split('/').any(|part| part == "..") {
+            return Err(format!("snapshot contains an invalid path \"{}\"", file).into());
+        }
+
+        let contents = fs::read(object_path(save, object))
+            .map_err(|e| format!("failed to read object {} of {}: {}", object, file, e))?;
+        files.push((save.dir.join(file), contents));
+    }
+
+    fs::create_dir_all(save.dir.join(REGION_DIR))?;
+
+    let restored: HashSet<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+    for region_coords in save.saved_regions()? {
+        let path = region_path(save.dir.join(REGION_DIR), region_coords);
+        if !restored.contains(&path) {
+            debug!("removing region {:?}", region_coords);
+            fs::remove_file(path)?;
+        }
+    }
+
+    for (path, contents) in files {
+        debug!("restoring {}", path.display());
+        write_atomically(path, &contents)?;
+    }
+
+    debug!("restored snapshot \"{}\"", name);
+    Ok(())
+}
+
+/// removes a snapshot along with the objects no other snapshot uses
+pub fn delete(save: &WorldSave, name: &str) -> Result<(), Box<dyn Error>> {
+    check_name(name)?;
+
+    let path = manifest_path(save, name);
+    if !path.is_file() {
+        return Err(format!("there is no snapshot called \"{}\"", name).into());
+    }
+
+    // an object is only removed if every other snapshot could be read,
+    // otherwise it might still be needed
+    let mut used = HashSet::new();
+    for (other, manifest) in manifests(save)? {
+        if other == name {
+            continue;
+        }
+
+        match manifest {
+            Ok(manifest) => used.extend(manifest.files.into_iter().map(|(object, _)| object)),
+            Err(e) => return Err(format!("failed to read snapshot \"{}\": {}", other, e).into()),
+        }
+    }
+
+    fs::remove_file(path)?;
+
+    for entry in fs::read_dir(snapshot_dir(save).join(OBJECT_DIR))? {
+        let path = entry?.path();
+        let object = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        if !used.contains(object) {
+            debug!("removing unused object {}", object);
+            fs::remove_file(&path)?;
+        }
+    }
+
+    debug!("deleted snapshot \"{}\"", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_manifest(contents: &str) -> Result<Manifest, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "rmc1-manifest-test-{}-{}.txt",
+            std::process::id(),
+            content_hash(contents.as_bytes())
+        ));
+        fs::write(&path, contents).unwrap();
+
+        let manifest = Manifest::read(&path);
+        fs::remove_file(&path).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn manifests_are_read() {
+        let manifest = read_manifest(
+            "created=12\nfile=0123456789abcdef world.txt\nfile=FEDCBA9876543210 regions/r.0.0.0.rmr\n",
+        )
+        .unwrap();
+
+        assert_eq!(manifest.created, 12);
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(manifest.files[1].1, "regions/r.0.0.0.rmr");
+    }
+
+    #[test]
+    fn object_names_have_to_be_hashes() {
+        for object in [
+            "../../../x",
+            "/etc/passwd",
+            "0123456789abcde",
+            "0123456789abcdefa",
+            "0123456789abcdeg",
+        ]
+        .iter()
+        {
+            let contents = format!("created=0\nfile={} world.txt\n", object);
+            assert!(read_manifest(&contents).is_err(), "{} was accepted", object);
+        }
+    }
+}
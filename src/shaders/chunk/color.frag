@@ -1,16 +1,19 @@
 #version 420
 
                 
-uniform sampler2D atlas;
+uniform sampler2DArray block_textures;
                 
 
 in vec2 v_texcoord;
+flat in float v_layer;
 in vec3 v_normal;
 in vec3 v_local_pos;
+in float v_shade;
 
 out vec4 f_color;
 
 void main() {
-    vec4 basecolor = texture(atlas, v_texcoord);
+    vec4 basecolor = texture(block_textures, vec3(v_texcoord, v_layer));
+    basecolor.rgb *= v_shade;
     f_color = basecolor;
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+#version 420
+
+uniform sampler2DArray block_textures;
+
+in vec2 v_texcoord;
+flat in float v_layer;
+in vec3 v_normal;
+in vec3 v_local_pos;
+in float v_shade;
+
+// the alpha of the accumulation target ends up as the revealage, the
+// product of one minus the alpha of every fragment, see world/oit.rs
+layout(location = 0) out vec4 f_accumulation;
+layout(location = 1) out vec4 f_weight;
+
+void main() {
+    vec4 color = texture(block_textures, vec3(v_texcoord, v_layer));
+    color.rgb *= v_shade;
+
+    // equation 10 from McGuire and Bavoil's "Weighted Blended
+    // Order-Independent Transparency", closer fragments weigh more
+    float depth = gl_FragCoord.z;
+    float weight = clamp(
+        pow(min(1.0, color.a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - depth * 0.9, 3.0),
+        1e-2,
+        3e3
+    );
+
+    f_accumulation = vec4(color.rgb * color.a * weight, color.a);
+    f_weight = vec4(color.a * weight, 0.0, 0.0, color.a);
+}
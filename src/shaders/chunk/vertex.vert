@@ -4,28 +4,46 @@ uniform mat4 projection;
 
 uniform mat4 view_rotation;
 uniform mat4 view_translation;
-uniform mat4 model_rotation;
-uniform mat4 model_translation;
 
-                
+// in the order the faces are packed in, see graphics::ChunkVertex
+const vec3 NORMALS[6] = vec3[](
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0)
+);
 
-layout(location = 0) in vec3 position;
-layout(location = 1) in vec2 uv;
-layout(location = 2) in vec3 normal;
+layout(location = 0) in uvec2 data;
+// where the chunk the vertex belongs to starts, see arena.rs
+layout(location = 1) in vec3 chunk_offset;
 
 out vec2 v_texcoord;
+flat out float v_layer;
 out vec3 v_normal;
 out vec3 v_local_pos;
+out float v_shade;
                 
 void main() {
-    
+    uint bits = data.x;
 
-    vec3 local_vertex_position = (view_rotation * view_translation * model_rotation * model_translation * vec4(position, 1.0)).xyz;
-    gl_Position = projection * view_rotation * view_translation * model_rotation * model_translation * vec4(position, 1.0);
+    vec3 position = vec3(bits & 63u, (bits >> 6) & 63u, (bits >> 12) & 63u);
+    vec3 normal = NORMALS[(bits >> 18) & 7u];
+    vec2 corner = vec2((bits >> 21) & 1u, (bits >> 22) & 1u);
+    float ao = float((bits >> 23) & 3u);
+    float light = float((bits >> 25) & 15u);
 
+    vec4 world_position = vec4(position + chunk_offset, 1.0);
 
+    vec3 local_vertex_position = (view_rotation * view_translation * world_position).xyz;
+    gl_Position = projection * view_rotation * view_translation * world_position;
 
-    v_texcoord = uv;
-    v_normal = normalize(normal);
+    v_texcoord = corner;
+    v_layer = float(data.y);
+    v_normal = normal;
     v_local_pos = local_vertex_position;
-}
\ No newline at end of file
+
+    // every level of light darker is 80% as bright, but never pitch black
+    v_shade = (0.55 + 0.15 * ao) * (0.1 + 0.9 * pow(0.8, 15.0 - light));
+}
@@ -0,0 +1,21 @@
+#version 420
+
+uniform sampler2D opaque;
+uniform sampler2D accumulation;
+uniform sampler2D weight;
+
+out vec4 f_color;
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+
+    vec3 background = texelFetch(opaque, pixel, 0).rgb;
+    vec4 accumulated = texelFetch(accumulation, pixel, 0);
+    float total_weight = texelFetch(weight, pixel, 0).r;
+
+    // how much of the background still shines through
+    float revealage = accumulated.a;
+    vec3 average = accumulated.rgb / max(total_weight, 1e-5);
+
+    f_color = vec4(mix(average, background, revealage), 1.0);
+}
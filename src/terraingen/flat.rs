@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+/// the same height everywhere, without caves or decorations so it
+/// really is flat
 pub struct FlatGenerator {
     height: usize,
 }
@@ -14,4 +16,10 @@ impl super::TerrainGenerator for FlatGenerator {
     fn get_height_at(&self, _: (isize, isize)) -> usize {
         self.height
     }
+
+    fn settings(&self) -> super::GeneratorSettings {
+        super::GeneratorSettings::Flat {
+            height: self.height,
+        }
+    }
 }
@@ -1,7 +1,59 @@
 pub mod flat;
 pub mod opensimplex;
 
+use crate::block::BlockType;
+use crate::chunk::Chunk;
+
+/// everything needed to recreate a generator, this is
+/// what gets stored alongside a saved world
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeneratorSettings {
+    Flat {
+        height: usize,
+    },
+    Opensimplex {
+        seed: Option<i64>,
+        coord_scaling: (f64, f64),
+        offset: f64,
+        amplitude: f64,
+    },
+}
+
+impl GeneratorSettings {
+    pub fn build(&self) -> Box<dyn TerrainGenerator> {
+        match *self {
+            GeneratorSettings::Flat { height } => Box::new(flat::FlatGenerator::new(height)),
+            GeneratorSettings::Opensimplex {
+                seed,
+                coord_scaling,
+                offset,
+                amplitude,
+            } => Box::new(opensimplex::OpensimplexGenerator::new(
+                seed,
+                coord_scaling,
+                offset,
+                amplitude,
+            )),
+        }
+    }
+}
+
 pub trait TerrainGenerator {
     //fn new(seed: Option<i64>) -> Self;
     fn get_height_at(&self, xz: (isize, isize)) -> usize;
+
+    fn settings(&self) -> GeneratorSettings;
+
+    /// returns true when the block at the given global position
+    /// should be hollowed out during the carve stage
+    fn is_carved(&self, _xyz: (isize, isize, isize)) -> bool {
+        false
+    }
+
+    /// returns the blocks that should be placed during the decorate
+    /// stage of the given chunk, in global coordinates. placements
+    /// may spill over into the directly neighbouring chunks
+    fn decorate(&self, _chunk: &Chunk) -> Vec<([i32; 3], &'static BlockType)> {
+        vec![]
+    }
 }
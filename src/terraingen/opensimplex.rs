@@ -2,8 +2,22 @@ use noise::NoiseFn;
 use noise::Seedable;
 use noise::*;
 
+use crate::block::types::STONE_BLOCK;
+use crate::block::BlockType;
+use crate::chunk::{Chunk, CHUNK_SIZE_I32};
+
+/// how stretched out the caves are, smaller is longer
+const CAVE_SCALING: f64 = 0.04;
+/// how close to zero both cave noises have to be, bigger is wider
+const CAVE_WIDTH: f64 = 0.09;
+/// caves stay this far under the grass so they don't punch holes in it
+const CAVE_ROOF: isize = 4;
+/// one in this many columns gets a boulder
+const BOULDER_RARITY: u64 = 600;
+
 pub struct OpensimplexGenerator {
     noisegen: Perlin,
+    seed: Option<i64>,
     coord_scaling: (f64, f64),
     offset: f64,
     amplitude: f64,
@@ -18,11 +32,22 @@ impl OpensimplexGenerator {
 
         OpensimplexGenerator {
             noisegen: gen,
+            seed: seed,
             coord_scaling: coord_scaling,
             offset: offset,
             amplitude: amplitude,
         }
     }
+
+    /// a number that looks random but is always the same for a column
+    fn column_hash(&self, x: i32, z: i32) -> u64 {
+        let mut hash = (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (z as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+            ^ self.seed.unwrap_or(0) as u64;
+        hash ^= hash >> 31;
+        hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash ^ (hash >> 29)
+    }
 }
 
 impl super::TerrainGenerator for OpensimplexGenerator {
@@ -37,4 +62,69 @@ impl super::TerrainGenerator for OpensimplexGenerator {
 
         combined as usize
     }
+
+    /// caves are where two noises are both close to zero, which makes
+    /// long winding tunnels instead of big open holes
+    fn is_carved(&self, xyz: (isize, isize, isize)) -> bool {
+        let (x, y, z) = xyz;
+        if y <= 0 || y > self.get_height_at((x, z)) as isize - CAVE_ROOF {
+            return false;
+        }
+
+        let point = [
+            x as f64 * CAVE_SCALING,
+            y as f64 * CAVE_SCALING * 2.0,
+            z as f64 * CAVE_SCALING,
+        ];
+        if self.noisegen.get(point).abs() >= CAVE_WIDTH {
+            return false;
+        }
+
+        let other = [point[0] + 71.3, point[1] - 13.7, point[2] + 29.1];
+        self.noisegen.get(other).abs() < CAVE_WIDTH
+    }
+
+    /// stone boulders lying around on the grass, the ones close to the
+    /// edge of the chunk spill over into its neighbours
+    fn decorate(&self, chunk: &Chunk) -> Vec<([i32; 3], &'static BlockType)> {
+        let origin = Chunk::get_global_coords_from_local_coord(chunk.coordinates, [0, 0, 0]);
+        let mut placements = vec![];
+
+        for x in origin[0]..origin[0] + CHUNK_SIZE_I32 {
+            for z in origin[2]..origin[2] + CHUNK_SIZE_I32 {
+                let hash = self.column_hash(x, z);
+                if hash % BOULDER_RARITY != 0 {
+                    continue;
+                }
+
+                // the boulder belongs to the chunk its center is in
+                let y = self.get_height_at((x as isize, z as isize)) as i32 + 1;
+                if y < origin[1] || y >= origin[1] + CHUNK_SIZE_I32 {
+                    continue;
+                }
+
+                let radius = 1 + (hash / BOULDER_RARITY % 2) as i32;
+                for dx in -radius..=radius {
+                    for dy in -radius..=radius {
+                        for dz in -radius..=radius {
+                            if dx * dx + dy * dy + dz * dz <= radius * radius + radius {
+                                placements.push(([x + dx, y + dy, z + dz], &STONE_BLOCK));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        placements
+    }
+
+    fn settings(&self) -> super::GeneratorSettings {
+        super::GeneratorSettings::Opensimplex {
+            seed: self.seed,
+            coord_scaling: self.coord_scaling,
+            offset: self.offset,
+            amplitude: self.amplitude,
+        }
+    }
 }
@@ -0,0 +1,151 @@
+use crate::block::types::current;
+use crate::block::BlockType;
+use crate::chunk::*;
+use crate::world::generation;
+
+/// a dense box of blocks that isn't tied to any chunks, used to
+/// copy parts of a world around and to import and export them
+#[derive(Clone)]
+pub struct Volume {
+    pub size: [usize; 3],
+    /// indexed with x changing the fastest, then z, then y
+    pub blocks: Vec<Option<&'static BlockType>>,
+}
+
+impl Volume {
+    pub fn new(size: [usize; 3]) -> Self {
+        Volume {
+            size: size,
+            blocks: vec![None; size[0] * size[1] * size[2]],
+        }
+    }
+
+    pub fn index(&self, coords: [usize; 3]) -> usize {
+        (coords[1] * self.size[2] + coords[2]) * self.size[0] + coords[0]
+    }
+
+    pub fn get(&self, coords: [usize; 3]) -> Option<&'static BlockType> {
+        self.blocks[self.index(coords)]
+    }
+
+    pub fn set(&mut self, coords: [usize; 3], block_type: Option<&'static BlockType>) {
+        let index = self.index(coords);
+        self.blocks[index] = block_type;
+    }
+
+    /// the overlap between the box starting at `origin` with the given
+    /// size and a chunk, in the chunk's local coordinates. None if they
+    /// don't overlap
+    fn chunk_overlap(chunk: &Chunk, origin: [i32; 3], size: [usize; 3]) -> Option<[[i32; 2]; 3]> {
+        let mut overlap = [[0; 2]; 3];
+
+        for axis in 0..3 {
+            let chunk_start = chunk.coordinates[axis] * CHUNK_SIZE_I32;
+            let start = (origin[axis] - chunk_start).max(0);
+            let end = (origin[axis] + size[axis] as i32 - chunk_start).min(CHUNK_SIZE_I32);
+
+            if start >= end {
+                return None;
+            }
+            overlap[axis] = [start, end];
+        }
+
+        Some(overlap)
+    }
+
+    /// copies the blocks in the box starting at `origin` out of the chunks,
+    /// blocks in chunks that don't exist are air
+    pub fn copy_from_chunks(chunks: &[Chunk], origin: [i32; 3], size: [usize; 3]) -> Self {
+        let mut volume = Self::new(size);
+
+        for chunk in chunks.iter() {
+            let overlap = match Self::chunk_overlap(chunk, origin, size) {
+                Some(overlap) => overlap,
+                None => continue,
+            };
+
+            for x in overlap[0][0]..overlap[0][1] {
+                for y in overlap[1][0]..overlap[1][1] {
+                    for z in overlap[2][0]..overlap[2][1] {
+                        let global =
+                            Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, y, z]);
+
+                        let block = chunk.get_block([x as u8, y as u8, z as u8]);
+                        volume.set(
+                            [
+                                (global[0] - origin[0]) as usize,
+                                (global[1] - origin[1]) as usize,
+                                (global[2] - origin[2]) as usize,
+                            ],
+                            block.map(|b| b.block_type),
+                        );
+                    }
+                }
+            }
+        }
+
+        volume
+    }
+
+    /// writes the volume into the chunks with its lowest corner at `origin`,
+    /// using the block types in use of the same names. air in the volume is
+    /// only written when `include_air` is set. blocks that would end up in
+    /// chunks that don't exist are dropped.
+    ///
+    /// changed chunks that were already lit, and the lit chunks around and
+    /// below them, go back to being decorated so they get lit again. returns
+    /// the coordinates of the chunks that changed
+    pub fn paste_into_chunks(
+        &self,
+        chunks: &mut [Chunk],
+        origin: [i32; 3],
+        include_air: bool,
+    ) -> Vec<[i32; 3]> {
+        let mut changed = vec![];
+
+        for chunk in chunks.iter_mut() {
+            let overlap = match Self::chunk_overlap(chunk, origin, self.size) {
+                Some(overlap) => overlap,
+                None => continue,
+            };
+
+            let mut chunk_changed = false;
+
+            for x in overlap[0][0]..overlap[0][1] {
+                for y in overlap[1][0]..overlap[1][1] {
+                    for z in overlap[2][0]..overlap[2][1] {
+                        let global =
+                            Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, y, z]);
+
+                        let block_type = self.get([
+                            (global[0] - origin[0]) as usize,
+                            (global[1] - origin[1]) as usize,
+                            (global[2] - origin[2]) as usize,
+                        ]);
+
+                        if block_type.is_none() && !include_air {
+                            continue;
+                        }
+
+                        let local = [x as u8, y as u8, z as u8];
+                        *chunk.get_block_mut(local) =
+                            block_type.map(|block_type| Block::new(local, current(block_type)));
+                        chunk_changed = true;
+                    }
+                }
+            }
+
+            if chunk_changed {
+                changed.push(chunk.coordinates);
+            }
+        }
+
+        // the light doesn't fit the new blocks anymore, going back a
+        // stage makes the game light the chunks again
+        for &coords in changed.iter() {
+            generation::invalidate_light(chunks, coords);
+        }
+
+        changed
+    }
+}
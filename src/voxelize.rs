@@ -0,0 +1,225 @@
+//! turning triangle meshes into blocks
+//!
+//! every block whose cube touches a triangle becomes part of the shell,
+//! which is found with the separating axis test by Akenine-Möller. for
+//! solid models the space outside of the shell is flood filled from the
+//! edges of the grid and everything it doesn't reach is filled in.
+
+use std::collections::VecDeque;
+use std::error::Error;
+
+use crate::block::BlockType;
+use crate::formats::triangle_mesh::Triangle;
+use crate::volume::Volume;
+
+// models larger than this on any axis, or with more blocks in
+// their bounding box, are refused before anything is allocated
+const MAX_SIZE: usize = 512;
+const MAX_BLOCKS: usize = 256 * 256 * 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fill {
+    /// only the blocks the surface passes through
+    Shell,
+    /// the shell and everything it encloses
+    Solid,
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// checks if a triangle overlaps an axis aligned box, given relative to
+/// the box's center. touching counts as overlapping
+pub fn triangle_overlaps_box(triangle: &Triangle, center: [f32; 3], half_size: [f32; 3]) -> bool {
+    let v = [
+        sub(triangle[0], center),
+        sub(triangle[1], center),
+        sub(triangle[2], center),
+    ];
+    let edges = [sub(v[1], v[0]), sub(v[2], v[1]), sub(v[0], v[2])];
+
+    // the triangle and the box are separated if their projections
+    // onto any of these axes don't overlap
+    let separated_on = |axis: [f32; 3]| {
+        let projections = [dot(v[0], axis), dot(v[1], axis), dot(v[2], axis)];
+        let min = projections[0].min(projections[1]).min(projections[2]);
+        let max = projections[0].max(projections[1]).max(projections[2]);
+        let radius = half_size[0] * axis[0].abs()
+            + half_size[1] * axis[1].abs()
+            + half_size[2] * axis[2].abs();
+
+        min > radius || max < -radius
+    };
+
+    // the nine cross products of the box's and the triangle's edges
+    for edge in edges.iter() {
+        for box_axis in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+            if separated_on(cross(*edge, box_axis)) {
+                return false;
+            }
+        }
+    }
+
+    // the box's own axes
+    for axis in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+        if separated_on(axis) {
+            return false;
+        }
+    }
+
+    // the triangle's normal
+    !separated_on(cross(edges[0], edges[1]))
+}
+
+/// voxelizes the triangles with `scale` blocks per unit of the mesh, the
+/// lowest corner of the model ends up at the lowest corner of the volume
+pub fn voxelize(
+    triangles: &[Triangle],
+    scale: f32,
+    fill: Fill,
+    block_type: &'static BlockType,
+) -> Result<Volume, Box<dyn Error>> {
+    if triangles.is_empty() {
+        return Err("the mesh has no triangles".into());
+    }
+    if scale <= 0.0 || scale.is_nan() {
+        return Err("the scale has to be positive".into());
+    }
+
+    let triangles: Vec<Triangle> = triangles
+        .iter()
+        .map(|t| {
+            [
+                [t[0][0] * scale, t[0][1] * scale, t[0][2] * scale],
+                [t[1][0] * scale, t[1][1] * scale, t[1][2] * scale],
+                [t[2][0] * scale, t[2][1] * scale, t[2][2] * scale],
+            ]
+        })
+        .collect();
+
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in triangles.iter().flatten() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex[axis]);
+            max[axis] = max[axis].max(vertex[axis]);
+        }
+    }
+
+    let origin = [min[0].floor(), min[1].floor(), min[2].floor()];
+    let mut size = [0; 3];
+    for axis in 0..3 {
+        // flat meshes still need to be one block thick
+        size[axis] = ((max[axis] - origin[axis]).ceil() as usize).max(1);
+    }
+    if size.iter().any(|&s| s > MAX_SIZE) || size[0] * size[1] * size[2] > MAX_BLOCKS {
+        return Err(format!("the voxelized mesh would be {:?} blocks large", size).into());
+    }
+
+    let mut shell = vec![false; size[0] * size[1] * size[2]];
+    let index = |x: usize, y: usize, z: usize| (y * size[2] + z) * size[0] + x;
+
+    for triangle in triangles.iter() {
+        let mut lowest = [0; 3];
+        let mut highest = [0; 3];
+        for axis in 0..3 {
+            let t_min = triangle.iter().map(|v| v[axis]).fold(f32::MAX, f32::min);
+            let t_max = triangle.iter().map(|v| v[axis]).fold(f32::MIN, f32::max);
+
+            lowest[axis] = ((t_min - origin[axis]).floor().max(0.0) as usize).min(size[axis] - 1);
+            highest[axis] = ((t_max - origin[axis]).floor().max(0.0) as usize).min(size[axis] - 1);
+        }
+
+        for x in lowest[0]..=highest[0] {
+            for y in lowest[1]..=highest[1] {
+                for z in lowest[2]..=highest[2] {
+                    let center = [
+                        origin[0] + x as f32 + 0.5,
+                        origin[1] + y as f32 + 0.5,
+                        origin[2] + z as f32 + 0.5,
+                    ];
+
+                    if triangle_overlaps_box(triangle, center, [0.5; 3]) {
+                        shell[index(x, y, z)] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let filled = match fill {
+        Fill::Shell => shell,
+        Fill::Solid => fill_interior(&shell, size),
+    };
+
+    let mut volume = Volume::new(size);
+    for (i, &filled) in filled.iter().enumerate() {
+        if filled {
+            volume.blocks[i] = Some(block_type);
+        }
+    }
+
+    Ok(volume)
+}
+
+/// flood fills the outside of the shell starting at every block on the
+/// edge of the grid, everything that isn't reached is inside
+fn fill_interior(shell: &[bool], size: [usize; 3]) -> Vec<bool> {
+    let index = |p: [usize; 3]| (p[1] * size[2] + p[2]) * size[0] + p[0];
+
+    let mut outside = vec![false; shell.len()];
+    let mut queue = VecDeque::new();
+
+    for x in 0..size[0] {
+        for y in 0..size[1] {
+            for z in 0..size[2] {
+                let on_edge = x == 0
+                    || y == 0
+                    || z == 0
+                    || x == size[0] - 1
+                    || y == size[1] - 1
+                    || z == size[2] - 1;
+
+                if on_edge && !shell[index([x, y, z])] {
+                    outside[index([x, y, z])] = true;
+                    queue.push_back([x, y, z]);
+                }
+            }
+        }
+    }
+
+    while let Some(p) = queue.pop_front() {
+        for axis in 0..3 {
+            for &step in [-1i64, 1].iter() {
+                let coord = p[axis] as i64 + step;
+                if coord < 0 || coord >= size[axis] as i64 {
+                    continue;
+                }
+
+                let mut neighbour = p;
+                neighbour[axis] = coord as usize;
+
+                let i = index(neighbour);
+                if !outside[i] && !shell[i] {
+                    outside[i] = true;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    outside.iter().map(|&o| !o).collect()
+}
@@ -0,0 +1,583 @@
+//! the staged chunk generation pipeline
+//!
+//! every chunk moves through the stages in `ChunkStatus` one at a time.
+//! a chunk may only enter the next stage once all of its present
+//! neighbours have reached the stage it is currently in, which means
+//! neighbouring chunks are never more than one stage apart. this is what
+//! lets decoration write into neighbouring chunks and lets lighting read
+//! from them without seeing half generated terrain.
+
+use std::collections::{HashMap, VecDeque};
+
+use log::*;
+
+use crate::block::types::*;
+use crate::block::Block;
+use crate::chunk::*;
+use crate::terraingen::TerrainGenerator;
+
+fn is_opaque(block: &Option<Block>) -> bool {
+    match block {
+        Some(block) => !block.block_type.transparent,
+        None => false,
+    }
+}
+
+/// checks if the neighbours of a chunk are far enough along
+/// for the chunk to enter its next stage
+pub fn can_advance(chunk: &Chunk, neighbours: &ChunkNeighbours) -> bool {
+    let present = [
+        neighbours.front,
+        neighbours.back,
+        neighbours.left,
+        neighbours.right,
+        neighbours.above,
+        neighbours.below,
+    ];
+
+    if !present
+        .iter()
+        .flatten()
+        .all(|neighbour| neighbour.status >= chunk.status)
+    {
+        return false;
+    }
+
+    // skylight comes from above, so the chunk above has to be lit first
+    if chunk.status.next() == Some(ChunkStatus::Lit) {
+        if let Some(above) = neighbours.above {
+            return above.status >= ChunkStatus::Lit;
+        }
+    }
+
+    true
+}
+
+/// runs at most `max_stages` generation stages on the chunks that are allowed
+/// to advance. chunks that become lit, and lit chunks whose light changed
+/// because of them, are pushed onto `newly_lit` so they can be meshed, since
+/// meshing needs a display it isn't done here.
+///
+/// returns the amount of stages that were run
+pub fn advance(
+    chunks: &mut [Chunk],
+    generator: &dyn TerrainGenerator,
+    max_stages: usize,
+    newly_lit: &mut Vec<usize>,
+) -> usize {
+    let mut stages_run = 0;
+
+    for i in 0..chunks.len() {
+        if stages_run >= max_stages {
+            break;
+        }
+
+        let target = match chunks[i].status.next() {
+            Some(ChunkStatus::Meshed) | None => continue,
+            Some(target) => target,
+        };
+
+        let neighbours = get_chunk_neighbours(chunks, chunks[i].coordinates);
+        if !can_advance(&chunks[i], &neighbours) {
+            continue;
+        }
+
+        trace!(
+            "advancing chunk {:?} to {:?}",
+            chunks[i].coordinates,
+            target
+        );
+
+        match target {
+            ChunkStatus::Shaped => shape(&mut chunks[i], generator),
+            ChunkStatus::Carved => carve(&mut chunks[i], generator),
+            ChunkStatus::Surfaced => surface(&mut chunks[i], generator),
+            ChunkStatus::Decorated => decorate(chunks, i, generator),
+            ChunkStatus::Lit => newly_lit.extend(light(chunks, i)),
+            _ => unreachable!(),
+        }
+
+        chunks[i].status = target;
+        stages_run += 1;
+    }
+
+    stages_run
+}
+
+/// throws away the blocks of a chunk and generates its terrain again, up
+/// to and including decoration, so the game lights and meshes it when it's
+/// loaded. decorations only reach the neighbours that are in `chunks`
+pub fn regenerate(chunks: &mut [Chunk], index: usize, generator: &dyn TerrainGenerator) {
+    let coords = chunks[index].coordinates;
+    chunks[index] = Chunk::empty(coords);
+
+    shape(&mut chunks[index], generator);
+    carve(&mut chunks[index], generator);
+    surface(&mut chunks[index], generator);
+    decorate(chunks, index, generator);
+
+    // the decorations the neighbours spilled into the chunk go back in too
+    for j in 0..chunks.len() {
+        let neighbour = chunks[j].coordinates;
+        let distance = (0..3)
+            .map(|axis| (neighbour[axis] - coords[axis]).abs())
+            .sum::<i32>();
+        if distance != 1 {
+            continue;
+        }
+
+        for (position, block_type) in generator.decorate(&chunks[j]) {
+            let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(position);
+            if chunk_coords == coords {
+                *chunks[index].get_block_mut(block_coords) =
+                    Some(Block::new(block_coords, current(block_type)));
+            }
+        }
+    }
+
+    chunks[index].status = ChunkStatus::Decorated;
+}
+
+/// checks if the light of the chunk at `other` can depend on the blocks of
+/// the chunk at `coords`, which is the case for the chunk itself, its face
+/// neighbours and every chunk below it
+pub fn light_depends_on(other: [i32; 3], coords: [i32; 3]) -> bool {
+    let offset = [
+        other[0] - coords[0],
+        other[1] - coords[1],
+        other[2] - coords[2],
+    ];
+
+    let below = offset[0] == 0 && offset[2] == 0 && offset[1] < 0;
+    below || offset.iter().map(|o| o.abs()).sum::<i32>() <= 1
+}
+
+/// drops every lit chunk whose light depends on the chunk at `coords` back
+/// to `Decorated`, after its blocks changed, so they're all lit again
+/// together. chunks that aren't lit yet are left alone
+pub fn invalidate_light(chunks: &mut [Chunk], coords: [i32; 3]) {
+    for chunk in chunks.iter_mut() {
+        if chunk.status > ChunkStatus::Decorated && light_depends_on(chunk.coordinates, coords) {
+            chunk.status = ChunkStatus::Decorated;
+        }
+    }
+}
+
+/// fills everything at or below the terrain height with stone
+fn shape(chunk: &mut Chunk, generator: &dyn TerrainGenerator) {
+    let stone = current(&STONE_BLOCK);
+
+    for x in 0..CHUNK_SIZE_U8 {
+        for z in 0..CHUNK_SIZE_U8 {
+            let column = Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, 0, z]);
+            let height = generator.get_height_at((column[0] as isize, column[2] as isize)) as i32;
+
+            for y in 0..CHUNK_SIZE_U8 {
+                if column[1] + y as i32 <= height {
+                    *chunk.get_block_mut([x, y, z]) = Some(Block::new([x, y, z], stone));
+                }
+            }
+        }
+    }
+}
+
+/// hollows out the blocks the generator wants gone, like caves
+fn carve(chunk: &mut Chunk, generator: &dyn TerrainGenerator) {
+    for x in 0..CHUNK_SIZE_U8 {
+        for y in 0..CHUNK_SIZE_U8 {
+            for z in 0..CHUNK_SIZE_U8 {
+                if chunk.get_block([x, y, z]).is_none() {
+                    continue;
+                }
+
+                let global =
+                    Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, y, z]);
+
+                if generator.is_carved((global[0] as isize, global[1] as isize, global[2] as isize))
+                {
+                    *chunk.get_block_mut([x, y, z]) = None;
+                }
+            }
+        }
+    }
+}
+
+/// turns the top of every column into grass with a few layers of dirt below
+fn surface(chunk: &mut Chunk, generator: &dyn TerrainGenerator) {
+    let (grass, dirt) = (current(&GRASS_BLOCK), current(&DIRT_BLOCK));
+
+    for x in 0..CHUNK_SIZE_U8 {
+        for z in 0..CHUNK_SIZE_U8 {
+            let column = Chunk::get_global_coords_from_local_coord(chunk.coordinates, [x, 0, z]);
+            let height = generator.get_height_at((column[0] as isize, column[2] as isize)) as i32;
+
+            for y in 0..CHUNK_SIZE_U8 {
+                let global_height = column[1] + y as i32;
+                let block = chunk.get_block_mut([x, y, z]);
+
+                if block.is_none() {
+                    continue;
+                }
+
+                if global_height == height {
+                    *block = Some(Block::new([x, y, z], grass));
+                } else if global_height < height && global_height >= height - 3 {
+                    *block = Some(Block::new([x, y, z], dirt));
+                }
+            }
+        }
+    }
+}
+
+/// places the generator's decorations, the ones that spill over into a
+/// face neighbour are written there, anything further away is dropped
+fn decorate(chunks: &mut [Chunk], index: usize, generator: &dyn TerrainGenerator) {
+    let origin = chunks[index].coordinates;
+    let placements = generator.decorate(&chunks[index]);
+
+    for (position, block_type) in placements {
+        let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(position);
+
+        let distance = (chunk_coords[0] - origin[0]).abs()
+            + (chunk_coords[1] - origin[1]).abs()
+            + (chunk_coords[2] - origin[2]).abs();
+
+        if distance > 1 {
+            trace!(
+                "dropping decoration at {:?}, too far from {:?}",
+                position,
+                origin
+            );
+            continue;
+        }
+
+        match chunks.iter_mut().find(|c| c.coordinates == chunk_coords) {
+            Some(chunk) => {
+                *chunk.get_block_mut(block_coords) = Some(Block::new(block_coords, block_type))
+            }
+            None => trace!("dropping decoration at {:?}, chunk doesn't exist", position),
+        }
+    }
+}
+
+/// skylight travels straight down from the chunk above without losing
+/// any strength, and then spreads sideways through non-opaque blocks,
+/// getting one level darker with every block
+fn compute_skylight(
+    chunk: &Chunk,
+    neighbours: ChunkNeighbours,
+) -> Box<[[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]> {
+    let mut skylight = Chunk::allocate_skylight();
+    let mut queue = VecDeque::new();
+
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            let mut level = match neighbours.above {
+                Some(above) => match above.skylight[x][0][z] {
+                    MAX_LIGHT => MAX_LIGHT,
+                    level => level.saturating_sub(1),
+                },
+                None => MAX_LIGHT,
+            };
+
+            for y in (0..CHUNK_SIZE).rev() {
+                if is_opaque(&chunk.blocks[x][y][z]) {
+                    level = 0;
+                }
+
+                skylight[x][y][z] = level;
+                if level > 1 {
+                    queue.push_back([x, y, z]);
+                }
+
+                if level < MAX_LIGHT {
+                    level = level.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    while let Some([x, y, z]) = queue.pop_front() {
+        let spread = skylight[x][y][z] - 1;
+
+        let candidates = [
+            [x.wrapping_sub(1), y, z],
+            [x + 1, y, z],
+            [x, y.wrapping_sub(1), z],
+            [x, y + 1, z],
+            [x, y, z.wrapping_sub(1)],
+            [x, y, z + 1],
+        ];
+
+        for [nx, ny, nz] in candidates.iter().copied() {
+            if nx >= CHUNK_SIZE || ny >= CHUNK_SIZE || nz >= CHUNK_SIZE {
+                continue;
+            }
+
+            if is_opaque(&chunk.blocks[nx][ny][nz]) || skylight[nx][ny][nz] >= spread {
+                continue;
+            }
+
+            skylight[nx][ny][nz] = spread;
+            if spread > 1 {
+                queue.push_back([nx, ny, nz]);
+            }
+        }
+    }
+
+    skylight
+}
+
+/// the chunk and position of a block, if it's in one of the given chunks
+fn find_in(chunks: &HashMap<[i32; 3], usize>, global: [i32; 3]) -> Option<(usize, [usize; 3])> {
+    let (chunk_coords, [x, y, z]) = Chunk::get_local_coord_from_world_coord(global);
+    let &j = chunks.get(&chunk_coords)?;
+
+    Some((j, [x as usize, y as usize, z as usize]))
+}
+
+/// lights a chunk from scratch and spreads its light into the lit chunks
+/// around it. spreading can only make things brighter, so the lit
+/// neighbours behind a border that got darker are lit again, and so is the
+/// chunk below when the bottom of the chunk changed. returns the chunk and
+/// the other chunks whose light changed
+fn light(chunks: &mut [Chunk], index: usize) -> Vec<usize> {
+    let coords = chunks[index].coordinates;
+    let skylight = compute_skylight(&chunks[index], get_chunk_neighbours(chunks, coords));
+    let previous = std::mem::replace(&mut chunks[index].skylight, skylight);
+    chunks[index].status = ChunkStatus::Lit;
+
+    // before spreading, or the light the neighbours got from this chunk
+    // would flow back in and hide that it got darker
+    let mut relight = darker_borders(coords, &previous, &chunks[index].skylight);
+
+    let mut changed = vec![index];
+    changed.extend(spread_skylight_across_borders(chunks, index));
+
+    let skylight = &chunks[index].skylight;
+    let [cx, cy, cz] = coords;
+    if (0..CHUNK_SIZE).any(|x| (0..CHUNK_SIZE).any(|z| previous[x][0][z] != skylight[x][0][z])) {
+        relight.push([cx, cy - 1, cz]);
+    }
+
+    for chunk in chunks.iter_mut() {
+        if chunk.status > ChunkStatus::Decorated && relight.contains(&chunk.coordinates) {
+            trace!("lighting chunk {:?} again", chunk.coordinates);
+            chunk.status = ChunkStatus::Decorated;
+        }
+    }
+
+    changed
+}
+
+/// the chunks next to the borders of the chunk at `coords` where the light
+/// went down from `before` to `after`
+fn darker_borders(
+    coords: [i32; 3],
+    before: &[[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+    after: &[[[u8; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
+) -> Vec<[i32; 3]> {
+    let [cx, cy, cz] = coords;
+    let last = CHUNK_SIZE - 1;
+    let mut darker = vec![];
+
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                if after[x][y][z] >= before[x][y][z] {
+                    continue;
+                }
+
+                match x {
+                    0 => darker.push([cx - 1, cy, cz]),
+                    x if x == last => darker.push([cx + 1, cy, cz]),
+                    _ => (),
+                }
+                match y {
+                    0 => darker.push([cx, cy - 1, cz]),
+                    y if y == last => darker.push([cx, cy + 1, cz]),
+                    _ => (),
+                }
+                match z {
+                    0 => darker.push([cx, cy, cz - 1]),
+                    z if z == last => darker.push([cx, cy, cz + 1]),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    darker.sort_unstable();
+    darker.dedup();
+    darker
+}
+
+/// `compute_skylight` stops at the borders of the chunk, so once a chunk is
+/// lit its light is spread into the lit chunks around it and theirs into
+/// it, from chunk to chunk until it stops changing. returns the other
+/// chunks whose light changed
+fn spread_skylight_across_borders(chunks: &mut [Chunk], index: usize) -> Vec<usize> {
+    let lit: HashMap<[i32; 3], usize> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.status >= ChunkStatus::Lit)
+        .map(|(j, chunk)| (chunk.coordinates, j))
+        .collect();
+
+    let directions = [
+        [-1, 0, 0],
+        [1, 0, 0],
+        [0, -1, 0],
+        [0, 1, 0],
+        [0, 0, -1],
+        [0, 0, 1],
+    ];
+
+    // starts from the border of the chunk and the blocks just outside of it
+    let mut queue = VecDeque::new();
+    let last = CHUNK_SIZE_U8 - 1;
+    for x in 0..CHUNK_SIZE_U8 {
+        for y in 0..CHUNK_SIZE_U8 {
+            for z in 0..CHUNK_SIZE_U8 {
+                if ![x, y, z].iter().any(|&c| c == 0 || c == last) {
+                    continue;
+                }
+
+                let global =
+                    Chunk::get_global_coords_from_local_coord(chunks[index].coordinates, [x, y, z]);
+                queue.push_back(global);
+                for d in directions.iter() {
+                    queue.push_back([global[0] + d[0], global[1] + d[1], global[2] + d[2]]);
+                }
+            }
+        }
+    }
+
+    let mut changed = vec![];
+    while let Some(global) = queue.pop_front() {
+        let (j, [x, y, z]) = match find_in(&lit, global) {
+            Some(found) => found,
+            None => continue,
+        };
+        let level = chunks[j].skylight[x][y][z];
+        if level <= 1 {
+            continue;
+        }
+
+        for d in directions.iter() {
+            let next = [global[0] + d[0], global[1] + d[1], global[2] + d[2]];
+            let (k, [nx, ny, nz]) = match find_in(&lit, next) {
+                Some(found) => found,
+                None => continue,
+            };
+
+            let neighbour = &mut chunks[k];
+            if is_opaque(&neighbour.blocks[nx][ny][nz])
+                || neighbour.skylight[nx][ny][nz] >= level - 1
+            {
+                continue;
+            }
+
+            neighbour.skylight[nx][ny][nz] = level - 1;
+            queue.push_back(next);
+            if k != index && !changed.contains(&k) {
+                changed.push(k);
+            }
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+    use crate::terraingen::GeneratorSettings;
+
+    /// a flat world 3 chunks across with its grass at the bottom of the
+    /// middle layer of chunks
+    fn flat_world() -> (Vec<Chunk>, Box<dyn TerrainGenerator>) {
+        let generator = GeneratorSettings::Flat { height: 32 }.build();
+        let mut chunks = vec![];
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    chunks.push(Chunk::empty([x, y, z]));
+                }
+            }
+        }
+
+        settle(&mut chunks, generator.as_ref());
+        (chunks, generator)
+    }
+
+    fn settle(chunks: &mut [Chunk], generator: &dyn TerrainGenerator) {
+        let mut newly_lit = vec![];
+        while advance(chunks, generator, usize::MAX, &mut newly_lit) > 0 {}
+        assert!(chunks.iter().all(|c| c.status == ChunkStatus::Lit));
+    }
+
+    fn set(chunks: &mut [Chunk], global: [i32; 3], block_type: Option<&'static BlockType>) {
+        assert!(set_block_at(chunks, global, block_type));
+        let (chunk_coords, _) = Chunk::get_local_coord_from_world_coord(global);
+        invalidate_light(chunks, chunk_coords);
+    }
+
+    fn skylight_at(chunks: &[Chunk], global: [i32; 3]) -> u8 {
+        let (chunk_coords, [x, y, z]) = Chunk::get_local_coord_from_world_coord(global);
+        let chunk = chunks
+            .iter()
+            .find(|c| c.coordinates == chunk_coords)
+            .unwrap();
+        chunk.skylight[x as usize][y as usize][z as usize]
+    }
+
+    #[test]
+    fn digging_next_to_the_sky_lights_the_exposed_face() {
+        let (mut chunks, generator) = flat_world();
+        assert_eq!(skylight_at(&chunks, [48, 33, 48]), MAX_LIGHT);
+        assert_eq!(skylight_at(&chunks, [48, 31, 48]), 0);
+
+        // the hole goes down into the chunk below the grass
+        set(&mut chunks, [48, 32, 48], None);
+        set(&mut chunks, [48, 31, 48], None);
+        settle(&mut chunks, generator.as_ref());
+
+        assert_eq!(skylight_at(&chunks, [48, 32, 48]), MAX_LIGHT);
+        assert_eq!(skylight_at(&chunks, [48, 31, 48]), MAX_LIGHT);
+    }
+
+    #[test]
+    fn light_spreads_down_a_tunnel_into_the_next_chunk() {
+        let (mut chunks, generator) = flat_world();
+
+        set(&mut chunks, [40, 32, 48], None);
+        for x in 28..=40 {
+            set(&mut chunks, [x, 31, 48], None);
+        }
+        settle(&mut chunks, generator.as_ref());
+
+        assert_eq!(skylight_at(&chunks, [40, 31, 48]), MAX_LIGHT);
+        assert_eq!(skylight_at(&chunks, [32, 31, 48]), MAX_LIGHT - 8);
+        assert_eq!(skylight_at(&chunks, [31, 31, 48]), MAX_LIGHT - 9);
+        assert_eq!(skylight_at(&chunks, [28, 31, 48]), MAX_LIGHT - 12);
+    }
+
+    #[test]
+    fn covering_a_hole_makes_it_dark_again() {
+        let (mut chunks, generator) = flat_world();
+
+        set(&mut chunks, [32, 32, 48], None);
+        set(&mut chunks, [32, 31, 48], None);
+        set(&mut chunks, [31, 31, 48], None);
+        settle(&mut chunks, generator.as_ref());
+        assert_eq!(skylight_at(&chunks, [31, 31, 48]), MAX_LIGHT - 1);
+
+        set(&mut chunks, [32, 32, 48], Some(&STONE_BLOCK));
+        settle(&mut chunks, generator.as_ref());
+
+        assert_eq!(skylight_at(&chunks, [32, 31, 48]), 0);
+        assert_eq!(skylight_at(&chunks, [31, 31, 48]), 0);
+    }
+}
@@ -0,0 +1,293 @@
+//! level of detail meshes for chunks far away from the camera
+//!
+//! a chunk is downsampled by merging cubes of 2, 4 or 8 blocks into a
+//! single big block, which is meshed like any other chunk and scaled back
+//! up. chunks next to each other can end up at different levels, so the
+//! faces on the sides of a downsampled chunk are always kept and hang
+//! further down as skirts that hide the cracks where the levels don't line
+//! up. the levels are only built once a chunk is first drawn at them.
+
+use glium::Display;
+
+use crate::arena::MeshArena;
+use crate::chunk::*;
+use crate::graphics::{ChunkVertex, Vertex};
+
+/// how many blocks along each axis are merged at every level,
+/// level 0 is the full mesh
+pub const LOD_SCALES: [usize; 4] = [1, 2, 4, 8];
+
+/// how far away from the camera, in blocks, a chunk switches to each level
+const LOD_DISTANCES: [f32; 4] = [0.0, 64.0, 128.0, 256.0];
+
+/// the meshes of the levels are raised this many blocks inside the chunk,
+/// and drawn that much lower, so the skirts of their bottom layer have room
+/// to hang into. it's the deepest a skirt goes, see `skirt_depth`
+pub const SKIRT_ROOM: i32 = LOD_SCALES[LOD_SCALES.len() - 1] as i32;
+
+/// the level of detail a chunk is drawn at, `distance`
+/// is measured to the closest point of the chunk
+pub fn level_for_distance(distance: f32) -> usize {
+    LOD_DISTANCES
+        .iter()
+        .rposition(|&d| distance >= d)
+        .unwrap_or(0)
+}
+
+fn count(counts: &mut Vec<(&'static BlockType, usize)>, block_type: &'static BlockType) {
+    match counts.iter_mut().find(|(t, _)| t.name == block_type.name) {
+        Some((_, n)) => *n += 1,
+        None => counts.push((block_type, 1)),
+    }
+}
+
+/// merges every cube of `scale` blocks into one, the merged blocks end up
+/// in the lowest corner of the chunk. a cube needs to be at least half
+/// full to become a block. it's transparent if most of its blocks are, and
+/// takes the type most common on its surface so grass stays on the hills
+fn downsample(chunk: &Chunk, scale: usize) -> Chunk {
+    let mut downsampled = Chunk::empty(chunk.coordinates);
+    let cells = CHUNK_SIZE / scale;
+
+    // faces on the sides of the chunk look into the unused part
+    for light in downsampled.skylight.iter_mut().flatten().flatten() {
+        *light = MAX_LIGHT;
+    }
+
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                let mut filled = 0;
+                let mut light = 0;
+                let mut all = vec![];
+                let mut surface = vec![];
+
+                // the surface is also looked for in the cube above, in
+                // case that one ends up too empty to become a block
+                let top = ((y + 2) * scale).min(CHUNK_SIZE);
+
+                for bx in x * scale..(x + 1) * scale {
+                    for by in y * scale..top {
+                        for bz in z * scale..(z + 1) * scale {
+                            if by < (y + 1) * scale {
+                                light = light.max(chunk.skylight[bx][by][bz]);
+                            }
+
+                            let block = match chunk.blocks[bx][by][bz] {
+                                Some(block) => block,
+                                None => continue,
+                            };
+
+                            if by < (y + 1) * scale {
+                                filled += 1;
+                                count(&mut all, block.block_type);
+                            }
+
+                            let exposed = by + 1 == CHUNK_SIZE
+                                || match chunk.blocks[bx][by + 1][bz] {
+                                    Some(above) => {
+                                        above.block_type.transparent
+                                            && !block.block_type.transparent
+                                    }
+                                    None => true,
+                                };
+                            if exposed {
+                                count(&mut surface, block.block_type);
+                            }
+                        }
+                    }
+                }
+
+                downsampled.skylight[x][y][z] = light;
+
+                if filled * 2 < scale * scale * scale {
+                    continue;
+                }
+
+                // water stays water and the ground under it stays ground
+                let transparent: usize = all
+                    .iter()
+                    .filter(|(t, _)| t.transparent)
+                    .map(|(_, n)| n)
+                    .sum();
+                let transparent = transparent * 2 > filled;
+
+                let (block_type, _) = surface
+                    .into_iter()
+                    .filter(|(t, _)| t.transparent == transparent)
+                    .max_by_key(|&(_, n)| n)
+                    .or_else(|| {
+                        all.into_iter()
+                            .filter(|(t, _)| t.transparent == transparent)
+                            .max_by_key(|&(_, n)| n)
+                    })
+                    .unwrap();
+
+                downsampled.blocks[x][y][z] =
+                    Some(Block::new([x as u8, y as u8, z as u8], block_type));
+            }
+        }
+    }
+
+    downsampled
+}
+
+/// how far the skirts of a level hang down, in blocks. `LOD_DISTANCES` are
+/// further apart than a chunk is across, so neighbouring chunks are at most
+/// one level apart and the cracks are never deeper than a cell of the next
+/// coarser level
+fn skirt_depth(level: usize) -> i32 {
+    LOD_SCALES[(level + 1).min(LOD_SCALES.len() - 1)] as i32
+}
+
+/// lowers the bottom edge of the faces on the sides of the chunk wherever
+/// there's nothing under them. the faces of the bottom layer always hang
+/// down, what's under them belongs to the chunk below. `verts` are the
+/// faces in cells and `packed` the same faces after packing
+fn add_skirts(
+    downsampled: &Chunk,
+    verts: &[Vertex],
+    packed: &mut [ChunkVertex],
+    cells: usize,
+    depth: i32,
+) {
+    for (face, packed) in verts.chunks_exact(4).zip(packed.chunks_exact_mut(4)) {
+        let normal = face[0].normal;
+        let axis = match (normal[0] != 0.0, normal[2] != 0.0) {
+            (true, _) => 0,
+            (_, true) => 2,
+            _ => continue,
+        };
+        let plane = match normal[axis] > 0.0 {
+            true => cells,
+            false => 0,
+        };
+        if face[0].position[axis] as usize != plane {
+            continue;
+        }
+
+        // the cell the face belongs to
+        let mut cell = [0; 3];
+        for (i, c) in cell.iter_mut().enumerate() {
+            *c = face
+                .iter()
+                .map(|vert| vert.position[i] as usize)
+                .min()
+                .unwrap();
+        }
+        cell[axis] = plane.min(cells - 1);
+
+        if cell[1] > 0 && downsampled.blocks[cell[0]][cell[1] - 1][cell[2]].is_some() {
+            continue;
+        }
+
+        for (vert, packed) in face.iter().zip(packed.iter_mut()) {
+            if vert.position[1] as usize == cell[1] {
+                packed.move_up(-depth);
+            }
+        }
+    }
+}
+
+/// the packed vertices and indices of the normal or transparent mesh of a
+/// downsampled chunk, skirts included
+fn geometry(downsampled: &Chunk, level: usize, transparent: bool) -> (Vec<ChunkVertex>, Vec<u32>) {
+    // without neighbours every face on the sides of the chunk is kept
+    let no_neighbours = ChunkNeighbours {
+        front: None,
+        back: None,
+        left: None,
+        right: None,
+        above: None,
+        below: None,
+    };
+
+    let scale = LOD_SCALES[level];
+    let (verts, indices) =
+        downsampled.generate_chunkmesh_geometry(|t| t.transparent == transparent, no_neighbours);
+    let mut packed = downsampled.pack_vertices(&verts, no_neighbours, scale as u32);
+
+    for vert in packed.iter_mut() {
+        vert.move_up(SKIRT_ROOM);
+    }
+    add_skirts(
+        downsampled,
+        &verts,
+        &mut packed,
+        CHUNK_SIZE / scale,
+        skirt_depth(level),
+    );
+
+    (packed, indices)
+}
+
+/// builds the normal and transparent mesh of a downsampled level of a chunk
+pub fn generate_lod_chunkmeshes(
+    display: &Display,
+    arena: &mut MeshArena,
+    chunk: &Chunk,
+    level: usize,
+) -> [ChunkMesh; 2] {
+    let downsampled = downsample(chunk, LOD_SCALES[level]);
+
+    let mut meshes = [false, true].iter().map(|&transparent| {
+        let (verts, indices) = geometry(&downsampled, level, transparent);
+        ChunkMesh::new(display, arena, &verts, &indices, transparent)
+    });
+    [meshes.next().unwrap(), meshes.next().unwrap()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::types::*;
+
+    /// stone up to the given height
+    fn ground(height: usize) -> Chunk {
+        let mut chunk = Chunk::empty([0, 0, 0]);
+        for x in 0..CHUNK_SIZE_U8 {
+            for y in 0..height as u8 {
+                for z in 0..CHUNK_SIZE_U8 {
+                    chunk.blocks[x as usize][y as usize][z as usize] =
+                        Some(Block::new([x, y, z], &STONE_BLOCK));
+                }
+            }
+        }
+
+        chunk
+    }
+
+    fn heights(verts: &[ChunkVertex]) -> (i32, i32) {
+        let heights = verts.iter().map(|vert| vert.position()[1] as i32);
+        (heights.clone().min().unwrap(), heights.max().unwrap())
+    }
+
+    #[test]
+    fn neighbours_are_at_most_one_level_apart() {
+        // as far apart as the closest points of two neighbouring chunks get
+        let across = (3.0 * (CHUNK_SIZE * CHUNK_SIZE) as f32).sqrt();
+
+        for distance in (0..1000).map(|d| d as f32 * 0.5) {
+            let near = level_for_distance(distance);
+            let far = level_for_distance(distance + across);
+            assert!(
+                far - near <= 1,
+                "{} and {} blocks away",
+                distance,
+                distance + across
+            );
+        }
+    }
+
+    #[test]
+    fn skirts_hang_below_the_bottom_layer() {
+        for (level, &scale) in LOD_SCALES.iter().enumerate().skip(1) {
+            let downsampled = downsample(&ground(CHUNK_SIZE), scale);
+            let (verts, _) = geometry(&downsampled, level, false);
+
+            let (lowest, highest) = heights(&verts);
+            assert_eq!(lowest, SKIRT_ROOM - skirt_depth(level));
+            assert_eq!(highest, SKIRT_ROOM + CHUNK_SIZE as i32);
+        }
+    }
+}
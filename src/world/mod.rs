@@ -1,16 +1,42 @@
-use std::fs;
-use std::path::Path;
+use std::collections::HashSet;
 use std::error::Error;
+use std::path::Path;
 
-use crate::block::types::*;
+use crate::arena::{ArenaAllocation, MeshArena};
+use crate::block::atlas::{BlockAtlas, TextureLayers};
+use crate::block::colors::BlockColors;
+use crate::block::definitions;
+use crate::block::types;
+use crate::block::{Block, BlockType};
 use crate::camera::*;
 use crate::chunk::*;
+use crate::formats::anvil;
+use crate::formats::block_names::BlockNameTable;
+use crate::formats::mesh::{clip_chunks, ExportMesh};
+use crate::formats::schematic::{Schematic, SchematicVersion};
+use crate::formats::triangle_mesh;
+use crate::formats::vox;
 use crate::graphics::*;
 use crate::hud::Hud;
-use crate::terraingen::TerrainGenerator;
+use crate::pixel_art::{image_to_volume, Orientation};
+use crate::resource_pack::ResourcePacks;
+use crate::save::metadata::WorldMetadata;
+use crate::save::region::region_of;
+use crate::save::snapshot::{self, SnapshotInfo, SnapshotStats};
+use crate::save::WorldSave;
+use crate::terraingen::{GeneratorSettings, TerrainGenerator};
+use crate::volume::Volume;
+use crate::voxelize::{voxelize, Fill};
 
+pub mod generation;
+mod lod;
+mod oit;
 mod sky;
+mod visibility;
+use oit::Oit;
+pub use oit::TransparencyMode;
 use sky::Sky;
+use visibility::FaceConnectivity;
 
 use log::*;
 
@@ -18,187 +44,544 @@ use glium::texture::*;
 use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
 use glium::*;
 
-
-
 const CHUNK_SHADER_VERT: &'static str = include_str!("../shaders/chunk/vertex.vert");
 const CHUNK_COLOR_SHADER_FRAG: &'static str = include_str!("../shaders/chunk/color.frag");
 
-const TEXTURE_ATLAS: &'static [u8] = include_bytes!("../../atlas.png");
+// how far the block textures are filtered along the
+// direction they're looked at from, if it's turned on
+const MAX_ANISOTROPY: u16 = 16;
+
+const GENERATION_STAGES_PER_FRAME: usize = 8;
+const AUTOSAVE_INTERVAL: f32 = 60.0;
 
-fn shader_load_helper<P: AsRef<Path>>(display: &Display, vertex_path: P, fragment_path: P, vertex_fallback: &str, fragment_fallback: &str) -> Program {
-    let plan_a = || -> Result<Program, Box<dyn Error>>{
-        match program!(display,
+/// builds a shader out of the sources in the resource packs, falling back
+/// to the built in sources if it doesn't compile
+fn shader_load_helper(
+    display: &Display,
+    packs: &ResourcePacks,
+    vertex_path: &str,
+    fragment_path: &str,
+    vertex_fallback: &str,
+    fragment_fallback: &str,
+) -> Program {
+    let vertex = packs.read_to_string(vertex_path);
+    let fragment = packs.read_to_string(fragment_path);
+
+    let plan_a = || -> Result<Program, Box<dyn Error>> {
+        Ok(program!(display,
         420 => {
-            vertex: &fs::read_to_string(vertex_path)?,
-            fragment: &fs::read_to_string(fragment_path)?
-        }) {
-            Ok(p) => Ok(p),
-            Err(e) => Err(Box::new(e))
-        }
+            vertex: vertex.as_deref().unwrap_or(vertex_fallback),
+            fragment: fragment.as_deref().unwrap_or(fragment_fallback)
+        })?)
     };
 
-    plan_a().or_else(|_| {
-        program!(display,
-        420 => {
-            vertex: vertex_fallback,
-            fragment: fragment_fallback
-        }
-        )
-    }).unwrap()
+    plan_a()
+        .or_else(|e| {
+            error!("{} and {}: {}", vertex_path, fragment_path, e);
+            program!(display,
+            420 => {
+                vertex: vertex_fallback,
+                fragment: fragment_fallback
+            }
+            )
+        })
+        .unwrap()
+}
+
+/// the assets from the resource packs the world can't be drawn without,
+/// all of them are loaded before any of them replaces the ones in use
+struct RequiredAssets {
+    block_types: &'static [&'static BlockType],
+    block_atlas: BlockAtlas,
+    block_textures: SrgbTexture2dArray,
+    chunk_color_shader: Program,
+}
+
+/// how many chunks the last frame drew, how many were skipped because
+/// they were outside of the view and how many because terrain hid them
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderStats {
+    pub drawn: usize,
+    pub culled: usize,
+    pub occluded: usize,
 }
 
 pub struct World {
     pub camera: OrbitalCamera,
     pub chunks: Vec<Chunk>,
-    chunk_meshes: Vec<[ChunkMesh; 2]>, // [0] is normal chunkmesh [1] is transparent chunkmesh
-    dirty_chunkmeshes: Vec<usize>,     // indices of the chunkmeshes that need to be rebuilt
+    // one pair for every level of detail, [0] is normal chunkmesh [1] is transparent chunkmesh
+    chunk_meshes: Vec<Vec<[ChunkMesh; 2]>>,
+    mesh_arena: MeshArena, // the buffers every chunkmesh is written into
+    dirty_chunkmeshes: Vec<usize>, // indices of the chunkmeshes that need to be rebuilt
+    chunk_connectivity: Vec<FaceConnectivity>, // which faces of each chunk see each other
+
+    generator: Box<dyn TerrainGenerator>,
+    size: (usize, usize), // width and depth in blocks
+
+    save: Option<WorldSave>,
+    unsaved_regions: HashSet<[i32; 3]>,
+    seconds_since_save: f32,
 
     chunk_color_shader: Program,
 
-    texture_atlas: CompressedSrgbTexture2d,
+    block_atlas: BlockAtlas,
+    block_textures: SrgbTexture2dArray,
+    anisotropic_filtering: bool,
     sky: Sky,
+    oit: Oit,
+    transparency_mode: TransparencyMode,
 
     hud: Hud,
+
+    render_stats: RenderStats,
+
+    resource_packs: ResourcePacks,
 }
 
 impl World {
-    fn shader_helper<P: AsRef<Path>>(path: P, default: &str) -> String {
-        match fs::read_to_string(path) {
-            Ok(s) => s,
-            Err(e) => {
-                error!("{}", e);
-                default.into()
-            }
-        }
-    }
-
-    fn texture_helper<P: AsRef<Path>>(path: P, default: &[u8]) -> Vec<u8> {
-        match fs::read(path) {
-            Ok(b) => b,
-            Err(e) => {
-                error!("{}", e);
-                default.into()
-            }
-        }
+    fn shader_helper(packs: &ResourcePacks, path: &str, default: &str) -> String {
+        packs.read_to_string(path).unwrap_or_else(|| default.into())
     }
 
-    fn create_chunk_color_shader(display: &Display) -> Result<Program, Box<dyn std::error::Error>> {
+    fn create_chunk_color_shader(
+        display: &Display,
+        packs: &ResourcePacks,
+    ) -> Result<Program, Box<dyn std::error::Error>> {
         Ok(program! (display,
             420 => {
-                vertex: &Self::shader_helper("shaders/chunk/vertex.vert", CHUNK_SHADER_VERT),
-                fragment: &Self::shader_helper("shaders/chunk/color.frag", CHUNK_COLOR_SHADER_FRAG)
+                vertex: &Self::shader_helper(packs, "shaders/chunk/vertex.vert", CHUNK_SHADER_VERT),
+                fragment: &Self::shader_helper(packs, "shaders/chunk/color.frag", CHUNK_COLOR_SHADER_FRAG)
             }
         )?)
     }
 
-    fn create_texture_atlas(
+    /// builds the textures of the block types from the pngs in
+    /// the resource packs, see block/atlas.rs
+    pub fn load_block_atlas(
+        packs: &ResourcePacks,
+        block_types: &[&BlockType],
+    ) -> Result<BlockAtlas, Box<dyn std::error::Error>> {
+        BlockAtlas::load(packs, &TextureLayers::from_block_types(block_types))
+    }
+
+    /// puts every block texture in its own layer of a texture
+    /// array, so mipmapping doesn't bleed between them
+    fn create_block_textures(
         display: &Display,
-    ) -> Result<CompressedSrgbTexture2d, Box<dyn std::error::Error>> {
-        let image = image::load(
-            std::io::Cursor::new(&Self::texture_helper("atlas.png", TEXTURE_ATLAS)[..]),
-            image::ImageFormat::Png,
-        )?
-        .to_rgba8();
-        let image_dimensions = image.dimensions();
-        let image =
-            glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-        Ok(glium::texture::CompressedSrgbTexture2d::new(
-            display, image,
+        atlas: &BlockAtlas,
+    ) -> Result<SrgbTexture2dArray, Box<dyn std::error::Error>> {
+        let size = atlas.tile_size();
+        let layers = atlas
+            .tiles()
+            .iter()
+            .map(|tile| RawImage2d::from_raw_rgba_reversed(tile.as_raw(), (size, size)))
+            .collect();
+
+        Ok(SrgbTexture2dArray::with_mipmaps(
+            display,
+            layers,
+            MipmapsOption::AutoGeneratedMipmaps,
         )?)
     }
 
+    /// the coordinates of every chunk in a world of the given size
+    pub fn chunk_grid(size: (usize, usize)) -> Vec<[i32; 3]> {
+        let required_chunk_width = size.0 / CHUNK_SIZE;
+        let required_chunk_depth = size.1 / CHUNK_SIZE;
+
+        let mut coords = vec![];
+
+        for chunk_x in 0..required_chunk_width {
+            for chunk_z in 0..required_chunk_depth {
+                for chunk_y in 0..16 {
+                    coords.push([chunk_x as i32, chunk_y, chunk_z as i32]);
+                }
+            }
+        }
+
+        coords
+    }
+
+    #[allow(dead_code)]
     pub fn generate(
         display: &Display,
-        generator: &dyn TerrainGenerator,
+        generator: Box<dyn TerrainGenerator>,
         width: usize,
         depth: usize,
-    ) -> World {
+    ) -> Result<World, Box<dyn Error>> {
+        Self::generate_with_save(display, generator, (width, depth), None)
+    }
+
+    fn generate_with_save(
+        display: &Display,
+        generator: Box<dyn TerrainGenerator>,
+        size: (usize, usize),
+        save: Option<WorldSave>,
+    ) -> Result<World, Box<dyn Error>> {
         info!("generating world");
 
-        let mut heightmap: Vec<Vec<u32>> = vec![vec![0; depth]; width];
+        let chunks = Self::chunk_grid(size)
+            .into_iter()
+            .map(Chunk::empty)
+            .collect();
+
+        let camera_position = [
+            (size.0 / 2) as f32,
+            generator.get_height_at((0, 0)) as f32,
+            (size.1 / 2) as f32,
+        ];
+
+        Self::from_chunks(
+            display,
+            generator,
+            size,
+            chunks,
+            (camera_position, [0.0, 0.0, 0.0]),
+            save,
+        )
+    }
+
+    /// opens the world saved in the given directory, if there is
+    /// none a new world is generated and saved there instead
+    pub fn open<P: AsRef<Path>>(
+        display: &Display,
+        dir: P,
+        settings: GeneratorSettings,
+        width: usize,
+        depth: usize,
+    ) -> Result<World, Box<dyn Error>> {
+        let save = WorldSave::new(dir);
+
+        if !save.exists() {
+            return Self::generate_with_save(display, settings.build(), (width, depth), Some(save));
+        }
 
-        for (x, rows) in heightmap.iter_mut().enumerate() {
-            for (z, height) in rows.iter_mut().enumerate() {
-                *height = generator.get_height_at((x as isize, z as isize)) as u32;
+        let metadata = match save.read_metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                error!(
+                    "failed to read world metadata, generating a new world: {}",
+                    e
+                );
+                return Self::generate_with_save(
+                    display,
+                    settings.build(),
+                    (width, depth),
+                    Some(save),
+                );
             }
+        };
+
+        info!("loading world");
+
+        let coords = Self::chunk_grid(metadata.size);
+        let chunks = save
+            .load_chunks(&coords)
+            .into_iter()
+            .zip(coords)
+            .map(|(chunk, coords)| chunk.unwrap_or_else(|| Chunk::empty(coords)))
+            .collect();
+
+        Self::from_chunks(
+            display,
+            metadata.generator.build(),
+            metadata.size,
+            chunks,
+            (metadata.camera_position, metadata.camera_rotation),
+            Some(save),
+        )
+    }
+
+    /// builds a world out of the terrain in a minecraft world's region
+    /// directory, the minecraft block at `origin` on the x and z axes ends
+    /// up in the corner of the world. the world isn't saved anywhere
+    pub fn import_anvil<P: AsRef<Path>>(
+        display: &Display,
+        region_dir: P,
+        origin: [i32; 2],
+        width: usize,
+        depth: usize,
+    ) -> Result<World, Box<dyn Error>> {
+        info!("importing minecraft world");
+
+        let size = (width, depth);
+        let mut chunks: Vec<Chunk> = Self::chunk_grid(size)
+            .into_iter()
+            .map(Chunk::empty)
+            .collect();
+
+        let imported = anvil::import_regions(
+            region_dir,
+            &mut chunks,
+            origin,
+            &BlockNameTable::load_or_default(&ResourcePacks::load()),
+        )?;
+        if imported == 0 {
+            return Err("there is no minecraft terrain in the imported area".into());
+        }
+
+        // the terrain is complete, it only needs to be lit and meshed
+        for chunk in chunks.iter_mut() {
+            chunk.status = ChunkStatus::Decorated;
         }
 
-        let required_chunk_width = width / CHUNK_SIZE;
-        let required_chunk_depth = depth / CHUNK_SIZE;
+        // start above the highest block in the middle of the world
+        let center = [(width / 2) as i32, (depth / 2) as i32];
+        let height = (0..(16 * CHUNK_SIZE) as i32)
+            .rev()
+            .find(|&y| get_block_at(&chunks, [center[0], y, center[1]]).is_some())
+            .unwrap_or(0);
+        let camera_position = [center[0] as f32, (height + 2) as f32, center[1] as f32];
 
-        let mut chunks = vec![];
+        Self::from_chunks(
+            display,
+            GeneratorSettings::Flat { height: 0 }.build(),
+            size,
+            chunks,
+            (camera_position, [0.0, 0.0, 0.0]),
+            None,
+        )
+    }
 
-        trace!("assigning blocktypes");
-        for chunk_x in 0..required_chunk_width {
-            for chunk_z in 0..required_chunk_depth {
-                for chunk_y in 0..16 {
-                    let mut blocktypes = Box::new([[[None; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
-
-                    for x in 0..CHUNK_SIZE {
-                        for y in 0..CHUNK_SIZE {
-                            for z in 0..CHUNK_SIZE {
-                                let global_height: u32 = (CHUNK_SIZE * chunk_y + y) as u32;
-                                let global_x = CHUNK_SIZE * chunk_x + x;
-                                let global_z = CHUNK_SIZE * chunk_z + z;
-
-                                blocktypes[x][y][z] = {
-                                    if global_height <= heightmap[global_x][global_z] - 4 {
-                                        Some(&STONE_BLOCK)
-                                    } else if global_height <= heightmap[global_x][global_z] - 1 {
-                                        Some(&DIRT_BLOCK)
-                                    } else if global_height == heightmap[global_x][global_z] {
-                                        Some(&GRASS_BLOCK)
-                                    } else {
-                                        None
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    trace!("creating chunk");
-                    let chunk = Box::new(Chunk::from_blocktypes(
-                        [chunk_x as i32, chunk_y as i32, chunk_z as i32],
-                        &blocktypes,
-                    ));
-
-                    trace!("pushing chunk");
-                    chunks.push(*chunk);
+    /// runs the generation stages until the given chunks are done, returns
+    /// the regions that have to be saved and the chunks that need meshes
+    fn finish_generation(
+        generator: &dyn TerrainGenerator,
+        chunks: &mut [Chunk],
+    ) -> (HashSet<[i32; 3]>, Vec<usize>) {
+        let initial_status: Vec<ChunkStatus> = chunks.iter().map(|c| c.status).collect();
+
+        trace!("running generation stages");
+        while generation::advance(chunks, generator, usize::MAX, &mut vec![]) != 0 {}
+
+        // anything the pipeline touched hasn't been written to disk yet
+        let unsaved_regions = chunks
+            .iter()
+            .zip(initial_status)
+            .filter(|(chunk, status)| chunk.status != *status)
+            .map(|(chunk, _)| region_of(chunk.coordinates))
+            .collect();
+
+        let dirty_chunkmeshes = (0..chunks.len())
+            .filter(|&i| chunks[i].status >= ChunkStatus::Lit)
+            .collect();
+
+        (unsaved_regions, dirty_chunkmeshes)
+    }
+
+    /// the camera the world is looked at through, placed where a save left it
+    pub fn create_camera(position: [f32; 3], rotation: [f32; 3]) -> OrbitalCamera {
+        OrbitalCamera::new(-2.0, position, rotation, 1.0 / 4.0, 16.0 / 9.0)
+    }
+
+    /// the block definitions, the block textures and the chunk shader
+    fn load_required_assets(
+        display: &Display,
+        packs: &ResourcePacks,
+    ) -> Result<RequiredAssets, Box<dyn Error>> {
+        let block_types = definitions::load(packs)?;
+        let block_atlas = Self::load_block_atlas(packs, block_types)?;
+        let block_textures = Self::create_block_textures(display, &block_atlas)?;
+        let chunk_color_shader = Self::create_chunk_color_shader(display, packs)?;
+
+        Ok(RequiredAssets {
+            block_types: block_types,
+            block_atlas: block_atlas,
+            block_textures: block_textures,
+            chunk_color_shader: chunk_color_shader,
+        })
+    }
+
+    /// makes the given block types the ones in use and moves every block
+    /// over to them, returns false if they already were. when a block
+    /// type's transparency changed, the lit chunks go back to being
+    /// decorated so they get lit again
+    fn use_block_types(chunks: &mut [Chunk], block_types: &'static [&'static BlockType]) -> bool {
+        let previous = types::block_types();
+        if std::ptr::eq(previous, block_types) {
+            return false;
+        }
+
+        let relight = previous
+            .iter()
+            .zip(block_types.iter())
+            .any(|(old, new)| old.transparent != new.transparent);
+        types::set_block_types(block_types);
+
+        for chunk in chunks.iter_mut() {
+            for block in chunk.blocks.iter_mut().flatten().flatten().flatten() {
+                if let Some(&block_type) =
+                    block_types.iter().find(|t| t.name == block.block_type.name)
+                {
+                    block.block_type = block_type;
                 }
             }
+
+            if relight && chunk.status > ChunkStatus::Decorated {
+                chunk.status = ChunkStatus::Decorated;
+            }
         }
 
+        true
+    }
+
+    /// finishes generating the given chunks and builds the world around them
+    fn from_chunks(
+        display: &Display,
+        generator: Box<dyn TerrainGenerator>,
+        size: (usize, usize),
+        mut chunks: Vec<Chunk>,
+        camera: ([f32; 3], [f32; 3]),
+        save: Option<WorldSave>,
+    ) -> Result<World, Box<dyn Error>> {
+        // a broken pack shouldn't keep the game from starting
+        let mut resource_packs = ResourcePacks::load();
+        let assets = match Self::load_required_assets(display, &resource_packs) {
+            Ok(assets) => assets,
+            Err(e) => {
+                error!(
+                    "failed to load the resource packs, using the default assets: {}",
+                    e
+                );
+                resource_packs = ResourcePacks::default();
+                Self::load_required_assets(display, &resource_packs)?
+            }
+        };
+        // the chunks were loaded with the block types that were in use before
+        Self::use_block_types(&mut chunks, assets.block_types);
+
+        let (unsaved_regions, dirty_chunkmeshes) =
+            Self::finish_generation(generator.as_ref(), &mut chunks);
+
         trace!("generating empty chunkmeshes");
         let mut chunkmeshes = vec![];
         for _ in 0..chunks.len() {
-            chunkmeshes.push([ChunkMesh::ungenerated(), ChunkMesh::ungenerated()]);
+            chunkmeshes.push(vec![[ChunkMesh::ungenerated(), ChunkMesh::ungenerated()]]);
         }
-        let dirty_chunkmeshes = (0..chunks.len()).collect();
+        let chunk_connectivity = vec![FaceConnectivity::all(); chunks.len()];
+        let mesh_arena = MeshArena::new(display, &Self::chunk_offsets(&chunks))?;
 
         info!("finished generating world");
 
-        World {
-            camera: OrbitalCamera::new(
-                -2.0,
-                [
-                    (width / 2) as f32,
-                    heightmap[0][0] as f32,
-                    (depth / 2) as f32,
-                ],
-                [0.0, 0.0, 0.0],
-                1.0 / 4.0,
-                16.0 / 9.0,
-            ),
+        Ok(World {
+            camera: Self::create_camera(camera.0, camera.1),
             chunks: chunks,
             chunk_meshes: chunkmeshes,
+            mesh_arena: mesh_arena,
             dirty_chunkmeshes: dirty_chunkmeshes,
-            chunk_color_shader: Self::create_chunk_color_shader(display).unwrap(),
-            texture_atlas: Self::create_texture_atlas(display).unwrap(),
-            sky: Sky::new(display),
+            chunk_connectivity: chunk_connectivity,
+            generator: generator,
+            size: size,
+            save: save,
+            unsaved_regions: unsaved_regions,
+            seconds_since_save: 0.0,
+            chunk_color_shader: assets.chunk_color_shader,
+            block_atlas: assets.block_atlas,
+            block_textures: assets.block_textures,
+            anisotropic_filtering: true,
+            sky: Sky::new(display, &resource_packs),
+            oit: Oit::new(display, &resource_packs),
+            transparency_mode: TransparencyMode::Sorted,
             hud: Hud::new(display),
+            render_stats: Default::default(),
+            resource_packs: resource_packs,
+        })
+    }
+
+    /// writes the metadata and every region with unsaved changes,
+    /// does nothing for worlds that weren't opened from a directory
+    pub fn save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let save = match &self.save {
+            Some(save) => save,
+            None => return Ok(()),
+        };
+
+        info!("saving world");
+
+        save.write_metadata(&WorldMetadata {
+            generator: self.generator.settings(),
+            size: self.size,
+            camera_position: *self.camera.get_position(),
+            camera_rotation: *self.camera.get_rotation(),
+        })?;
+
+        save.save_regions(&self.chunks, &self.unsaved_regions)?;
+        self.unsaved_regions.clear();
+        self.seconds_since_save = 0.0;
+
+        Ok(())
+    }
+
+    /// saves the world and stores a snapshot of it under the given name
+    pub fn create_snapshot(&mut self, name: &str) -> Result<SnapshotStats, Box<dyn Error>> {
+        self.save()?;
+
+        match &self.save {
+            Some(save) => snapshot::create(save, name),
+            None => Err("the world isn't saved anywhere, so it can't be snapshotted".into()),
+        }
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<SnapshotInfo>, Box<dyn Error>> {
+        match &self.save {
+            Some(save) => snapshot::list(save),
+            None => Ok(vec![]),
         }
     }
 
+    /// puts the world back the way it was when the snapshot was created,
+    /// changes since then are lost unless another snapshot has them
+    pub fn restore_snapshot(
+        &mut self,
+        display: &Display,
+        name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let save = match &self.save {
+            Some(save) => save,
+            None => return Err("the world isn't saved anywhere, so it has no snapshots".into()),
+        };
+
+        snapshot::restore(save, name)?;
+        let metadata = save.read_metadata()?;
+
+        let coords = Self::chunk_grid(metadata.size);
+        let mut chunks: Vec<Chunk> = save
+            .load_chunks(&coords)
+            .into_iter()
+            .zip(coords)
+            .map(|(chunk, coords)| chunk.unwrap_or_else(|| Chunk::empty(coords)))
+            .collect();
+
+        let generator = metadata.generator.build();
+        let (unsaved_regions, dirty_chunkmeshes) =
+            Self::finish_generation(generator.as_ref(), &mut chunks);
+
+        self.chunk_meshes = chunks
+            .iter()
+            .map(|_| vec![[ChunkMesh::ungenerated(), ChunkMesh::ungenerated()]])
+            .collect();
+        self.mesh_arena.clear();
+        self.mesh_arena
+            .set_chunk_offsets(display, &Self::chunk_offsets(&chunks))?;
+        self.chunk_connectivity = vec![FaceConnectivity::all(); chunks.len()];
+        self.chunks = chunks;
+        self.dirty_chunkmeshes = dirty_chunkmeshes;
+        self.generator = generator;
+        self.size = metadata.size;
+        self.unsaved_regions = unsaved_regions;
+        self.seconds_since_save = 0.0;
+
+        *self.camera.get_position_mut() = metadata.camera_position;
+        *self.camera.get_rotation_mut() = metadata.camera_rotation;
+
+        Ok(())
+    }
+
+    /// marks a chunk whose blocks changed so it will be written on the next save
+    pub fn flag_chunk_modified(&mut self, chunk_coords: [i32; 3]) {
+        self.unsaved_regions.insert(region_of(chunk_coords));
+    }
+
     pub fn flag_chunkmesh_dirty(&mut self, chunk_coords: [i32; 3]) {
         let index = match self
             .chunks
@@ -215,54 +598,448 @@ impl World {
         self.dirty_chunkmeshes.push(index);
     }
 
+    /// dirties the meshes of a changed chunk and of its neighbours,
+    /// since their faces towards it might have changed too
+    fn invalidate_chunk_and_neighbours(&mut self, chunk_coords: [i32; 3]) {
+        self.flag_chunk_modified(chunk_coords);
+
+        let [x, y, z] = chunk_coords;
+        for coords in [
+            [x, y, z],
+            [x - 1, y, z],
+            [x + 1, y, z],
+            [x, y - 1, z],
+            [x, y + 1, z],
+            [x, y, z - 1],
+            [x, y, z + 1],
+        ] {
+            if self.chunks.iter().any(|c| c.coordinates == coords) {
+                self.flag_chunkmesh_dirty(coords);
+            }
+        }
+    }
+
+    /// invalidates the correct chunks when the specified block
+    /// changes
+    fn invalidate_block_chunkmeshes(&mut self, global_coords: [i32; 3]) {
+        let (chunk_coords, block_coords) = Chunk::get_local_coord_from_world_coord(global_coords);
+
+        // the chunk in which the block changed will always be invalid
+        self.flag_chunkmesh_dirty(chunk_coords);
+        self.flag_chunk_modified(chunk_coords);
+
+        if block_coords[0] == 0 {
+            self.flag_chunkmesh_dirty([chunk_coords[0] - 1, chunk_coords[1], chunk_coords[2]]);
+        } else if block_coords[0] == CHUNK_SIZE_U8 - 1 {
+            self.flag_chunkmesh_dirty([chunk_coords[0] + 1, chunk_coords[1], chunk_coords[2]]);
+        }
+
+        if block_coords[1] == 0 {
+            self.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1] - 1, chunk_coords[2]]);
+        } else if block_coords[1] == CHUNK_SIZE_U8 - 1 {
+            self.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1] + 1, chunk_coords[2]]);
+        }
+
+        if block_coords[2] == 0 {
+            self.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1], chunk_coords[2] - 1]);
+        } else if block_coords[2] == CHUNK_SIZE_U8 - 1 {
+            self.flag_chunkmesh_dirty([chunk_coords[0], chunk_coords[1], chunk_coords[2] + 1]);
+        }
+    }
+
+    pub fn get_block(&self, coords: [i32; 3]) -> Option<Block> {
+        get_block_at(&self.chunks, coords)
+    }
+
+    /// replaces a block and queues the affected chunks to be lit and
+    /// meshed again, returns false if the block's chunk doesn't exist
+    pub fn set_block(&mut self, coords: [i32; 3], block_type: Option<&'static BlockType>) -> bool {
+        if !set_block_at(&mut self.chunks, coords, block_type) {
+            return false;
+        }
+
+        self.invalidate_block_chunkmeshes(coords);
+        let (chunk_coords, _) = Chunk::get_local_coord_from_world_coord(coords);
+        generation::invalidate_light(&mut self.chunks, chunk_coords);
+        true
+    }
+
+    pub fn copy_volume(&self, origin: [i32; 3], size: [usize; 3]) -> Volume {
+        Volume::copy_from_chunks(&self.chunks, origin, size)
+    }
+
+    /// writes a volume into the world with its lowest corner at `origin`,
+    /// see `Volume::paste_into_chunks`
+    pub fn paste_volume(&mut self, volume: &Volume, origin: [i32; 3], include_air: bool) {
+        for chunk_coords in volume.paste_into_chunks(&mut self.chunks, origin, include_air) {
+            self.invalidate_chunk_and_neighbours(chunk_coords);
+        }
+    }
+
+    /// saves the blocks in the box starting at `origin` as a sponge schematic
+    pub fn export_schematic<P: AsRef<Path>>(
+        &self,
+        path: P,
+        origin: [i32; 3],
+        size: [usize; 3],
+        table: &BlockNameTable,
+        version: SchematicVersion,
+    ) -> Result<(), Box<dyn Error>> {
+        let schematic = Schematic {
+            volume: self.copy_volume(origin, size),
+            offset: [0, 0, 0],
+        };
+
+        schematic.write(path, table, version)?;
+        info!("exported {:?} blocks at {:?} as a schematic", size, origin);
+        Ok(())
+    }
+
+    /// exports the world as a textured mesh, the format is picked by the
+    /// extension of the path, `.obj` or `.glb`. only the blocks inside the
+    /// box given by `region` are exported when there is one. the block
+    /// textures are packed into an atlas next to the mesh unless there is
+    /// one already
+    pub fn export_mesh<P: AsRef<Path>>(
+        &self,
+        path: P,
+        region: Option<([i32; 3], [usize; 3])>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+
+        let mesh = match region {
+            Some((origin, size)) => {
+                ExportMesh::from_chunks(&clip_chunks(&self.chunks, origin, size))
+            }
+            None => ExportMesh::from_chunks(&self.chunks),
+        };
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("obj") => mesh.write_obj(path, "atlas.png")?,
+            Some("glb") => mesh.write_glb(path, "atlas.png")?,
+            _ => return Err(format!("can't export a mesh as {}", path.display()).into()),
+        }
+
+        let atlas_path = path.with_file_name("atlas.png");
+        if !atlas_path.exists() {
+            self.block_atlas.to_image().save(atlas_path)?;
+        }
+
+        info!("exported the world as {}", path.display());
+        Ok(())
+    }
+
+    /// the average colours of the block types in the current block textures
+    pub fn block_colors(&self) -> BlockColors {
+        BlockColors::from_block_atlas(&self.block_atlas)
+    }
+
+    /// saves the blocks in the box starting at `origin` as a magicavoxel model
+    pub fn export_vox<P: AsRef<Path>>(
+        &self,
+        path: P,
+        origin: [i32; 3],
+        size: [usize; 3],
+    ) -> Result<(), Box<dyn Error>> {
+        vox::write(path, &self.copy_volume(origin, size), &self.block_colors())?;
+        info!("exported {:?} blocks at {:?} as a vox model", size, origin);
+        Ok(())
+    }
+
+    /// stamps a magicavoxel model into the world with its lowest corner
+    /// at `position`, empty voxels leave the world untouched
+    pub fn import_vox<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        position: [i32; 3],
+    ) -> Result<(), Box<dyn Error>> {
+        let overrides = vox::load_overrides(vox::PALETTE_OVERRIDES_FILE)?;
+        let volume = vox::read(path, &self.block_colors(), &overrides)?;
+
+        self.paste_volume(&volume, position, false);
+        info!(
+            "imported {:?} blocks at {:?} from a vox model",
+            volume.size, position
+        );
+        Ok(())
+    }
+
+    /// voxelizes an obj or stl mesh and places it into the world with the
+    /// lowest corner of its bounding box at `position`
+    pub fn import_mesh<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        scale: f32,
+        fill: Fill,
+        block_type: &'static BlockType,
+        position: [i32; 3],
+    ) -> Result<(), Box<dyn Error>> {
+        let triangles = triangle_mesh::read(path)?;
+        let volume = voxelize(&triangles, scale, fill, block_type)?;
+
+        self.paste_volume(&volume, position, false);
+        info!(
+            "voxelized {} triangles into {:?} blocks at {:?}",
+            triangles.len(),
+            volume.size,
+            position
+        );
+        Ok(())
+    }
+
+    /// builds an image out of blocks with its lowest corner at `position`,
+    /// see `pixel_art::image_to_volume`
+    pub fn import_image<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        orientation: Orientation,
+        dither: bool,
+        position: [i32; 3],
+    ) -> Result<(), Box<dyn Error>> {
+        let image = image::open(path)?.to_rgba8();
+        let volume = image_to_volume(&image, &self.block_colors(), orientation, dither);
+
+        self.paste_volume(&volume, position, false);
+        info!(
+            "placed a {}x{} image at {:?}",
+            image.width(),
+            image.height(),
+            position
+        );
+        Ok(())
+    }
+
+    /// pastes a sponge schematic into the world at `position`, moved by
+    /// the offset stored in the schematic
+    pub fn import_schematic<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        position: [i32; 3],
+        table: &BlockNameTable,
+        include_air: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let schematic = Schematic::read(path, table)?;
+        let origin = [
+            position[0] + schematic.offset[0],
+            position[1] + schematic.offset[1],
+            position[2] + schematic.offset[2],
+        ];
+
+        self.paste_volume(&schematic.volume, origin, include_air);
+        info!(
+            "imported {:?} blocks at {:?} from a schematic",
+            schematic.volume.size, origin
+        );
+        Ok(())
+    }
+
     fn regenerate_dirty_chunkmeshes(
         display: &Display,
-        chunks: &[Chunk],
-        chunk_meshes: &mut [[ChunkMesh; 2]],
+        chunks: &mut [Chunk],
+        chunk_meshes: &mut [Vec<[ChunkMesh; 2]>],
+        arena: &mut MeshArena,
+        chunk_connectivity: &mut [FaceConnectivity],
         dirty_meshes: &mut Vec<usize>,
         max_regens: usize,
     ) {
         debug!("there are {} dirty meshes", dirty_meshes.len());
 
         let mut num_processed = 0;
+        let mut postponed = vec![];
 
-        while num_processed < max_regens && dirty_meshes.len() != 0 {
+        while num_processed < max_regens && !dirty_meshes.is_empty() {
             let i = dirty_meshes[0];
 
             let chunk = &chunks[i];
             let neighbors = get_chunk_neighbours(chunks, chunk.coordinates);
 
-            if neighbors.is_xz_complete() {
+            if chunk.status < ChunkStatus::Lit {
+                // the generation pipeline will queue it again once it's lit
+            } else if !neighbors.is_xz_complete() {
+                // chunks on the edge of the world are never meshed
+            } else if chunk.status == ChunkStatus::Lit
+                && !generation::can_advance(chunk, &neighbors)
+            {
+                postponed.push(i);
+            } else {
                 num_processed += 1;
-                chunk_meshes[i][0] = chunk.generate_base_chunkmesh(display, neighbors);
-                chunk_meshes[i][1] = chunk.generate_transparent_chunkmesh(display, neighbors);
+                for [base, transparent] in chunk_meshes[i].drain(..) {
+                    base.free(arena);
+                    transparent.free(arena);
+                }
+                chunk_meshes[i] = vec![[
+                    chunk.generate_base_chunkmesh(display, arena, neighbors),
+                    chunk.generate_transparent_chunkmesh(display, arena, neighbors),
+                ]];
+                // the coarser levels are built once they're needed, see build_lod_levels
+                for _ in 1..lod::LOD_SCALES.len() {
+                    chunk_meshes[i].push([ChunkMesh::ungenerated(), ChunkMesh::ungenerated()]);
+                }
+                chunk_connectivity[i] = FaceConnectivity::compute(chunk);
+                chunks[i].status = ChunkStatus::Meshed;
             }
 
             dirty_meshes.remove(0);
         }
+
+        dirty_meshes.extend(postponed);
     }
 
     pub fn update(&mut self, display: &Display, seconds: f32) {
         trace!("updating after {}s", seconds);
         //self.camera.update(seconds);
 
+        // picks up chunks that were left partially generated
+        let mut newly_lit = vec![];
+        generation::advance(
+            &mut self.chunks,
+            self.generator.as_ref(),
+            GENERATION_STAGES_PER_FRAME,
+            &mut newly_lit,
+        );
+        for &i in newly_lit.iter() {
+            self.unsaved_regions
+                .insert(region_of(self.chunks[i].coordinates));
+        }
+        self.dirty_chunkmeshes.extend(newly_lit);
+
         if !self.dirty_chunkmeshes.is_empty() {
             Self::regenerate_dirty_chunkmeshes(
                 display,
-                &self.chunks,
+                &mut self.chunks,
                 &mut self.chunk_meshes,
+                &mut self.mesh_arena,
+                &mut self.chunk_connectivity,
                 &mut self.dirty_chunkmeshes,
                 2,
             );
         }
-        
+
+        self.seconds_since_save += seconds;
+        if self.save.is_some() && self.seconds_since_save >= AUTOSAVE_INTERVAL {
+            if let Err(e) = self.save() {
+                error!("failed to save world: {}", e);
+            }
+        }
+
         self.hud.set_aspect_ratio(*self.camera.get_aspect_ratio());
         self.sky.set_view_rotation(self.camera.get_view_rotation());
         self.sky.set_projection(self.camera.get_projection());
     }
 
-    pub fn render(&mut self, frame: &mut impl Surface) {
-        let params = glium::DrawParameters {
+    fn world_uniforms(&self) -> WorldUniforms<'_> {
+        let anisotropy = match self.anisotropic_filtering {
+            true => MAX_ANISOTROPY,
+            false => 1,
+        };
+
+        WorldUniforms {
+            block_textures: self
+                .block_textures
+                .sampled()
+                .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+                .magnify_filter(MagnifySamplerFilter::Nearest)
+                .anisotropy(anisotropy),
+            render_distance: crate::camera::CLIP_FAR,
+        }
+    }
+
+    /// where every chunk starts in the world, the arena moves the meshes
+    /// of each chunk there. the level of detail meshes are drawn with a
+    /// second, lowered, set of offsets after the first, see lod::SKIRT_ROOM
+    fn chunk_offsets(chunks: &[Chunk]) -> Vec<[f32; 3]> {
+        let offsets = chunks.iter().map(|chunk| chunk.get_bounds().0);
+        let lowered = offsets
+            .clone()
+            .map(|[x, y, z]| [x, y - lod::SKIRT_ROOM as f32, z]);
+
+        offsets.chain(lowered).collect()
+    }
+
+    /// the offset the mesh of a chunk at a level is drawn with, see chunk_offsets
+    fn mesh_instance(&self, chunk: usize, level: usize) -> usize {
+        match level {
+            0 => chunk,
+            _ => self.chunks.len() + chunk,
+        }
+    }
+
+    /// builds the levels of detail chunks are about to be drawn at for the
+    /// first time, at most `max_builds` of them. chunks that have to wait
+    /// for theirs are drawn with their full mesh in the meantime
+    fn build_lod_levels(
+        &mut self,
+        display: &Display,
+        levels: &mut [Option<usize>],
+        max_builds: usize,
+    ) {
+        let mut num_built = 0;
+
+        for (i, level) in levels.iter_mut().enumerate() {
+            let l = match *level {
+                Some(l) if l < self.chunk_meshes[i].len() && self.chunk_meshes[i][l][0].dirty => l,
+                _ => continue,
+            };
+
+            if num_built == max_builds {
+                *level = Some(0);
+                continue;
+            }
+
+            num_built += 1;
+            self.chunk_meshes[i][l] =
+                lod::generate_lod_chunkmeshes(display, &mut self.mesh_arena, &self.chunks[i], l);
+        }
+    }
+
+    /// the chunks that are drawn along with the level of detail they're
+    /// drawn at, `levels` has the level of every chunk or None if it
+    /// isn't visible
+    fn visible_levels(&self, levels: &[Option<usize>]) -> Vec<(usize, usize)> {
+        levels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, level)| {
+                level.map(|level| (i, level.min(self.chunk_meshes[i].len() - 1)))
+            })
+            .collect()
+    }
+
+    /// draws either the opaque (0) or the transparent (1) mesh of the
+    /// chunks in the order they're given, all in a single draw call
+    /// when the driver supports it, see arena.rs
+    fn render_chunk_meshes<S: Surface>(
+        &self,
+        surface: &mut S,
+        chunks: &[(usize, usize)],
+        mesh: usize,
+        shader: &Program,
+        params: &DrawParameters,
+    ) -> Result<(), Box<dyn Error>> {
+        let worlduniforms = self.world_uniforms();
+        let uniforms = uniform! {
+            projection: self.camera.get_projection(),
+            view_translation: self.camera.get_view_translation(),
+            view_rotation: self.camera.get_view_rotation(),
+            block_textures: worlduniforms.block_textures,
+            render_distance: worlduniforms.render_distance,
+        };
+
+        let meshes: Vec<(&ArenaAllocation, usize)> = chunks
+            .iter()
+            .filter_map(|&(i, level)| {
+                self.chunk_meshes[i][level][mesh]
+                    .allocation()
+                    .map(|allocation| (allocation, self.mesh_instance(i, level)))
+            })
+            .collect();
+
+        self.mesh_arena
+            .draw(surface, &meshes, shader, &uniforms, params)
+    }
+
+    fn opaque_params<'a>() -> DrawParameters<'a> {
+        glium::DrawParameters {
             depth: glium::Depth {
                 test: glium::draw_parameters::DepthTest::IfLessOrEqual,
                 write: true,
@@ -270,8 +1047,15 @@ impl World {
             },
             blend: Blend::alpha_blending(),
             ..Default::default()
-        };
+        }
+    }
 
+    fn render_sorted(
+        &mut self,
+        frame: &mut impl Surface,
+        levels: &[Option<usize>],
+        eye: [f32; 3],
+    ) -> Result<(), Box<dyn Error>> {
         let transparent_params = glium::DrawParameters {
             depth: glium::Depth {
                 test: glium::draw_parameters::DepthTest::IfLess,
@@ -283,48 +1067,238 @@ impl World {
             ..Default::default()
         };
 
+        self.sky.render(frame)?;
 
-        self.sky.render(frame);
+        let mut visible = self.visible_levels(levels);
+        self.render_chunk_meshes(
+            frame,
+            &visible,
+            0,
+            &self.chunk_color_shader,
+            &Self::opaque_params(),
+        )?;
 
-        let worlduniforms = WorldUniforms {
-            texture_atlas: self
-                .texture_atlas
-                .sampled()
-                .minify_filter(MinifySamplerFilter::Nearest)
-                .magnify_filter(MagnifySamplerFilter::Nearest),
-            render_distance: crate::camera::CLIP_FAR,
+        // transparent faces are blended with whatever is behind them, so
+        // they're drawn from the furthest chunk to the closest one
+        let distance = |chunk: &Chunk| {
+            let (min, max) = chunk.get_bounds();
+            (0..3)
+                .map(|axis| ((min[axis] + max[axis]) / 2.0 - eye[axis]).powi(2))
+                .sum::<f32>()
         };
+        visible.sort_by(|&(a, _), &(b, _)| {
+            distance(&self.chunks[b])
+                .partial_cmp(&distance(&self.chunks[a]))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
 
-        for (chunk, chunk_mesh) in self.chunks.iter().zip(self.chunk_meshes.iter()) {
-            chunk_mesh[0].render(
-                frame,
-                &self.chunk_color_shader,
-                &params,
-                &worlduniforms,
-                &chunk.get_uniforms(),
-                &self.camera,
+        for &(i, level) in visible.iter() {
+            let (min, _) = self.chunks[i].get_bounds();
+            // the level of detail meshes are drawn lower, see chunk_offsets
+            let lowered = match level {
+                0 => 0.0,
+                _ => lod::SKIRT_ROOM as f32,
+            };
+            self.chunk_meshes[i][level][1].sort_faces(
+                &self.mesh_arena,
+                [eye[0] - min[0], eye[1] - min[1] + lowered, eye[2] - min[2]],
             );
         }
 
-        for (chunk, chunk_mesh) in self.chunks.iter().zip(self.chunk_meshes.iter()) {
-            chunk_mesh[1].render(
-                frame,
-                &self.chunk_color_shader,
-                &transparent_params,
-                &worlduniforms,
-                &chunk.get_uniforms(),
-                &self.camera,
-            );
+        self.render_chunk_meshes(
+            frame,
+            &visible,
+            1,
+            &self.chunk_color_shader,
+            &transparent_params,
+        )
+    }
+
+    /// nothing has to be sorted, see oit.rs
+    fn render_weighted_blended(
+        &mut self,
+        display: &Display,
+        frame: &mut impl Surface,
+        levels: &[Option<usize>],
+    ) -> Result<(), Box<dyn Error>> {
+        self.oit.resize(display, frame.get_dimensions())?;
+
+        let visible = self.visible_levels(levels);
+        self.oit.render(
+            display,
+            frame,
+            |surface| {
+                self.sky.render(surface)?;
+                self.render_chunk_meshes(
+                    surface,
+                    &visible,
+                    0,
+                    &self.chunk_color_shader,
+                    &Self::opaque_params(),
+                )
+            },
+            |surface, shader, params| {
+                self.render_chunk_meshes(surface, &visible, 1, shader, params)
+            },
+        )
+    }
+
+    pub fn render(&mut self, display: &Display, frame: &mut impl Surface) {
+        let frustum = Frustum::from_camera(&self.camera);
+        let in_frustum: Vec<bool> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let (min, max) = chunk.get_bounds();
+                frustum.intersects_aabb(min, max)
+            })
+            .collect();
+
+        // the view translation moves the world away from the eye
+        let eye = self.camera.get_view_translation()[3];
+        let eye = [-eye[0], -eye[1], -eye[2]];
+        let (camera_chunk, _) = Chunk::get_local_coord_from_world_coord([
+            eye[0].floor() as i32,
+            eye[1].floor() as i32,
+            eye[2].floor() as i32,
+        ]);
+        let visible = visibility::reachable_chunks(
+            &self.chunks,
+            &self.chunk_connectivity,
+            &in_frustum,
+            camera_chunk,
+        );
+
+        let in_frustum_count = in_frustum.iter().filter(|&&v| v).count();
+        let drawn = visible.iter().filter(|&&v| v).count();
+        self.render_stats = RenderStats {
+            drawn: drawn,
+            culled: in_frustum.len() - in_frustum_count,
+            occluded: in_frustum_count.saturating_sub(drawn),
+        };
+
+        // chunks further away are drawn with coarser meshes, see lod.rs
+        let mut levels: Vec<Option<usize>> = self
+            .chunks
+            .iter()
+            .zip(visible.iter())
+            .map(|(chunk, &visible)| {
+                if !visible {
+                    return None;
+                }
+
+                let (min, max) = chunk.get_bounds();
+                let distance = (0..3)
+                    .map(|axis| (eye[axis] - eye[axis].max(min[axis]).min(max[axis])).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                Some(lod::level_for_distance(distance))
+            })
+            .collect();
+        self.build_lod_levels(display, &mut levels, 4);
+
+        // every visible chunk gets a draw command of its own
+        if let Err(e) = self.mesh_arena.reserve_draws(display, self.chunks.len()) {
+            error!("failed to make room for the draw commands: {}", e);
+        }
+
+        let blended = self.transparency_mode == TransparencyMode::WeightedBlended
+            && match self.render_weighted_blended(display, frame, &levels) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!(
+                        "weighted blended transparency failed, sorting instead: {}",
+                        e
+                    );
+                    self.transparency_mode = TransparencyMode::Sorted;
+                    false
+                }
+            };
+
+        if !blended {
+            if let Err(e) = self.render_sorted(frame, &levels, eye) {
+                error!("failed to render the world: {}", e);
+            }
         }
 
         self.hud.render(frame);
     }
 
+    /// switches between sorting transparent faces and blending them
+    /// without sorting, returns the new mode
+    pub fn toggle_transparency_mode(&mut self) -> TransparencyMode {
+        self.transparency_mode = match self.transparency_mode {
+            TransparencyMode::Sorted => TransparencyMode::WeightedBlended,
+            TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+        };
+        self.transparency_mode
+    }
+
+    /// turns anisotropic filtering of the block textures
+    /// on or off, returns whether it's on now
+    pub fn toggle_anisotropic_filtering(&mut self) -> bool {
+        self.anisotropic_filtering = !self.anisotropic_filtering;
+        self.anisotropic_filtering
+    }
+
+    pub fn render_stats(&self) -> RenderStats {
+        self.render_stats
+    }
+
+    /// opens the resource packs again and loads every asset from them
     pub fn reload_assets(&mut self, display: &Display) -> Result<(), Box<dyn std::error::Error>> {
-        self.chunk_color_shader = Self::create_chunk_color_shader(display)?;
-        self.texture_atlas = Self::create_texture_atlas(display)?;
-        self.sky.reload(display);
+        let packs = self.resource_packs.reopen()?;
+        self.load_assets(display, packs)
+    }
+
+    /// switches to the assets of the given packs, if any of them
+    /// can't be loaded nothing changes and the old packs stay in use
+    fn load_assets(
+        &mut self,
+        display: &Display,
+        packs: ResourcePacks,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let assets = Self::load_required_assets(display, &packs)?;
+
+        // the texture layers change along with the block
+        // definitions, so every mesh is built again
+        if Self::use_block_types(&mut self.chunks, assets.block_types) {
+            for (i, chunk) in self.chunks.iter().enumerate() {
+                if chunk.status >= ChunkStatus::Lit && !self.dirty_chunkmeshes.contains(&i) {
+                    self.dirty_chunkmeshes.push(i);
+                }
+            }
+        }
+
+        self.chunk_color_shader = assets.chunk_color_shader;
+        self.block_atlas = assets.block_atlas;
+        self.block_textures = assets.block_textures;
+        // these fall back to the built in shaders on their own
+        self.sky.reload(display, &packs);
+        self.oit.reload(display, &packs);
+        self.resource_packs = packs;
 
         Ok(())
     }
+
+    pub fn resource_packs(&self) -> &ResourcePacks {
+        &self.resource_packs
+    }
+
+    /// switches to the named resource packs, the first has the highest
+    /// priority, and remembers them for the next time the game starts.
+    /// if their assets can't be loaded the old packs are kept
+    pub fn set_resource_packs(
+        &mut self,
+        display: &Display,
+        names: &[&str],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.load_assets(display, ResourcePacks::open(names)?)?;
+        self.resource_packs.save()
+    }
+
+    /// the block name table of the resource packs
+    pub fn block_name_table(&self) -> BlockNameTable {
+        BlockNameTable::load_or_default(&self.resource_packs)
+    }
 }
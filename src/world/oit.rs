@@ -0,0 +1,252 @@
+//! weighted blended order-independent transparency, from McGuire and
+//! Bavoil's paper of the same name
+//!
+//! the opaque part of the frame is drawn into a texture first. the
+//! transparent faces are then drawn in any order into two more textures
+//! sharing its depth buffer, they add up their weighted colors and
+//! multiply up how much of the background still shows through. a single
+//! fullscreen pass then mixes the weighted average over the opaque frame.
+//!
+//! glium sets one blend function for every render target, so the
+//! revealage is kept in the alpha of the accumulation target, which is
+//! blended multiplicatively, and the total weight in the red of a second
+//! target, which is blended additively like the accumulated colors.
+
+use std::error::Error;
+
+use glium::draw_parameters::{BlendingFunction, LinearBlendingFactor};
+use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer};
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{
+    DepthFormat, DepthTexture2d, MipmapsOption, Texture2d, UncompressedFloatFormat,
+};
+use glium::{uniform, Blend, Display, DrawParameters, Program, Surface, VertexBuffer};
+
+use log::*;
+
+use crate::graphics::*;
+use crate::resource_pack::ResourcePacks;
+
+const CHUNK_SHADER_VERT: &str = include_str!("../shaders/chunk/vertex.vert");
+const ACCUMULATE_SHADER_FRAG: &str = include_str!("../shaders/chunk/oit.frag");
+const COMPOSITE_SHADER_VERT: &str = include_str!("../shaders/oit/composite.vert");
+const COMPOSITE_SHADER_FRAG: &str = include_str!("../shaders/oit/composite.frag");
+
+const CHUNK_SHADER_HOTLOAD_VERT: &str = "shaders/chunk/vertex.vert";
+const ACCUMULATE_SHADER_HOTLOAD_FRAG: &str = "shaders/chunk/oit.frag";
+const COMPOSITE_SHADER_HOTLOAD_VERT: &str = "shaders/oit/composite.vert";
+const COMPOSITE_SHADER_HOTLOAD_FRAG: &str = "shaders/oit/composite.frag";
+
+/// how the transparent faces of the chunks are drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// chunks and the faces inside of them are sorted back to front
+    Sorted,
+    /// faces are drawn in any order and blended by their depth
+    WeightedBlended,
+}
+
+/// the textures the frame is drawn into, they're
+/// created again whenever the window changes size
+struct Targets {
+    size: (u32, u32),
+    opaque: Texture2d,
+    depth: DepthTexture2d,
+    accumulation: Texture2d,
+    weight: Texture2d,
+}
+
+impl Targets {
+    fn new(display: &Display, size: (u32, u32)) -> Result<Self, Box<dyn Error>> {
+        let color = || {
+            Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::F16F16F16F16,
+                MipmapsOption::NoMipmap,
+                size.0,
+                size.1,
+            )
+        };
+
+        Ok(Targets {
+            size: size,
+            opaque: color()?,
+            depth: DepthTexture2d::empty_with_format(
+                display,
+                DepthFormat::F32,
+                MipmapsOption::NoMipmap,
+                size.0,
+                size.1,
+            )?,
+            accumulation: color()?,
+            weight: color()?,
+        })
+    }
+}
+
+pub struct Oit {
+    targets: Option<Targets>,
+    accumulate_shader: Program,
+    composite_shader: Program,
+    quad: VertexBuffer<Vertex2d>,
+}
+
+impl Oit {
+    fn create_accumulate_shader(display: &Display, packs: &ResourcePacks) -> Program {
+        super::shader_load_helper(
+            display,
+            packs,
+            CHUNK_SHADER_HOTLOAD_VERT,
+            ACCUMULATE_SHADER_HOTLOAD_FRAG,
+            CHUNK_SHADER_VERT,
+            ACCUMULATE_SHADER_FRAG,
+        )
+    }
+
+    fn create_composite_shader(display: &Display, packs: &ResourcePacks) -> Program {
+        super::shader_load_helper(
+            display,
+            packs,
+            COMPOSITE_SHADER_HOTLOAD_VERT,
+            COMPOSITE_SHADER_HOTLOAD_FRAG,
+            COMPOSITE_SHADER_VERT,
+            COMPOSITE_SHADER_FRAG,
+        )
+    }
+
+    pub fn new(display: &Display, packs: &ResourcePacks) -> Self {
+        // a single triangle strip that covers the whole screen
+        let quad = VertexBuffer::new(
+            display,
+            &[
+                Vertex2d {
+                    position: [-1.0, -1.0],
+                },
+                Vertex2d {
+                    position: [1.0, -1.0],
+                },
+                Vertex2d {
+                    position: [-1.0, 1.0],
+                },
+                Vertex2d {
+                    position: [1.0, 1.0],
+                },
+            ],
+        )
+        .unwrap();
+
+        Oit {
+            targets: None,
+            accumulate_shader: Self::create_accumulate_shader(display, packs),
+            composite_shader: Self::create_composite_shader(display, packs),
+            quad: quad,
+        }
+    }
+
+    pub fn reload(&mut self, display: &Display, packs: &ResourcePacks) {
+        self.accumulate_shader = Self::create_accumulate_shader(display, packs);
+        self.composite_shader = Self::create_composite_shader(display, packs);
+    }
+
+    /// the blending that adds up the weighted colors and the weights,
+    /// while multiplying the revealage in the accumulation alpha
+    fn accumulate_params<'a>() -> DrawParameters<'a> {
+        DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: false,
+                ..Default::default()
+            },
+            blend: Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::One,
+                },
+                alpha: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::Zero,
+                    destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// makes sure the textures are as big as the frame
+    pub fn resize(&mut self, display: &Display, size: (u32, u32)) -> Result<(), Box<dyn Error>> {
+        if self.targets.as_ref().map(|t| t.size) != Some(size) {
+            debug!("creating {}x{} transparency targets", size.0, size.1);
+            self.targets = Some(Targets::new(display, size)?);
+        }
+
+        Ok(())
+    }
+
+    /// draws a frame, `opaque` draws everything that isn't transparent
+    /// and `transparent` the transparent faces with the shader and
+    /// parameters it's given. the result is drawn over the whole `frame`
+    pub fn render<S, O, T>(
+        &self,
+        display: &Display,
+        frame: &mut S,
+        opaque: O,
+        transparent: T,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: Surface,
+        O: FnOnce(&mut SimpleFrameBuffer) -> Result<(), Box<dyn Error>>,
+        T: FnOnce(
+            &mut MultiOutputFrameBuffer,
+            &Program,
+            &DrawParameters,
+        ) -> Result<(), Box<dyn Error>>,
+    {
+        let targets = match &self.targets {
+            Some(targets) if targets.size == frame.get_dimensions() => targets,
+            _ => return Err("the transparency targets don't fit the frame".into()),
+        };
+
+        let mut opaque_framebuffer =
+            SimpleFrameBuffer::with_depth_buffer(display, &targets.opaque, &targets.depth)?;
+        opaque_framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
+        opaque(&mut opaque_framebuffer)?;
+
+        let mut transparent_framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
+            display,
+            [
+                ("f_accumulation", &targets.accumulation),
+                ("f_weight", &targets.weight),
+            ]
+            .iter()
+            .copied(),
+            &targets.depth,
+        )?;
+        // the revealage starts out at 1, nothing covers the background yet
+        targets
+            .accumulation
+            .as_surface()
+            .clear_color(0.0, 0.0, 0.0, 1.0);
+        targets.weight.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+        transparent(
+            &mut transparent_framebuffer,
+            &self.accumulate_shader,
+            &Self::accumulate_params(),
+        )?;
+
+        let uniforms = uniform! {
+            opaque: &targets.opaque,
+            accumulation: &targets.accumulation,
+            weight: &targets.weight,
+        };
+
+        frame.draw(
+            &self.quad,
+            NoIndices(PrimitiveType::TriangleStrip),
+            &self.composite_shader,
+            &uniforms,
+            &Default::default(),
+        )?;
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use std::error::Error;
 use glium::{Display, Surface, Program, VertexBuffer, IndexBuffer, index::PrimitiveType, uniform};
 use crate::graphics::*;
+use crate::resource_pack::ResourcePacks;
 
 const SKY_SHADER_VERT: &'static str = include_str!("../shaders/sky_shader.vert");
 const SKY_SHADER_FRAG: &'static str = include_str!("../shaders/sky_shader.frag");
@@ -57,8 +58,8 @@ impl Sky {
         }
     }
 
-    fn create_shader(display: &Display) -> Program {
-        super::shader_load_helper(display, SKY_SHADER_HOTLOAD_VERT, SKY_SHADER_HOTLOAD_FRAG, SKY_SHADER_VERT, SKY_SHADER_FRAG)
+    fn create_shader(display: &Display, packs: &ResourcePacks) -> Program {
+        super::shader_load_helper(display, packs, SKY_SHADER_HOTLOAD_VERT, SKY_SHADER_HOTLOAD_FRAG, SKY_SHADER_VERT, SKY_SHADER_FRAG)
     }
 
     pub fn set_view_rotation(&mut self, view_rotation: [[f32; 4]; 4]) {
@@ -69,14 +70,14 @@ impl Sky {
         self.projection = projection;
     }
 
-    pub fn reload(&mut self, display: &Display) {
-        self.shader = Self::create_shader(display);
+    pub fn reload(&mut self, display: &Display, packs: &ResourcePacks) {
+        self.shader = Self::create_shader(display, packs);
     }
 
-    pub fn new(display: &Display) -> Self {
+    pub fn new(display: &Display, packs: &ResourcePacks) -> Self {
         Self {
             mesh: Self::create_mesh(display),
-            shader: Self::create_shader(display),
+            shader: Self::create_shader(display, packs),
 
             view_rotation: Default::default(),
             projection: Default::default(),
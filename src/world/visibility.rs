@@ -0,0 +1,184 @@
+//! cave culling, based on Tommaso Checchi's "advanced cave culling"
+//!
+//! when a chunk is meshed it also finds out which of its faces can see
+//! each other through the blocks that aren't opaque. rendering walks from
+//! the chunk the camera is in to its neighbours, but only leaves a chunk
+//! through a face that is connected to the face it came in through, and
+//! never turns back towards the camera. chunks the walk doesn't reach are
+//! hidden behind terrain and aren't drawn.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::chunk::*;
+
+/// the offsets to the neighbour behind each face, a face and
+/// the face opposite of it only differ in the lowest bit
+const FACES: [[i32; 3]; 6] = [
+    [-1, 0, 0],
+    [1, 0, 0],
+    [0, -1, 0],
+    [0, 1, 0],
+    [0, 0, -1],
+    [0, 0, 1],
+];
+
+fn opposite(face: usize) -> usize {
+    face ^ 1
+}
+
+/// which faces of a chunk are connected to each other, bit `b`
+/// of `faces[a]` is set if face `a` can see face `b`
+#[derive(Copy, Clone, Debug)]
+pub struct FaceConnectivity {
+    faces: [u8; 6],
+}
+
+impl FaceConnectivity {
+    /// chunks that haven't been meshed yet could be hiding anything,
+    /// so they're treated as if every face could see every other one
+    pub fn all() -> Self {
+        FaceConnectivity {
+            faces: [0b111111; 6],
+        }
+    }
+
+    fn none() -> Self {
+        FaceConnectivity { faces: [0; 6] }
+    }
+
+    pub fn connected(&self, a: usize, b: usize) -> bool {
+        self.faces[a] & (1 << b) != 0
+    }
+
+    /// connects all of the faces in the bitmask with each other
+    fn connect(&mut self, faces: u8) {
+        for face in 0..6 {
+            if faces & (1 << face) != 0 {
+                self.faces[face] |= faces;
+            }
+        }
+    }
+
+    /// flood fills every space of non-opaque blocks that touches the
+    /// outside of the chunk, and connects the faces each one touches
+    pub fn compute(chunk: &Chunk) -> Self {
+        let is_open = |[x, y, z]: [usize; 3]| match &chunk.blocks[x][y][z] {
+            Some(block) => block.block_type.transparent,
+            None => true,
+        };
+        let index = |[x, y, z]: [usize; 3]| (x * CHUNK_SIZE + y) * CHUNK_SIZE + z;
+
+        let mut connectivity = Self::none();
+        let mut visited = vec![false; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        let mut stack = vec![];
+        let last = CHUNK_SIZE - 1;
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let start = [x, y, z];
+
+                    // spaces that don't touch a face don't connect anything
+                    let on_face = start.iter().any(|&c| c == 0 || c == last);
+                    if !on_face || visited[index(start)] || !is_open(start) {
+                        continue;
+                    }
+
+                    let mut touched = 0u8;
+                    visited[index(start)] = true;
+                    stack.push(start);
+
+                    while let Some(block) = stack.pop() {
+                        for (face, offset) in FACES.iter().enumerate() {
+                            let axis = face / 2;
+                            let neighbour = [
+                                block[0].wrapping_add(offset[0] as usize),
+                                block[1].wrapping_add(offset[1] as usize),
+                                block[2].wrapping_add(offset[2] as usize),
+                            ];
+
+                            // wrapping below 0 makes the coordinate huge too
+                            if neighbour[axis] >= CHUNK_SIZE {
+                                touched |= 1 << face;
+                                continue;
+                            }
+
+                            if !visited[index(neighbour)] && is_open(neighbour) {
+                                visited[index(neighbour)] = true;
+                                stack.push(neighbour);
+                            }
+                        }
+                    }
+
+                    connectivity.connect(touched);
+                }
+            }
+        }
+
+        connectivity
+    }
+}
+
+/// walks through the chunks from the one the camera is in and returns
+/// which of them it reached. chunks outside of the frustum stop the walk.
+/// if the camera is outside of the world everything in the frustum is
+/// treated as reachable
+pub fn reachable_chunks(
+    chunks: &[Chunk],
+    connectivity: &[FaceConnectivity],
+    in_frustum: &[bool],
+    camera_chunk: [i32; 3],
+) -> Vec<bool> {
+    let indices: HashMap<[i32; 3], usize> = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| (chunk.coordinates, i))
+        .collect();
+
+    let start = match indices.get(&camera_chunk) {
+        Some(&start) => start,
+        None => return in_frustum.to_vec(),
+    };
+
+    let mut reached = vec![false; chunks.len()];
+    reached[start] = true;
+
+    // the chunk, the face it was entered through and
+    // a bitmask of the directions the walk went in
+    let mut queue = VecDeque::new();
+    queue.push_back((start, None, 0u8));
+
+    while let Some((i, entered, directions)) = queue.pop_front() {
+        for (face, offset) in FACES.iter().enumerate() {
+            if directions & (1 << opposite(face)) != 0 {
+                continue;
+            }
+
+            if let Some(entered) = entered {
+                if !connectivity[i].connected(entered, face) {
+                    continue;
+                }
+            }
+
+            let coords = chunks[i].coordinates;
+            let neighbour = [
+                coords[0] + offset[0],
+                coords[1] + offset[1],
+                coords[2] + offset[2],
+            ];
+
+            let j = match indices.get(&neighbour) {
+                Some(&j) => j,
+                None => continue,
+            };
+            if reached[j] || !in_frustum[j] {
+                continue;
+            }
+
+            reached[j] = true;
+            queue.push_back((j, Some(opposite(face)), directions | (1 << face)));
+        }
+    }
+
+    reached
+}